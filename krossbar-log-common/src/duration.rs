@@ -0,0 +1,37 @@
+//! Human readable durations: `100ms`, `30s`, `15m`, `2h`, `7d`.
+//! A number without a suffix is a number of seconds
+use std::time::Duration;
+
+const MINUTE_SECS: u64 = 60;
+const HOUR_SECS: u64 = 60 * MINUTE_SECS;
+const DAY_SECS: u64 = 24 * HOUR_SECS;
+
+/// Parse a duration with an optional `ms`, `s`, `m`, `h`, or `d` suffix
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+
+    if let Some(number) = duration.strip_suffix("ms") {
+        return number
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|_| format!("Invalid duration '{duration}'. Expected e.g. 100ms, 30s, 15m"));
+    }
+
+    let (number, multiplier) = match duration.char_indices().last() {
+        Some((position, 's')) => (&duration[..position], 1),
+        Some((position, 'm')) => (&duration[..position], MINUTE_SECS),
+        Some((position, 'h')) => (&duration[..position], HOUR_SECS),
+        Some((position, 'd')) => (&duration[..position], DAY_SECS),
+        Some(_) => (duration, 1),
+        None => return Err("Empty duration".into()),
+    };
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration '{duration}'. Expected e.g. 30s, 15m, 2h, 7d"))?;
+
+    number
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Duration '{duration}' is too long"))
+}
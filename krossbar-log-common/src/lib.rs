@@ -1,9 +1,18 @@
+pub mod duration;
+pub mod line_template;
 pub mod log_message;
 pub mod logger_interface;
+pub mod naming;
 
 pub const LOG_CONTROL_SERVICE_NAME: &str = "krossbar.log.control";
 
 pub const DEFAULT_LOG_LOCATION: &str = "/var/log/krossbar/krossbar.log";
 pub const DEFAULT_LOGGER_SOCKET_PATH: &str = "/var/run/krossbar.logger.socket";
+/// Persisted service log levels
+pub const DEFAULT_LEVELS_LOCATION: &str = "/var/lib/krossbar/log_levels.json";
 
 pub const ROTATED_LOG_TIMESTAMP_FORMAT: &str = "%Y_%m_%d_%H_%M_%S";
+
+/// Compressed rotated log files extensions
+pub const GZIP_EXTENSION: &str = "gz";
+pub const ZSTD_EXTENSION: &str = "zst";
@@ -0,0 +1,469 @@
+//! Text log line template.
+//!
+//! A template is a string with placeholders in curly braces:
+//! - `{ts:FORMAT}` message local time in `chrono` strftime FORMAT;
+//! - `{ts_utc:FORMAT}` message UTC time in `chrono` strftime FORMAT;
+//! - `{service}` client service name;
+//! - `{pid}` client PID;
+//! - `{level}` message level;
+//! - `{target}` message target;
+//! - `{message}` message text;
+//! - `{fields}` structured fields as ` {key=value, ...}`. Empty if the message has no fields;
+//! - `{location}` source location and thread as ` @ module file:line thread#id`. Empty if unknown.
+//!
+//! Use `{{` and `}}` to insert literal braces.
+//!
+//! The same template is used to parse log lines back, e.g. to colorize them in the viewer.
+//! Space padded timestamp numbers, e.g. `%e`, can't be parsed back, so they're rejected.
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use chrono::{
+    format::{Item, Pad, StrftimeItems},
+    DateTime, Local, NaiveDateTime, TimeZone, Utc,
+};
+use log::Level;
+
+use crate::log_message::{FieldsDisplay, LocationDisplay, LogMessage, LogRecord};
+
+pub const DEFAULT_LINE_TEMPLATE: &str =
+    "<{ts:%d-%m-%Y %H:%M:%S%.3f}> {service}#{pid} [{level}] {target} > {message}{fields}{location}";
+
+/// Template segment
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Literal(String),
+    Timestamp {
+        format: String,
+        utc: bool,
+        /// Number of spaces in a formatted timestamp. Used to parse lines
+        num_spaces: usize,
+    },
+    Service,
+    Pid,
+    Level,
+    Target,
+    Message,
+    Fields,
+    Location,
+}
+
+/// Parsed log line template
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+impl LineTemplate {
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Format log message into a line. The line doesn't include trailing newline
+    pub fn format(&self, service_name: &str, pid: i32, message: &LogMessage) -> String {
+        let mut line = String::new();
+
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(literal) => line += literal,
+                Segment::Timestamp { format, utc, .. } => {
+                    if *utc {
+                        line += &message
+                            .timestamp
+                            .with_timezone(&Utc)
+                            .format(format)
+                            .to_string()
+                    } else {
+                        line += &message.timestamp.format(format).to_string()
+                    }
+                }
+                Segment::Service => line += service_name,
+                Segment::Pid => line += &pid.to_string(),
+                Segment::Level => line += message.level.as_str(),
+                Segment::Target => line += &message.target,
+                Segment::Message => line += &message.message,
+                Segment::Fields => {
+                    if !message.fields.is_empty() {
+                        line += &format!(" {}", FieldsDisplay(&message.fields))
+                    }
+                }
+                Segment::Location => {
+                    if message.has_location() {
+                        line += &format!(" @ {}", LocationDisplay(message))
+                    }
+                }
+            }
+        }
+
+        line
+    }
+
+    /// Split a log line into template segments.
+    /// Returns [None] if the line doesn't match the template
+    pub fn parse_line<'a>(&'a self, line: &'a str) -> Option<Vec<(&'a Segment, &'a str)>> {
+        let mut result = vec![];
+        let mut position = 0;
+
+        for (index, segment) in self.segments.iter().enumerate() {
+            let rest = &line[position..];
+
+            let len = match segment {
+                Segment::Literal(literal) => {
+                    if !rest.starts_with(literal.as_str()) {
+                        return None;
+                    }
+
+                    literal.len()
+                }
+                Segment::Fields => Self::fields_len(rest),
+                Segment::Location => Self::location_len(rest, self.next_literal(index)),
+                Segment::Message => self.message_len(rest, index),
+                Segment::Timestamp { num_spaces, .. } => {
+                    // Skip timestamp spaces before searching for the next literal
+                    let skip = if *num_spaces == 0 {
+                        0
+                    } else {
+                        rest.match_indices(' ')
+                            .nth(num_spaces - 1)
+                            .map(|(position, _)| position + 1)?
+                    };
+
+                    skip + Self::value_len(&rest[skip..], self.next_literal(index))
+                }
+                _ => Self::value_len(rest, self.next_literal(index)),
+            };
+
+            result.push((segment, &rest[..len]));
+            position += len;
+        }
+
+        Some(result)
+    }
+
+    /// Parse a log line back into a record. Structured fields and location are not restored.
+    /// Returns [None] if the line doesn't match the template, or doesn't have a timestamp
+    /// with a date and a level
+    pub fn parse_record(&self, line: &str) -> Option<LogRecord> {
+        let mut timestamp = None;
+        let mut level = None;
+
+        let mut record = LogRecord {
+            pid: 0,
+            service_name: String::new(),
+            message: LogMessage::new(Level::Info, String::new(), String::new()),
+        };
+
+        for (segment, value) in self.parse_line(line)? {
+            match segment {
+                Segment::Timestamp { format, utc, .. } => {
+                    timestamp = Self::parse_timestamp(value, format, *utc)
+                }
+                Segment::Service => record.service_name = value.into(),
+                Segment::Pid => record.pid = value.parse().ok()?,
+                Segment::Level => level = value.parse().ok(),
+                Segment::Target => record.message.target = value.into(),
+                Segment::Message => record.message.message = value.into(),
+                _ => {}
+            }
+        }
+
+        record.message.timestamp = timestamp?;
+        record.message.level = level?;
+        Some(record)
+    }
+
+    fn parse_timestamp(value: &str, format: &str, utc: bool) -> Option<DateTime<Local>> {
+        let timestamp = NaiveDateTime::parse_from_str(value, format).ok()?;
+
+        if utc {
+            Some(Utc.from_utc_datetime(&timestamp).with_timezone(&Local))
+        } else {
+            Local.from_local_datetime(&timestamp).earliest()
+        }
+    }
+
+    /// Next literal after a placeholder. Optional blocks are skipped
+    fn next_literal(&self, index: usize) -> Option<&str> {
+        for segment in self.segments[index + 1..].iter() {
+            match segment {
+                Segment::Literal(literal) => return Some(literal),
+                Segment::Fields | Segment::Location => continue,
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    /// Placeholder value ends at the first occurrence of the following literal.
+    /// If placeholder is followed by another placeholder, value ends at a whitespace
+    fn value_len(rest: &str, next_literal: Option<&str>) -> usize {
+        match next_literal {
+            Some(literal) => rest.find(literal),
+            None => rest.find(char::is_whitespace),
+        }
+        .unwrap_or(rest.len())
+    }
+
+    /// Message is a free text, so it ends at the last occurrence of the following literal.
+    /// Fields and location blocks are cut from the end of the message
+    fn message_len(&self, rest: &str, index: usize) -> usize {
+        let following = &self.segments[index + 1..];
+
+        let mut len = match self.next_literal(index) {
+            Some(literal) => rest.rfind(literal).unwrap_or(rest.len()),
+            None if following.is_empty()
+                || matches!(following[0], Segment::Fields | Segment::Location) =>
+            {
+                rest.len()
+            }
+            None => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+        };
+
+        let mut followers = following
+            .iter()
+            .take_while(|segment| matches!(segment, Segment::Fields | Segment::Location))
+            .collect::<Vec<_>>();
+
+        // Cut optional blocks starting from the last one
+        while let Some(segment) = followers.pop() {
+            let message = &rest[..len];
+
+            match segment {
+                Segment::Location => {
+                    if let Some(position) = message
+                        .match_indices(" @ ")
+                        .map(|(position, _)| position)
+                        .find(|position| Self::is_location(&message[position + 3..]))
+                    {
+                        len = position
+                    }
+                }
+                _ => {
+                    if let Some((text, _)) = split_fields(message) {
+                        len = text.len()
+                    }
+                }
+            }
+        }
+
+        len
+    }
+
+    /// Fields block len if the string starts with one
+    fn fields_len(rest: &str) -> usize {
+        if !rest.starts_with(" {") {
+            return 0;
+        }
+
+        block_len(&rest[1..]).map(|len| len + 1).unwrap_or(0)
+    }
+
+    /// Location block len if the string starts with one
+    fn location_len(rest: &str, next_literal: Option<&str>) -> usize {
+        if !rest.starts_with(" @ ") {
+            return 0;
+        }
+
+        let len = match next_literal {
+            Some(literal) => rest[3..].find(literal).map(|len| len + 3),
+            None => None,
+        }
+        .unwrap_or(rest.len());
+
+        if Self::is_location(&rest[3..len]) {
+            len
+        } else {
+            0
+        }
+    }
+
+    /// Check if string looks like `module file:line thread#id`
+    fn is_location(location: &str) -> bool {
+        let mut components = location.splitn(3, ' ');
+
+        match (components.next(), components.next(), components.next()) {
+            (Some(_), Some(file_line), Some(thread)) => {
+                file_line.contains(':') && thread.contains('#')
+            }
+            _ => false,
+        }
+    }
+
+    /// Number of spaces in timestamps formatted with the **format**. Space padded numbers,
+    /// e.g. `%e`, have a variable number of spaces, so they're not supported
+    fn timestamp_num_spaces(format: &str) -> Result<usize, String> {
+        let mut num_spaces = 0;
+
+        for item in StrftimeItems::new(format) {
+            num_spaces += match item {
+                Item::Literal(literal) | Item::Space(literal) => literal.matches(' ').count(),
+                Item::OwnedLiteral(ref literal) | Item::OwnedSpace(ref literal) => {
+                    literal.matches(' ').count()
+                }
+                Item::Numeric(_, Pad::Space) => {
+                    return Err(format!(
+                        "Space padded numbers are not supported in timestamp format '{format}'"
+                    ))
+                }
+                // Fixed formats, e.g. RFC 2822, have the same number of spaces for any timestamp
+                Item::Fixed(_) => DateTime::UNIX_EPOCH
+                    .format_with_items([item].iter())
+                    .to_string()
+                    .matches(' ')
+                    .count(),
+                _ => 0,
+            };
+        }
+
+        Ok(num_spaces)
+    }
+
+    fn parse_placeholder(placeholder: &str) -> Result<Segment, String> {
+        let (name, argument) = match placeholder.split_once(':') {
+            Some((name, argument)) => (name, Some(argument)),
+            None => (placeholder, None),
+        };
+
+        let segment = match name {
+            "ts" | "ts_utc" => {
+                let format = argument
+                    .ok_or_else(|| format!("Missing timestamp format in '{{{placeholder}}}'"))?;
+
+                if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                    return Err(format!("Invalid timestamp format '{format}'"));
+                }
+
+                Segment::Timestamp {
+                    format: format.into(),
+                    utc: name == "ts_utc",
+                    num_spaces: Self::timestamp_num_spaces(format)?,
+                }
+            }
+            "service" => Segment::Service,
+            "pid" => Segment::Pid,
+            "level" => Segment::Level,
+            "target" => Segment::Target,
+            "message" => Segment::Message,
+            "fields" => Segment::Fields,
+            "location" => Segment::Location,
+            _ => return Err(format!("Unknown placeholder '{{{placeholder}}}'")),
+        };
+
+        if argument.is_some() && !matches!(segment, Segment::Timestamp { .. }) {
+            return Err(format!("Unexpected argument in '{{{placeholder}}}'"));
+        }
+
+        Ok(segment)
+    }
+}
+
+impl Default for LineTemplate {
+    fn default() -> Self {
+        DEFAULT_LINE_TEMPLATE.parse().unwrap()
+    }
+}
+
+impl FromStr for LineTemplate {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    let mut closed = false;
+
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+
+                        placeholder.push(c);
+                    }
+
+                    if !closed {
+                        return Err(format!(
+                            "Unterminated '{{{placeholder}' in the template. Use '{{{{' for a literal"
+                        ));
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    segments.push(Self::parse_placeholder(&placeholder)?);
+                }
+                '}' => return Err("Unmatched '}' in the template. Use '}}' for a literal".into()),
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self {
+            template: template.into(),
+            segments,
+        })
+    }
+}
+
+impl Display for LineTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+/// Split message text and trailing ` {key=value, ...}` fields block
+pub fn split_fields(message: &str) -> Option<(&str, &str)> {
+    message
+        .match_indices(" {")
+        .map(|(position, _)| position)
+        .find(|position| block_len(&message[position + 1..]) == Some(message.len() - position - 1))
+        .map(|position| (&message[..position], &message[position + 1..]))
+}
+
+/// Length of the braces block at the beginning of the string, skipping quoted strings
+fn block_len(block: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (position, c) in block.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(position + 1);
+                }
+            }
+            _ if depth == 0 => return None,
+            _ => {}
+        }
+    }
+
+    None
+}
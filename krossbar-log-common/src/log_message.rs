@@ -1,13 +1,82 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+};
+
 use chrono::{DateTime, Local};
-use log::Level;
+use log::{
+    kv::{self, VisitSource},
+    Level, Record,
+};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/// Target of the synthetic record, which reports messages dropped by a client
+pub const DROPPED_MESSAGES_TARGET: &str = "krossbar::dropped";
+
+/// Next thread id to assign
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// Process unique id of the current thread. Assigned on the first log message from the thread
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Structured message fields. Keys are sorted to keep the output stable
+pub type LogFields = BTreeMap<String, LogValue>;
+
+/// Typed value of a structured log field
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum LogValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Map(LogFields),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct LogMessage {
     pub timestamp: DateTime<Local>,
     pub level: Level,
     pub target: String,
     pub message: String,
+    /// Structured key-value fields attached with the `log` crate `kv` API
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: LogFields,
+    /// Source file the record was emitted from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Source line the record was emitted from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// Module path the record was emitted from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module_path: Option<String>,
+    /// Name of the thread the record was emitted from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_name: Option<String>,
+    /// Process unique id of the thread the record was emitted from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<u64>,
+    /// Client message sequence number. Increases monotonically in the order
+    /// the messages were logged by the client process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    /// Number of dropped messages for [LogMessage::dropped] reports. Can't be set
+    /// with the `log` macros, so regular records don't count as reports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dropped: Option<u64>,
+}
+
+/// Log message with its sender
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LogRecord {
+    pub pid: i32,
+    pub service_name: String,
+    pub message: LogMessage,
 }
 
 impl LogMessage {
@@ -17,6 +86,141 @@ impl LogMessage {
             level,
             target,
             message,
+            fields: LogFields::new(),
+            file: None,
+            line: None,
+            module_path: None,
+            thread_name: None,
+            thread_id: None,
+            seq: None,
+            dropped: None,
+        }
+    }
+
+    /// Make a log message out of a `log` crate record, including its key-value pairs,
+    /// source location, and the calling thread
+    pub fn from_record(record: &Record) -> Self {
+        let mut fields = FieldsCollector(LogFields::new());
+        // Collector never fails
+        let _ = record.key_values().visit(&mut fields);
+
+        let thread = thread::current();
+
+        Self {
+            timestamp: Local::now(),
+            level: record.level(),
+            target: record.metadata().target().to_owned(),
+            message: format!("{}", record.args()),
+            fields: fields.0,
+            file: record.file().map(Into::into),
+            line: record.line(),
+            module_path: record.module_path().map(Into::into),
+            thread_name: thread.name().map(Into::into),
+            // Not available while the thread is being destroyed
+            thread_id: THREAD_ID.try_with(|id| *id).ok(),
+            seq: None,
+            dropped: None,
+        }
+    }
+
+    /// Synthetic record reporting **num_dropped** messages dropped by a client
+    pub fn dropped(num_dropped: u64) -> Self {
+        let mut message = Self::new(
+            Level::Warn,
+            DROPPED_MESSAGES_TARGET.into(),
+            format!("{num_dropped} messages dropped"),
+        );
+
+        message.dropped = Some(num_dropped);
+        message
+    }
+
+    /// If the message has any of source location or thread metadata
+    pub fn has_location(&self) -> bool {
+        self.file.is_some()
+            || self.line.is_some()
+            || self.module_path.is_some()
+            || self.thread_name.is_some()
+            || self.thread_id.is_some()
+    }
+}
+
+impl LogValue {
+    /// Convert `log` crate value into a typed value.
+    /// Values, which don't fit into any of the types (e.g. sequences, or integers
+    /// out of `i64` range) are stored as strings
+    pub fn from_kv(value: &kv::Value) -> Self {
+        bson::to_bson(value)
+            .ok()
+            .and_then(|bson| bson::from_bson(bson).ok())
+            .unwrap_or_else(|| LogValue::Str(value.to_string()))
+    }
+}
+
+impl Display for LogValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogValue::Bool(value) => write!(f, "{value}"),
+            LogValue::Int(value) => write!(f, "{value}"),
+            LogValue::Float(value) => write!(f, "{value}"),
+            LogValue::Str(value) => write!(f, "{value:?}"),
+            LogValue::Map(fields) => write!(f, "{}", FieldsDisplay(fields)),
         }
     }
 }
+
+/// Displays fields as `{key="value", other_key=42}`
+pub struct FieldsDisplay<'a>(pub &'a LogFields);
+
+impl<'a> Display for FieldsDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{key}={value}")?;
+        }
+
+        write!(f, "}}")
+    }
+}
+
+/// Displays message metadata as `module_path file:line thread_name#thread_id`.
+/// Missing values are replaced with `?`
+pub struct LocationDisplay<'a>(pub &'a LogMessage);
+
+impl<'a> Display for LocationDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = || "?".to_owned();
+        let message = self.0;
+
+        write!(
+            f,
+            "{} {}:{} {}#{}",
+            message.module_path.clone().unwrap_or_else(unknown),
+            message.file.clone().unwrap_or_else(unknown),
+            message.line.map(|l| l.to_string()).unwrap_or_else(unknown),
+            message
+                .thread_name
+                .clone()
+                .unwrap_or_else(|| "<unnamed>".to_owned()),
+            message
+                .thread_id
+                .map(|id| id.to_string())
+                .unwrap_or_else(unknown),
+        )
+    }
+}
+
+/// Record key-values visitor
+struct FieldsCollector(LogFields);
+
+impl<'kvs> VisitSource<'kvs> for FieldsCollector {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.insert(key.to_string(), LogValue::from_kv(&value));
+        Ok(())
+    }
+}
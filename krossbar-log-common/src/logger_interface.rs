@@ -1,16 +1,256 @@
+use std::time::Duration;
+
+use bson::Bson;
+use chrono::{DateTime, Local, NaiveDateTime};
 use log::LevelFilter;
 use serde::{Deserialize, Serialize};
 
+use crate::log_message::{LogMessage, LogRecord};
+
 pub const LOGGER_SERVICE_NAME: &str = "krossbar.logger";
 
 pub const SET_LOG_LEVEL_METHOD_NAME: &str = "set_log_level";
+pub const GET_LOG_LEVEL_METHOD_NAME: &str = "get_log_level";
 pub const LOG_CLIENTS_METHOD_NAME: &str = "clients";
+pub const LOG_CLIENTS_INFO_METHOD_NAME: &str = "clients_info";
+pub const ROTATE_METHOD_NAME: &str = "rotate";
+pub const TAIL_METHOD_NAME: &str = "tail";
+pub const QUERY_METHOD_NAME: &str = "query";
+pub const DUMP_METHOD_NAME: &str = "dump";
 pub const LOG_METHOD_NAME: &str = "log";
+/// Batched [LOG_METHOD_NAME]. Payload is an array of [LogMessage].
+/// Clients send batches only if the logger reports support in the [RegisterResponse]
+pub const LOG_BATCH_METHOD_NAME: &str = "log_batch";
+/// Client registration. Payload is the client service name. Response is a [RegisterResponse]
 pub const REGISTER_METHOD_NAME: &str = "register";
+/// Rotated log file signal. Payload is the rotated file path
 pub const ROTATED_SIGNAL: &str = "rotated";
+/// Rotated log file signal. Payload is a [LogRotated]
+pub const LOG_ROTATED_SIGNAL: &str = "log_rotated";
+pub const REMOVED_SIGNAL: &str = "removed";
+/// Live tail signal name prefix. See [tail_signal_name]
+pub const TAIL_SIGNAL: &str = "log_events";
+
+/// Live tail subscription lifetime. Subscribers keep the subscription by calling
+/// [TAIL_METHOD_NAME] again before it expires
+pub const TAIL_SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(30);
 
-#[derive(Serialize, Deserialize)]
+/// Live tail signal of the **subscriber**. Payload is a [LogRecord] matching the subscriber
+/// filter. The signal is registered by the first [TAIL_METHOD_NAME] call, so subscribe after it
+pub fn tail_signal_name(subscriber: &str) -> String {
+    format!("{TAIL_SIGNAL}.{subscriber}")
+}
+
+/// Decode [LOG_METHOD_NAME] or [LOG_BATCH_METHOD_NAME] message **body**
+pub fn decode_log_messages(endpoint: &str, body: Bson) -> bson::de::Result<Vec<LogMessage>> {
+    if endpoint == LOG_BATCH_METHOD_NAME {
+        bson::from_bson(body)
+    } else {
+        bson::from_bson(body).map(|message| vec![message])
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SetLogLevel {
     pub service_name: String,
+    /// Default level for targets, which don't match any of the directives
     pub level: LevelFilter,
+    /// `env_filter` directives, e.g. `info,my_crate::net=trace,hyper=warn`.
+    /// Directives take precedence over the `level`
+    #[serde(default)]
+    pub directives: Option<String>,
+    /// Temporary level change. The logger restores the previous level after the duration.
+    /// Temporary levels are kept across the logger restarts, but are not stored as the service level
+    #[serde(default)]
+    pub revert_after: Option<Duration>,
+    /// Restore the level the client started with. `level` and `directives` are ignored
+    #[serde(default)]
+    pub reset: bool,
+}
+
+impl SetLogLevel {
+    /// Permanent level change message
+    pub fn new(service_name: &str, log_level: LogLevel) -> Self {
+        Self {
+            service_name: service_name.into(),
+            level: log_level.level,
+            directives: log_level.directives,
+            revert_after: None,
+            reset: false,
+        }
+    }
+
+    /// Message to restore client initial level
+    pub fn reset(service_name: &str) -> Self {
+        Self {
+            service_name: service_name.into(),
+            level: LevelFilter::Off,
+            directives: None,
+            revert_after: None,
+            reset: true,
+        }
+    }
+
+    pub fn log_level(&self) -> LogLevel {
+        LogLevel {
+            level: self.level,
+            directives: self.directives.clone(),
+        }
+    }
+}
+
+/// [REGISTER_METHOD_NAME] response. Older loggers respond with an empty value,
+/// which means no optional features are supported
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RegisterResponse {
+    /// If the logger accepts [LOG_BATCH_METHOD_NAME] frames
+    #[serde(default)]
+    pub log_batch: bool,
+}
+
+/// Client log level
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogLevel {
+    /// Default level for targets, which don't match any of the directives
+    pub level: LevelFilter,
+    /// `env_filter` directives. Directives take precedence over the `level`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directives: Option<String>,
+}
+
+/// [GET_LOG_LEVEL_METHOD_NAME] response entry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServiceLogLevel {
+    pub service_name: String,
+    /// Level reported by the client, or an error if the client failed to respond
+    pub log_level: Result<LogLevel, String>,
+}
+
+/// Number of received messages per level
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MessageCounters {
+    pub error: u64,
+    pub warn: u64,
+    pub info: u64,
+    pub debug: u64,
+    pub trace: u64,
+}
+
+impl MessageCounters {
+    pub fn total(&self) -> u64 {
+        self.error + self.warn + self.info + self.debug + self.trace
+    }
+}
+
+/// [LOG_CLIENTS_INFO_METHOD_NAME] response entry
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClientInfo {
+    pub service_name: String,
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Client executable path. [None] if the logger can't read it
+    pub exe: Option<String>,
+    pub connected_at: DateTime<Local>,
+    pub messages: MessageCounters,
+    /// Bytes of the client messages written into the logs. A message written into
+    /// both the combined and a per-service log file is counted once
+    pub bytes_written: u64,
+    pub last_message_at: Option<DateTime<Local>>,
+    /// Messages dropped by the client or by the logger, because the log queue was full
+    #[serde(default)]
+    pub dropped: u64,
+}
+
+/// [TAIL_METHOD_NAME] params. Messages matching all of the conditions are sent to the subscriber
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TailFilter {
+    /// Service to tail. All services if [None]
+    #[serde(default)]
+    pub service_name: Option<String>,
+    /// Minimum message level
+    pub level: LevelFilter,
+    /// Message target prefix, e.g. `my_crate::net`
+    #[serde(default)]
+    pub target_prefix: Option<String>,
+}
+
+impl TailFilter {
+    pub fn matches(&self, service_name: &str, message: &LogMessage) -> bool {
+        message.level <= self.level
+            && self
+                .service_name
+                .as_ref()
+                .map(|name| name == service_name)
+                .unwrap_or(true)
+            && self
+                .target_prefix
+                .as_ref()
+                .map(|prefix| message.target.starts_with(prefix))
+                .unwrap_or(true)
+    }
+}
+
+/// Max number of records in a [QUERY_METHOD_NAME] response page
+pub const MAX_QUERY_PAGE_SIZE: usize = 1000;
+
+/// [QUERY_METHOD_NAME] params. Records matching all of the conditions are returned
+/// in the order they were written, from the oldest log file to the live one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogQuery {
+    /// Minimal record time
+    #[serde(default)]
+    pub from: Option<DateTime<Local>>,
+    /// Maximal record time
+    #[serde(default)]
+    pub to: Option<DateTime<Local>>,
+    /// Service name. All services if [None]
+    #[serde(default)]
+    pub service_name: Option<String>,
+    /// Minimum message level
+    pub level: LevelFilter,
+    /// Message target prefix, e.g. `my_crate::net`
+    #[serde(default)]
+    pub target_prefix: Option<String>,
+    /// Message text substring, or a regular expression if `regex` is set
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub regex: bool,
+    /// Max number of records in the page. Limited by [MAX_QUERY_PAGE_SIZE]
+    pub page_size: usize,
+    /// Position to continue the search from. Use [QueryPage::next] of the previous page
+    #[serde(default)]
+    pub cursor: Option<QueryCursor>,
+}
+
+/// Search position in the log files
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryCursor {
+    /// Rotated log file timestamp. [None] for the live log file
+    pub rotated_at: Option<NaiveDateTime>,
+    /// Rotated log file index among the logs rotated within the same second
+    #[serde(default)]
+    pub rotated_index: u32,
+    /// Line to continue from
+    pub line: u64,
+    /// Time the cursor was made at. Used to find the live log file if it's rotated since
+    pub created_at: NaiveDateTime,
+}
+
+/// [QUERY_METHOD_NAME] response
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueryPage {
+    pub records: Vec<LogRecord>,
+    /// Next page position. [None] if there are no more records
+    pub next: Option<QueryCursor>,
+}
+
+/// [LOG_ROTATED_SIGNAL] payload
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogRotated {
+    /// Service name if a per-service log file is rotated. [None] for the combined log file
+    pub service_name: Option<String>,
+    /// Rotated log file path
+    pub file_name: String,
 }
@@ -0,0 +1,135 @@
+//! Rotated log files naming.
+//!
+//! Rotated logs are named after the live log file:
+//! `<live log stem>_<ROTATED_LOG_TIMESTAMP_FORMAT>.<live log extension>`, and may have an
+//! additional compression extension, e.g. `krossbar_2024_01_31_12_00_00.log.gz`.
+//! Logs rotated within the same second get an index after the timestamp,
+//! e.g. `krossbar_2024_01_31_12_00_00_1.log`
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDateTime};
+
+use crate::{GZIP_EXTENSION, ROTATED_LOG_TIMESTAMP_FORMAT, ZSTD_EXTENSION};
+
+const DEFAULT_STEM: &str = "krossbar_log";
+const DEFAULT_EXTENSION: &str = "log";
+
+/// Rotated log identity. Sorted from the oldest to the newest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RotatedLogId {
+    /// Rotation timestamp truncated to seconds
+    pub timestamp: NaiveDateTime,
+    /// Index among the logs rotated within the same second. Zero for the first one
+    pub index: u32,
+}
+
+/// Rotated log names for a particular live log file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogNaming {
+    stem: String,
+    extension: String,
+}
+
+impl LogNaming {
+    pub fn new(live_log_path: &Path) -> Self {
+        Self {
+            stem: live_log_path
+                .file_stem()
+                .unwrap_or(OsStr::new(DEFAULT_STEM))
+                .to_string_lossy()
+                .into(),
+            extension: live_log_path
+                .extension()
+                .unwrap_or(OsStr::new(DEFAULT_EXTENSION))
+                .to_string_lossy()
+                .into(),
+        }
+    }
+
+    /// Make uncompressed rotated log file name. Non-zero **index** distinguishes logs
+    /// rotated within the same second
+    pub fn rotated_name(&self, timestamp: &DateTime<Local>, index: u32) -> String {
+        let timestamp = timestamp.format(ROTATED_LOG_TIMESTAMP_FORMAT);
+
+        if index == 0 {
+            format!("{}_{}.{}", self.stem, timestamp, self.extension)
+        } else {
+            format!("{}_{}_{}.{}", self.stem, timestamp, index, self.extension)
+        }
+    }
+
+    /// Parse rotated log identity out of the rotated log file name.
+    /// Returns [None] if the file is not a rotated log of the live log
+    pub fn parse_rotated_name(&self, file_name: &str) -> Option<RotatedLogId> {
+        let file_name = [GZIP_EXTENSION, ZSTD_EXTENSION]
+            .iter()
+            .find_map(|extension| file_name.strip_suffix(&format!(".{extension}")))
+            .unwrap_or(file_name);
+
+        let timestamp = file_name
+            .strip_prefix(&format!("{}_", self.stem))?
+            .strip_suffix(&format!(".{}", self.extension))?;
+
+        let parse_timestamp = |timestamp: &str| {
+            NaiveDateTime::parse_from_str(timestamp, ROTATED_LOG_TIMESTAMP_FORMAT).ok()
+        };
+
+        if let Some(timestamp) = parse_timestamp(timestamp) {
+            return Some(RotatedLogId {
+                timestamp,
+                index: 0,
+            });
+        }
+
+        let (timestamp, index) = timestamp.rsplit_once('_')?;
+        if !index.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+
+        Some(RotatedLogId {
+            timestamp: parse_timestamp(timestamp)?,
+            index: index.parse().ok().filter(|index| *index > 0)?,
+        })
+    }
+
+    /// List rotated logs in the **logs_dir** sorted from the oldest to the newest.
+    /// If a log has both plain and compressed files, e.g. while it's being compressed,
+    /// the plain file is listed. The compressed one is incomplete until the plain one is removed
+    pub fn list_rotated_logs(&self, logs_dir: &Path) -> io::Result<Vec<(RotatedLogId, PathBuf)>> {
+        let mut rotated_logs: BTreeMap<RotatedLogId, PathBuf> = BTreeMap::new();
+
+        for dir_entry in logs_dir.read_dir()?.flatten() {
+            if !dir_entry
+                .file_type()
+                .is_ok_and(|file_type| file_type.is_file())
+            {
+                continue;
+            }
+
+            let Some(id) = self.parse_rotated_name(&dir_entry.file_name().to_string_lossy()) else {
+                continue;
+            };
+
+            match rotated_logs.get(&id) {
+                Some(existing) if !is_compressed(existing) => {}
+                _ => {
+                    rotated_logs.insert(id, dir_entry.path());
+                }
+            }
+        }
+
+        Ok(rotated_logs.into_iter().collect())
+    }
+}
+
+/// If the **path** has a compressed log extension
+pub fn is_compressed(path: &Path) -> bool {
+    let extension = path.extension();
+
+    extension == Some(OsStr::new(GZIP_EXTENSION)) || extension == Some(OsStr::new(ZSTD_EXTENSION))
+}
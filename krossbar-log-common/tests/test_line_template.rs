@@ -0,0 +1,130 @@
+use chrono::{DateTime, Local};
+use log::Level;
+
+use krossbar_log_common::{
+    line_template::{LineTemplate, Segment},
+    log_message::{LogMessage, LogValue},
+};
+
+fn test_message() -> LogMessage {
+    let mut message = LogMessage::new(
+        Level::Warn,
+        "test_target".into(),
+        "Message with > and {braces}".into(),
+    );
+
+    message.timestamp = DateTime::parse_from_rfc3339("2024-03-05T10:20:30.123+00:00")
+        .unwrap()
+        .with_timezone(&Local);
+    message
+        .fields
+        .insert("request_id".into(), LogValue::Str("a b".into()));
+    message.module_path = Some("test::module".into());
+    message.file = Some("src/test.rs".into());
+    message.line = Some(42);
+
+    message
+}
+
+fn find<'a>(components: &[(&Segment, &'a str)], segment: &Segment) -> &'a str {
+    components
+        .iter()
+        .find(|(s, _)| *s == segment)
+        .map(|(_, value)| *value)
+        .unwrap()
+}
+
+#[test]
+fn test_default_template() {
+    let template = LineTemplate::default();
+    let message = test_message();
+
+    let line = template.format("test.service", 420, &message);
+    assert!(line.contains(" test.service#420 [WARN] test_target > Message with > and {braces}"));
+    assert!(line.ends_with(r#" {request_id="a b"} @ test::module src/test.rs:42 <unnamed>#?"#));
+
+    let components = template.parse_line(&line).unwrap();
+    assert_eq!(find(&components, &Segment::Service), "test.service");
+    assert_eq!(find(&components, &Segment::Pid), "420");
+    assert_eq!(find(&components, &Segment::Level), "WARN");
+    assert_eq!(find(&components, &Segment::Target), "test_target");
+    assert_eq!(
+        find(&components, &Segment::Message),
+        "Message with > and {braces}"
+    );
+    assert_eq!(
+        find(&components, &Segment::Fields),
+        r#" {request_id="a b"}"#
+    );
+    assert_eq!(
+        find(&components, &Segment::Location),
+        " @ test::module src/test.rs:42 <unnamed>#?"
+    );
+
+    // Components cover the whole line
+    let joined: String = components.iter().map(|(_, value)| *value).collect();
+    assert_eq!(joined, line);
+}
+
+#[test]
+fn test_custom_template() {
+    let template: LineTemplate = "{ts_utc:%Y-%m-%d %H:%M:%S} {level} | {message} | {service}"
+        .parse()
+        .unwrap();
+
+    let mut message = test_message();
+    message.fields.clear();
+
+    let line = template.format("test.service", 420, &message);
+    assert_eq!(
+        line,
+        "2024-03-05 10:20:30 WARN | Message with > and {braces} | test.service"
+    );
+
+    let components = template.parse_line(&line).unwrap();
+    assert_eq!(find(&components, &Segment::Level), "WARN");
+    assert_eq!(
+        find(&components, &Segment::Message),
+        "Message with > and {braces}"
+    );
+    assert_eq!(find(&components, &Segment::Service), "test.service");
+
+    assert!(template.parse_line("Not a log line").is_none());
+}
+
+#[test]
+fn test_timestamp_with_spaces() {
+    let template: LineTemplate = "{ts_utc:%d %b %Y %H:%M:%S} {level} {message}"
+        .parse()
+        .unwrap();
+
+    let line = template.format("test.service", 420, &test_message());
+
+    let components = template.parse_line(&line).unwrap();
+    assert_eq!(find(&components, &Segment::Level), "WARN");
+    assert_eq!(
+        find(&components, &Segment::Message),
+        "Message with > and {braces}"
+    );
+}
+
+#[test]
+fn test_invalid_template() {
+    assert!("{unknown}".parse::<LineTemplate>().is_err());
+    assert!("{ts}".parse::<LineTemplate>().is_err());
+    assert!("{ts:%Q}".parse::<LineTemplate>().is_err());
+    // Space padded day has a variable number of spaces
+    assert!("{ts:%e %H:%M}".parse::<LineTemplate>().is_err());
+    assert!("{level:arg}".parse::<LineTemplate>().is_err());
+    assert!("message}".parse::<LineTemplate>().is_err());
+    assert!("{timestamp {message".parse::<LineTemplate>().is_err());
+    assert!("{message} {level".parse::<LineTemplate>().is_err());
+
+    let template: LineTemplate = "{{{level}}} {message}".parse().unwrap();
+    let line = template.format(
+        "service",
+        1,
+        &LogMessage::new(Level::Info, "".into(), "Hello".into()),
+    );
+    assert_eq!(line, "{INFO} Hello");
+}
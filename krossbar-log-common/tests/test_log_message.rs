@@ -0,0 +1,25 @@
+use std::thread;
+
+use log::{Level, Record};
+
+use krossbar_log_common::log_message::LogMessage;
+
+fn thread_id() -> Option<u64> {
+    let record = Record::builder()
+        .level(Level::Info)
+        .args(format_args!("Test"))
+        .build();
+
+    LogMessage::from_record(&record).thread_id
+}
+
+#[test]
+fn test_thread_id() {
+    let id = thread_id();
+    assert!(id.is_some());
+    assert_eq!(thread_id(), id);
+
+    let other_id = thread::spawn(thread_id).join().unwrap();
+    assert!(other_id.is_some());
+    assert_ne!(other_id, id);
+}
@@ -0,0 +1,208 @@
+use std::time::Duration;
+
+use chrono::{Local, TimeZone};
+use log::{Level, LevelFilter};
+
+use krossbar_log_common::{
+    log_message::{LogMessage, LogValue, DROPPED_MESSAGES_TARGET},
+    logger_interface::{
+        decode_log_messages, ClientInfo, LogLevel, MessageCounters, ServiceLogLevel, SetLogLevel,
+        TailFilter, LOG_BATCH_METHOD_NAME, LOG_METHOD_NAME,
+    },
+};
+
+#[test]
+fn test_set_log_level_bson() {
+    let message = SetLogLevel {
+        service_name: "com.test.service".into(),
+        level: LevelFilter::Info,
+        directives: Some("net=trace".into()),
+        revert_after: Some(Duration::from_secs(15 * 60)),
+        reset: false,
+    };
+
+    let bson = bson::to_bson(&message).unwrap();
+    assert_eq!(bson::from_bson::<SetLogLevel>(bson).unwrap(), message);
+
+    // Older control tools do not send optional fields
+    let bson = bson::bson!({ "service_name": "com.test.service", "level": "DEBUG" });
+    assert_eq!(
+        bson::from_bson::<SetLogLevel>(bson).unwrap(),
+        SetLogLevel::new(
+            "com.test.service",
+            LogLevel {
+                level: LevelFilter::Debug,
+                directives: None
+            }
+        )
+    );
+}
+
+#[test]
+fn test_service_log_level_bson() {
+    let levels = vec![
+        ServiceLogLevel {
+            service_name: "com.test.first".into(),
+            log_level: Ok(LogLevel {
+                level: LevelFilter::Warn,
+                directives: Some("net=trace".into()),
+            }),
+        },
+        ServiceLogLevel {
+            service_name: "com.test.second".into(),
+            log_level: Err("Client didn't respond".into()),
+        },
+    ];
+
+    let bson = bson::to_bson(&levels).unwrap();
+    assert_eq!(
+        bson::from_bson::<Vec<ServiceLogLevel>>(bson).unwrap(),
+        levels
+    );
+}
+
+#[test]
+fn test_client_info_bson() {
+    let connected_at = Local.timestamp_millis_opt(1_700_000_000_123).unwrap();
+
+    let clients = vec![
+        ClientInfo {
+            service_name: "com.test.first".into(),
+            pid: 42,
+            uid: 1000,
+            gid: 1000,
+            exe: Some("/usr/bin/test".into()),
+            connected_at,
+            messages: MessageCounters {
+                error: 1,
+                warn: 2,
+                info: 3,
+                debug: 4,
+                trace: 5,
+            },
+            bytes_written: 1024,
+            last_message_at: Some(connected_at),
+            dropped: 7,
+        },
+        ClientInfo {
+            service_name: "com.test.second".into(),
+            pid: 43,
+            uid: 0,
+            gid: 0,
+            exe: None,
+            connected_at,
+            messages: MessageCounters::default(),
+            bytes_written: 0,
+            last_message_at: None,
+            dropped: 0,
+        },
+    ];
+
+    assert_eq!(clients[0].messages.total(), 15);
+
+    let bson = bson::to_bson(&clients).unwrap();
+    assert_eq!(bson::from_bson::<Vec<ClientInfo>>(bson).unwrap(), clients);
+}
+
+#[test]
+fn test_dropped_messages_report() {
+    let report = LogMessage::dropped(42);
+    assert_eq!(report.level, Level::Warn);
+    assert_eq!(report.message, "42 messages dropped");
+    assert_eq!(report.dropped, Some(42));
+
+    let bson = bson::to_bson(&report).unwrap();
+    assert_eq!(
+        bson::from_bson::<LogMessage>(bson).unwrap().dropped,
+        Some(42)
+    );
+
+    // Regular messages with the same field are not reports
+    let mut message = LogMessage::new(Level::Warn, "my_crate".into(), "Dropped".into());
+    message.fields = report.fields.clone();
+    assert_eq!(message.dropped, None);
+
+    // Including the ones with the report target and a `dropped` field
+    let mut message = LogMessage::new(
+        Level::Warn,
+        DROPPED_MESSAGES_TARGET.into(),
+        "42 messages dropped".into(),
+    );
+    message.fields.insert("dropped".into(), LogValue::Int(42));
+    assert_eq!(message.dropped, None);
+}
+
+#[test]
+fn test_decode_log_messages() {
+    let messages: Vec<_> = (0..3)
+        .map(|i| LogMessage::new(Level::Info, "my_crate".into(), format!("Message {i}")))
+        .collect();
+
+    let texts = |messages: Vec<LogMessage>| -> Vec<String> {
+        messages
+            .into_iter()
+            .map(|message| message.message)
+            .collect()
+    };
+
+    let batch = bson::to_bson(&messages).unwrap();
+    assert_eq!(
+        texts(decode_log_messages(LOG_BATCH_METHOD_NAME, batch.clone()).unwrap()),
+        ["Message 0", "Message 1", "Message 2"]
+    );
+
+    // Older clients send messages one by one
+    let single = bson::to_bson(&messages[1]).unwrap();
+    assert_eq!(
+        texts(decode_log_messages(LOG_METHOD_NAME, single.clone()).unwrap()),
+        ["Message 1"]
+    );
+
+    assert!(decode_log_messages(LOG_METHOD_NAME, batch).is_err());
+    assert!(decode_log_messages(LOG_BATCH_METHOD_NAME, single).is_err());
+}
+
+#[test]
+fn test_tail_filter() {
+    let message = LogMessage::new(Level::Debug, "my_crate::net::tcp".into(), "Hello".into());
+
+    let filter = TailFilter {
+        service_name: None,
+        level: LevelFilter::Trace,
+        target_prefix: None,
+    };
+    assert!(filter.matches("com.test.service", &message));
+
+    let filter = TailFilter {
+        service_name: Some("com.test.service".into()),
+        level: LevelFilter::Debug,
+        target_prefix: Some("my_crate::net".into()),
+    };
+    assert!(filter.matches("com.test.service", &message));
+    assert!(!filter.matches("com.test.other", &message));
+
+    let filter = TailFilter {
+        service_name: None,
+        level: LevelFilter::Info,
+        target_prefix: None,
+    };
+    assert!(!filter.matches("com.test.service", &message));
+
+    let filter = TailFilter {
+        service_name: None,
+        level: LevelFilter::Trace,
+        target_prefix: Some("my_crate::db".into()),
+    };
+    assert!(!filter.matches("com.test.service", &message));
+
+    // Older subscribers may omit optional fields
+    let bson = bson::bson!({ "level": "WARN" });
+    assert_eq!(
+        bson::from_bson::<TailFilter>(bson).unwrap(),
+        TailFilter {
+            service_name: None,
+            level: LevelFilter::Warn,
+            target_prefix: None,
+        }
+    );
+}
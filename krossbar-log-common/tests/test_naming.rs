@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use chrono::{Local, NaiveDate, TimeZone};
+
+use krossbar_log_common::naming::{LogNaming, RotatedLogId};
+
+#[test]
+fn test_rotated_names() {
+    let naming = LogNaming::new(Path::new("/var/log/krossbar/krossbar.log"));
+
+    let timestamp = NaiveDate::from_ymd_opt(2024, 1, 31)
+        .unwrap()
+        .and_hms_opt(12, 30, 59)
+        .unwrap();
+
+    let id = RotatedLogId {
+        timestamp,
+        index: 0,
+    };
+
+    let rotated_name = naming.rotated_name(&Local.from_local_datetime(&timestamp).unwrap(), 0);
+    assert_eq!(rotated_name, "krossbar_2024_01_31_12_30_59.log");
+
+    assert_eq!(naming.parse_rotated_name(&rotated_name), Some(id));
+    assert_eq!(
+        naming.parse_rotated_name("krossbar_2024_01_31_12_30_59.log.gz"),
+        Some(id)
+    );
+    assert_eq!(
+        naming.parse_rotated_name("krossbar_2024_01_31_12_30_59.log.zst"),
+        Some(id)
+    );
+
+    // Logs rotated within the same second
+    let next_id = RotatedLogId {
+        timestamp,
+        index: 2,
+    };
+
+    let rotated_name = naming.rotated_name(&Local.from_local_datetime(&timestamp).unwrap(), 2);
+    assert_eq!(rotated_name, "krossbar_2024_01_31_12_30_59_2.log");
+
+    assert_eq!(naming.parse_rotated_name(&rotated_name), Some(next_id));
+    assert_eq!(
+        naming.parse_rotated_name("krossbar_2024_01_31_12_30_59_2.log.gz"),
+        Some(next_id)
+    );
+    assert!(id < next_id);
+
+    // Foreign files
+    assert!(naming.parse_rotated_name("krossbar.log").is_none());
+    assert!(naming.parse_rotated_name("core.1234").is_none());
+    assert!(naming
+        .parse_rotated_name("krossbar_2024_01_31_12_30_59.log.bak")
+        .is_none());
+    assert!(naming
+        .parse_rotated_name("krossbar_2024_01_31_12_30_59.txt")
+        .is_none());
+    assert!(naming
+        .parse_rotated_name("other_2024_01_31_12_30_59.log")
+        .is_none());
+    assert!(naming.parse_rotated_name("krossbar_backup.log").is_none());
+    assert!(naming
+        .parse_rotated_name("krossbar_2024_01_31_12_30_59_0.log")
+        .is_none());
+    assert!(naming
+        .parse_rotated_name("krossbar_2024_01_31_12_30_59_+1.log")
+        .is_none());
+    assert!(naming
+        .parse_rotated_name("krossbar_2024_01_31_12_30_59_old.log")
+        .is_none());
+}
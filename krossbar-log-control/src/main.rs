@@ -2,8 +2,8 @@
 //!
 //! The tool allows listing connected clients, and change their log level interactively.
 //!
-//! Note: Log level for a particular service changes until restarted. Use logger internal mechanism to
-//! persistently change log level.
+//! Log levels are persisted by the logger (see `krossbar-logger --levels-location`), and
+//! re-applied when a service restarts and reconnects to the logger.
 //!
 //! # Usage
 //!
@@ -19,40 +19,160 @@
 //! SUBCOMMANDS:
 //!     help             Print this message or the help of the given subcommand(s)
 //!     list             List connected services
+//!     get-log-level    Show service log level
 //!     set-log-level    Change service log level
+//!     rotate           Rotate log file
+//!     tail             Watch log messages live
+//!     query            Search live and rotated log files
+//!     dump             Print recent messages kept in the logger memory
 //! ```
 //!
-//! List connected services:
+//! List connected services with their pid, uid, gid, connection time, number of received
+//! messages per level (ERROR/WARN/INFO/DEBUG/TRACE), bytes written into the log, and the last
+//! message time. Use it to find a service, which floods the log:
 //! ```sh
 //! USAGE:
-//!     krossbar-log-control list
+//!     krossbar-log-control list [OPTIONS]
 //!
 //! OPTIONS:
 //!     -h, --help    Print help information
+//!         --json    Print clients as JSON
 //! ```
 //!
-//! Change service log level:
+//! Show service log level as reported by the service. Shows all connected services if the service
+//! name is not set:
 //! ```sh
 //! USAGE:
-//!     krossbar-log-control set-log-level --service-name <SERVICE_NAME> --level <LEVEL>
+//!     krossbar-log-control get-log-level [OPTIONS]
 //!
 //! OPTIONS:
 //!     -h, --help                           Print help information
-//!     -l, --level <LEVEL>                  Log level: OFF, ERROR, WARN, INFO, DEBUG, TRACE
+//!     -s, --service-name <SERVICE_NAME>    Service name. Shows all connected services if not set
+//! ```
+//!
+//! Change service log level. The command waits for the service to apply the level, and fails if
+//! the service is not connected or doesn't confirm the change:
+//! ```sh
+//! USAGE:
+//!     krossbar-log-control set-log-level [OPTIONS] --service-name <SERVICE_NAME>
+//!
+//! OPTIONS:
+//!     -d, --directives <DIRECTIVES>        `env_filter` directives, e.g.
+//!                                          `info,my_crate::net=trace,hyper=warn`
+//!         --for <REVERT_AFTER>             Change the level temporarily, e.g. 30s, 15m, 2h. The
+//!                                          logger restores the previous level after the duration.
+//!                                          Temporary levels are kept if the logger restarts
+//!     -h, --help                           Print help information
+//!     -l, --level <LEVEL>                  Log level: OFF, ERROR, WARN, INFO, DEBUG, TRACE. Default
+//!                                          level for the targets, which don't match any of the
+//!                                          directives [default: OFF]
+//!         --reset                          Remove the level set before, and restore the config
+//!                                          file default level, or the level the service started
+//!                                          with
 //!     -s, --service-name <SERVICE_NAME>    Log files location
 //! ```
+//!
+//! Either `--level`, `--directives`, or `--reset` is required. Use directives to debug a particular module
+//! without flooding the log, e.g.:
+//! ```sh
+//! krossbar-log-control set-log-level -s com.example.service -l info -d my_crate::net=trace
+//! ```
+//!
+//! Use `--for` to make sure a verbose level doesn't stay forever. If the service reconnects to the logger
+//! during the change, e.g. because the logger restarted, the temporary level is applied again until it expires:
+//! ```sh
+//! krossbar-log-control set-log-level -s com.example.service -l trace --for 15m
+//! ```
+//!
+//! Levels set without `--for` are stored by the logger and re-applied when the service reconnects.
+//! Use `--reset` to remove the stored level:
+//! ```sh
+//! krossbar-log-control set-log-level -s com.example.service --reset
+//! ```
+//!
+//! Rotate the combined log file, or the service log file if the service name is set, and print the
+//! rotated file path. The logger closes the file before rotating it, so the rotated file is safe to
+//! copy. If compression is enabled, the command waits for the compression to finish and prints the
+//! compressed file path:
+//! ```sh
+//! USAGE:
+//!     krossbar-log-control rotate [OPTIONS]
+//!
+//! OPTIONS:
+//!     -h, --help                           Print help information
+//!     -s, --service-name <SERVICE_NAME>    Service name to rotate per-service log file. Rotates the
+//!                                          combined log file if not set
+//! ```
+//!
+//! Watch log messages as they are written by the logger. No access to the log files is required.
+//! Messages are printed with the default line template:
+//! ```sh
+//! USAGE:
+//!     krossbar-log-control tail [OPTIONS]
+//!
+//! OPTIONS:
+//!     -h, --help                           Print help information
+//!     -l, --level <LEVEL>                  Minimum message level: ERROR, WARN, INFO, DEBUG, TRACE
+//!                                          [default: TRACE]
+//!     -s, --service-name <SERVICE_NAME>    Service name. Shows all services if not set
+//!     -t, --target <TARGET>                Message target prefix, e.g. `my_crate::net`
+//! ```
+//!
+//! The logger drops live messages if the tool can't keep up with the log. Log files are not affected.
+//! The tool shows the messages written into the log files. Messages buffered by a logger trigger flush
+//! policy are shown once they are flushed.
+//!
+//! Search live and rotated log files, including compressed ones. Records are printed from the oldest
+//! to the newest with the default line template. Per-service log file is searched if the service
+//! name is set and the logger writes per-service files:
+//! ```sh
+//! USAGE:
+//!     krossbar-log-control query [OPTIONS]
+//!
+//! OPTIONS:
+//!         --from <FROM>                    Minimal record time, e.g. `2024-01-31 12:00:00`
+//!     -h, --help                           Print help information
+//!     -l, --level <LEVEL>                  Minimum message level: ERROR, WARN, INFO, DEBUG, TRACE
+//!                                          [default: TRACE]
+//!     -n, --limit <LIMIT>                  Max number of records to print [default: 100]
+//!     -p, --pattern <PATTERN>              Message text substring
+//!     -r, --regex                          Treat the pattern as a regular expression
+//!     -s, --service-name <SERVICE_NAME>    Service name. Searches all services if not set
+//!     -t, --target <TARGET>                Message target prefix, e.g. `my_crate::net`
+//!         --to <TO>                        Maximal record time, e.g. `2024-01-31 13:00:00`
+//! ```
+//!
+//! Print recent messages kept in the logger memory (see `krossbar-logger --ring-size`). The buffer
+//! also keeps messages below the logger `--file-level`, which are not written into log files. Let
+//! services log `DEBUG` into memory, and dump it when something goes wrong:
+//! ```sh
+//! USAGE:
+//!     krossbar-log-control dump [OPTIONS]
+//!
+//! OPTIONS:
+//!     -h, --help                           Print help information
+//!     -s, --service-name <SERVICE_NAME>    Service name. Prints all services messages if not set
+//! ```
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 use clap::{self, Parser, Subcommand};
+use futures::StreamExt;
 use log::LevelFilter;
 
 use krossbar_bus_common::DEFAULT_HUB_SOCKET_PATH;
-use krossbar_bus_lib::Service;
+use krossbar_bus_lib::{Client, Service};
 
 use krossbar_log_common::{
+    duration::parse_duration,
+    line_template::LineTemplate,
+    log_message::LogRecord,
     logger_interface::{
-        SetLogLevel, LOGGER_SERVICE_NAME, LOG_CLIENTS_METHOD_NAME, SET_LOG_LEVEL_METHOD_NAME,
+        tail_signal_name, ClientInfo, LogLevel, LogQuery, QueryPage, ServiceLogLevel, SetLogLevel,
+        TailFilter, DUMP_METHOD_NAME, GET_LOG_LEVEL_METHOD_NAME, LOGGER_SERVICE_NAME,
+        LOG_CLIENTS_INFO_METHOD_NAME, MAX_QUERY_PAGE_SIZE, QUERY_METHOD_NAME, ROTATE_METHOD_NAME,
+        SET_LOG_LEVEL_METHOD_NAME, TAIL_METHOD_NAME, TAIL_SUBSCRIPTION_TIMEOUT,
     },
     LOG_CONTROL_SERVICE_NAME,
 };
@@ -60,15 +180,93 @@ use krossbar_log_common::{
 #[derive(Subcommand, Debug, Clone)]
 enum Commands {
     /// List connected services
-    List,
+    List {
+        /// Print clients as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Show service log level
+    GetLogLevel {
+        /// Service name. Shows all connected services if not set
+        #[clap(short, long, value_parser)]
+        service_name: Option<String>,
+    },
     /// Change service log level
     SetLogLevel {
         /// Log files location
         #[clap(short, long, value_parser)]
         service_name: String,
-        /// Log level: OFF, ERROR, WARN, INFO, DEBUG, TRACE
+        /// Log level: OFF, ERROR, WARN, INFO, DEBUG, TRACE. Default level for
+        /// the targets, which don't match any of the directives [default: OFF]
+        #[clap(
+            short,
+            long,
+            value_parser,
+            required_unless_present_any = ["directives", "reset"]
+        )]
+        level: Option<log::LevelFilter>,
+        /// `env_filter` directives, e.g. `info,my_crate::net=trace,hyper=warn`
         #[clap(short, long, value_parser)]
-        level: log::LevelFilter,
+        directives: Option<String>,
+        /// Change the level temporarily, e.g. 30s, 15m, 2h. The logger restores the previous
+        /// level after the duration. Temporary levels are kept if the logger restarts
+        #[clap(long = "for", value_parser = parse_duration)]
+        revert_after: Option<Duration>,
+        /// Remove the level set before, and restore the config file default level,
+        /// or the level the service started with
+        #[clap(long, conflicts_with_all = ["level", "directives", "revert_after"])]
+        reset: bool,
+    },
+    /// Rotate log file
+    Rotate {
+        /// Service name to rotate per-service log file. Rotates the combined log file if not set
+        #[clap(short, long, value_parser)]
+        service_name: Option<String>,
+    },
+    /// Watch log messages live
+    Tail {
+        /// Service name. Shows all services if not set
+        #[clap(short, long, value_parser)]
+        service_name: Option<String>,
+        /// Minimum message level: ERROR, WARN, INFO, DEBUG, TRACE
+        #[clap(short, long, value_parser, default_value_t = LevelFilter::Trace)]
+        level: LevelFilter,
+        /// Message target prefix, e.g. `my_crate::net`
+        #[clap(short, long, value_parser)]
+        target: Option<String>,
+    },
+    /// Search live and rotated log files
+    Query {
+        /// Service name. Searches all services if not set
+        #[clap(short, long, value_parser)]
+        service_name: Option<String>,
+        /// Minimum message level: ERROR, WARN, INFO, DEBUG, TRACE
+        #[clap(short, long, value_parser, default_value_t = LevelFilter::Trace)]
+        level: LevelFilter,
+        /// Message target prefix, e.g. `my_crate::net`
+        #[clap(short, long, value_parser)]
+        target: Option<String>,
+        /// Message text substring
+        #[clap(short, long, value_parser)]
+        pattern: Option<String>,
+        /// Treat the pattern as a regular expression
+        #[clap(short, long)]
+        regex: bool,
+        /// Minimal record time, e.g. `2024-01-31 12:00:00`
+        #[clap(long, value_parser = parse_time)]
+        from: Option<DateTime<Local>>,
+        /// Maximal record time, e.g. `2024-01-31 13:00:00`
+        #[clap(long, value_parser = parse_time)]
+        to: Option<DateTime<Local>>,
+        /// Max number of records to print
+        #[clap(short = 'n', long, default_value_t = 100)]
+        limit: usize,
+    },
+    /// Print recent messages kept in the logger memory
+    Dump {
+        /// Service name. Prints all services messages if not set
+        #[clap(short, long, value_parser)]
+        service_name: Option<String>,
     },
 }
 
@@ -99,28 +297,302 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let client = bus.connect(LOGGER_SERVICE_NAME).await.unwrap();
 
     match args.command {
-        Commands::List => {
-            let clients: Vec<String> = client.get(LOG_CLIENTS_METHOD_NAME).await.unwrap();
-            println!("Logger clients: {clients:?}");
+        Commands::List { json } => {
+            let clients: Vec<ClientInfo> = client.get(LOG_CLIENTS_INFO_METHOD_NAME).await.unwrap();
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&clients)?);
+            } else {
+                print_clients(&clients);
+            }
+        }
+        Commands::GetLogLevel { service_name } => {
+            let result: Result<Vec<ServiceLogLevel>, String> = client
+                .call(GET_LOG_LEVEL_METHOD_NAME, &service_name)
+                .await
+                .unwrap();
+
+            match result {
+                Ok(levels) => {
+                    for ServiceLogLevel {
+                        service_name,
+                        log_level,
+                    } in levels
+                    {
+                        match log_level {
+                            Ok(LogLevel {
+                                level,
+                                directives: Some(directives),
+                            }) => println!("{service_name}: {level} [{directives}]"),
+                            Ok(LogLevel { level, .. }) => println!("{service_name}: {level}"),
+                            Err(e) => println!("{service_name}: unknown ({e})"),
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to get log level: {e}");
+                    std::process::exit(1);
+                }
+            }
         }
         Commands::SetLogLevel {
             service_name,
             level,
+            directives,
+            revert_after,
+            reset,
         } => {
-            client
-                .message(
+            let result: Result<(), String> = client
+                .call(
                     SET_LOG_LEVEL_METHOD_NAME,
                     &SetLogLevel {
                         service_name: service_name.clone(),
-                        level,
+                        level: level.unwrap_or(LevelFilter::Off),
+                        directives: directives.clone(),
+                        revert_after,
+                        reset,
                     },
                 )
                 .await
                 .unwrap();
 
-            println!("Succesfully changed log {service_name} log level to {level}");
+            match result {
+                Ok(_) if reset => println!("Succesfully reset {service_name} log level"),
+                Ok(_) => {
+                    let change = match directives {
+                        Some(directives) => format!("log directives to '{directives}'"),
+                        None => format!("log level to {}", level.unwrap_or(LevelFilter::Off)),
+                    };
+
+                    match revert_after {
+                        Some(revert_after) => println!(
+                            "Succesfully changed {service_name} {change} for {revert_after:?}"
+                        ),
+                        None => println!("Succesfully changed {service_name} {change}"),
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to change {service_name} log level: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Rotate { service_name } => {
+            let result: Result<String, String> = client
+                .call(ROTATE_METHOD_NAME, &service_name)
+                .await
+                .unwrap();
+
+            match result {
+                Ok(rotated_file) => println!("{rotated_file}"),
+                Err(e) => {
+                    eprintln!("Failed to rotate log file: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Tail {
+            service_name,
+            level,
+            target,
+        } => {
+            let filter = TailFilter {
+                service_name,
+                level,
+                target_prefix: target,
+            };
+
+            tail(&mut bus, &client, filter).await;
+        }
+        Commands::Query {
+            service_name,
+            level,
+            target,
+            pattern,
+            regex,
+            from,
+            to,
+            limit,
+        } => {
+            let mut query = LogQuery {
+                from,
+                to,
+                service_name,
+                level,
+                target_prefix: target,
+                pattern,
+                regex,
+                page_size: 0,
+                cursor: None,
+            };
+
+            let line_template = LineTemplate::default();
+            let mut remaining = limit;
+
+            while remaining > 0 {
+                query.page_size = remaining.min(MAX_QUERY_PAGE_SIZE);
+
+                let result: Result<QueryPage, String> =
+                    client.call(QUERY_METHOD_NAME, &query).await.unwrap();
+
+                let page = match result {
+                    Ok(page) => page,
+                    Err(e) => {
+                        eprintln!("Failed to query logs: {e}");
+                        std::process::exit(1);
+                    }
+                };
+
+                remaining -= page.records.len();
+                for record in page.records {
+                    println!(
+                        "{}",
+                        line_template.format(&record.service_name, record.pid, &record.message)
+                    );
+                }
+
+                match page.next {
+                    Some(cursor) => query.cursor = Some(cursor),
+                    None => break,
+                }
+            }
+        }
+        Commands::Dump { service_name } => {
+            let records: Vec<LogRecord> =
+                client.call(DUMP_METHOD_NAME, &service_name).await.unwrap();
+
+            let line_template = LineTemplate::default();
+            for record in records {
+                println!(
+                    "{}",
+                    line_template.format(&record.service_name, record.pid, &record.message)
+                );
+            }
         }
     }
 
     Ok(())
 }
+
+/// Print live log messages until interrupted. Renews the subscription before it expires
+async fn tail(bus: &mut Service, client: &Client, filter: TailFilter) {
+    // The logger registers the subscriber signal on the first call
+    if let Err(e) = client.call::<_, ()>(TAIL_METHOD_NAME, &filter).await {
+        eprintln!("Failed to subscribe to log messages: {e}");
+        std::process::exit(1);
+    }
+
+    let mut events = client
+        .subscribe::<LogRecord>(&tail_signal_name(LOG_CONTROL_SERVICE_NAME))
+        .await
+        .unwrap();
+
+    let line_template = LineTemplate::default();
+    let renew_period = TAIL_SUBSCRIPTION_TIMEOUT / 3;
+    let mut renew =
+        tokio::time::interval_at(tokio::time::Instant::now() + renew_period, renew_period);
+
+    loop {
+        tokio::select! {
+            _ = renew.tick() => {
+                if let Err(e) = client.call::<_, ()>(TAIL_METHOD_NAME, &filter).await {
+                    eprintln!("Failed to subscribe to log messages: {e}");
+                    std::process::exit(1);
+                }
+            }
+            event = events.next() => match event {
+                Some(Ok(event)) => {
+                    println!(
+                        "{}",
+                        line_template.format(&event.service_name, event.pid, &event.message)
+                    );
+                }
+                Some(Err(e)) => eprintln!("Invalid log message: {e}"),
+                None => {
+                    eprintln!("Logger closed the subscription");
+                    std::process::exit(1);
+                }
+            },
+            _ = bus.poll() => {}
+            _ = tokio::signal::ctrl_c() => return,
+        }
+    }
+}
+
+/// Parse local time: `2024-01-31 12:00:00` or `2024-01-31T12:00:00`
+fn parse_time(time: &str) -> Result<DateTime<Local>, String> {
+    let time = time.trim();
+
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(time, format).ok())
+        .and_then(|time| Local.from_local_datetime(&time).earliest())
+        .ok_or_else(|| format!("Invalid time '{time}'. Expected e.g. 2024-01-31 12:00:00"))
+}
+
+fn format_time(time: &DateTime<Local>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Print clients as a table with columns aligned to the widest value
+fn print_clients(clients: &[ClientInfo]) {
+    let header = [
+        "SERVICE",
+        "PID",
+        "UID",
+        "GID",
+        "CONNECTED",
+        "E/W/I/D/T",
+        "TOTAL",
+        "BYTES",
+        "DROPPED",
+        "LAST MESSAGE",
+        "EXE",
+    ]
+    .map(String::from);
+
+    let rows: Vec<[String; 11]> = clients
+        .iter()
+        .map(|client| {
+            let messages = &client.messages;
+
+            [
+                client.service_name.clone(),
+                client.pid.to_string(),
+                client.uid.to_string(),
+                client.gid.to_string(),
+                format_time(&client.connected_at),
+                format!(
+                    "{}/{}/{}/{}/{}",
+                    messages.error, messages.warn, messages.info, messages.debug, messages.trace
+                ),
+                messages.total().to_string(),
+                client.bytes_written.to_string(),
+                client.dropped.to_string(),
+                client
+                    .last_message_at
+                    .as_ref()
+                    .map(format_time)
+                    .unwrap_or_else(|| "-".into()),
+                client.exe.clone().unwrap_or_else(|| "-".into()),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|column| column.len());
+    for row in rows.iter() {
+        for (width, column) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(column.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(column, width)| format!("{column:<width$}"))
+            .collect();
+
+        println!("{}", line.join("  ").trim_end());
+    }
+}
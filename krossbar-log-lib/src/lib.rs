@@ -8,13 +8,40 @@
 //!
 //! The library uses Unix stream connection to send logging messages, which means you need
 //! running [Krossbar logger](https://crates.io/crates/krossbar-logger) to log mesage.
-//! In case service can't connect to the logger, it logs to stdout.
+//! In case service can't connect to the logger, e.g. at boot before the logger starts or
+//! while it restarts, messages are spooled and the library reconnects every second.
+//! Once connected, spooled messages are sent in order with their original timestamps.
+//! Use [Logger::with_spool] to set the spool size or to spool messages into a file,
+//! which keeps them across client restarts.
 //!
 //! Also, you can use [Logger] manually to control whether log into stdout or send
 //! message to the logger. Both option are independent.
 //!
 //! In case you use Krossbar logger, you have to run logging loop using [Logger::run].
 //!
+//! Messages are queued synchronously in the calling thread, so the logger receives
+//! messages in the order they were logged. Each message sent to the logger gets a sequence
+//! number, which increases monotonically for the process lifetime.
+//!
+//! The queue of messages waiting to be sent is bounded. Use [Logger::with_overflow_policy]
+//! to set its capacity and whether to block or drop messages if it is full. Dropped messages
+//! are counted and reported to the logger as a `N messages dropped` warning.
+//! Callers inside a Tokio runtime can't block, so with the blocking policy their messages
+//! are queued over the capacity up to [OVERFLOW_LIMIT_FACTOR] times the capacity, and dropped after that.
+//!
+//! Queued messages are sent to the logger in batches, one frame per batch. By default,
+//! a batch is sent as soon as there're no more queued messages. Use [Logger::with_batching]
+//! to set the max batch size in messages and bytes, and how long a batch waits for more messages.
+//! Older loggers, which don't accept batches, receive messages one by one.
+//!
+//! Key-value pairs attached to a record using `log` crate `kv` API are sent to the logger
+//! as typed structured fields, e.g. `info!(request_id = "42", attempt = 3; "Request failed")`.
+//!
+//! Log level can be set per target using `env_filter` directives, e.g.
+//! `info,my_crate::net=trace,hyper=warn`. See [init_logger_with_directives].
+//! If [LOG_ENV_VAR] environment variable is set, its directives are used instead of the
+//! level or directives provided in code.
+//!
 //! # Examples
 //! ```rust
 //! use std::time::Duration;
@@ -44,13 +71,20 @@
 //! }
 //! ```
 pub mod logger;
+mod queue;
 mod rpc;
+mod spool;
 
 use log::LevelFilter;
 
 use krossbar_log_common::DEFAULT_LOGGER_SOCKET_PATH;
 
 pub use logger::Logger;
+pub use queue::{OverflowPolicy, OVERFLOW_LIMIT_FACTOR};
+
+/// Environment variable with `env_filter` directives, which override the code provided
+/// log level, e.g. `KROSSBAR_LOG=info,my_crate::net=trace,hyper=warn`
+pub const LOG_ENV_VAR: &str = "KROSSBAR_LOG";
 
 /// Init logger.
 /// **service_name** is a client service name. It must be uniques across the system.
@@ -66,3 +100,21 @@ pub async fn init_logger(service_name: &str, level: LevelFilter, log_to_stdout:
     .await
     .unwrap()
 }
+
+/// Init logger, which filters messages using `env_filter` **directives**,
+/// e.g. `info,my_crate::net=trace,hyper=warn`.
+/// See [init_logger] for the rest of the arguments.
+pub async fn init_logger_with_directives(
+    service_name: &str,
+    directives: &str,
+    log_to_stdout: bool,
+) -> Logger {
+    Logger::new_with_directives(
+        service_name,
+        directives,
+        log_to_stdout,
+        Some(DEFAULT_LOGGER_SOCKET_PATH.into()),
+    )
+    .await
+    .unwrap()
+}
@@ -1,47 +1,79 @@
 use std::{
     io::{self, Write},
-    path::PathBuf,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
-    time::{Duration, SystemTime},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
 };
 
-use chrono::Local;
+use bson::Bson;
 use colored::Colorize;
-use futures::{select, FutureExt};
+use env_filter::{Builder, Filter, ParseError};
+use futures::{future, select, FutureExt};
 use log::{warn, Level, LevelFilter, Log, Record};
 use tokio::{
     net::UnixStream,
-    runtime::Handle,
-    sync::mpsc::{channel, Receiver, Sender},
+    time::{self, Instant, MissedTickBehavior},
 };
 
-use krossbar_log_common::{log_message::LogMessage, logger_interface::REGISTER_METHOD_NAME};
-use krossbar_rpc::{Error, Result, RpcData};
+use krossbar_log_common::{
+    log_message::{FieldsDisplay, LogMessage},
+    logger_interface::{
+        LogLevel, RegisterResponse, SetLogLevel, GET_LOG_LEVEL_METHOD_NAME, REGISTER_METHOD_NAME,
+        SET_LOG_LEVEL_METHOD_NAME,
+    },
+};
+use krossbar_rpc::{Error, Result, RpcData, RpcMessage};
 
-use crate::rpc::Rpc;
+use crate::{
+    queue::{LogQueue, OverflowPolicy},
+    rpc::Rpc,
+    spool::Spool,
+    LOG_ENV_VAR,
+};
 
 /// How often the library tries to reconnect to a logger
 const RECONNECT_PERIOD: Duration = Duration::from_millis(1000);
-/// How many message to store in a buffer
+/// Default number of messages queued for sending to the logger
 const LOG_BUFFER_SIZE: usize = 100;
+/// Default number of messages spooled while there's no logger connection
+const SPOOL_SIZE: usize = 1000;
+/// Default max number of messages sent to the logger in a single frame
+const BATCH_SIZE: usize = 100;
+/// Default max number of serialized message bytes sent to the logger in a single frame
+const BATCH_BYTES: usize = 64 * 1024;
 
 /// Logger handle to use for running the logger
 pub struct Logger {
     /// Client service name
     service_name: String,
-    /// Logger RPC handle
+    /// Logger RPC handle. [None] while there's no logger connection
     rpc: Option<Rpc>,
-    /// Last sussessfull logger connection
-    last_connect_ts_ms: SystemTime,
     /// Logger socket path
     logger_socket_path: Option<PathBuf>,
-    /// Receiving part of log messages channel
-    log_receiver: Receiver<LogMessage>,
-    /// Logging level
-    _level: Arc<AtomicUsize>,
+    /// Messages logged while there's no logger connection
+    spool: Spool,
+    /// Log messages queue. Shared with the [LogHandle]
+    queue: Arc<LogQueue>,
+    /// Sequence number of the next message sent to the logger
+    next_seq: u64,
+    /// Messages to send to the logger in a single frame
+    batch: Vec<LogMessage>,
+    /// Max number of messages in a batch
+    max_batch_size: usize,
+    /// Serialized size of the batch messages
+    batch_bytes: usize,
+    /// Max serialized size of the batch messages
+    max_batch_bytes: usize,
+    /// How long a batch waits for more messages before it's sent
+    batch_latency: Duration,
+    /// When to send the current batch
+    batch_deadline: Option<Instant>,
+    /// Log filter. Shared with the [LogHandle]
+    filter: Arc<RwLock<Filter>>,
+    /// Log level the client started with. Restored on a reset request
+    initial_level: LogLevel,
+    /// Log level the filter is built from
+    log_level: LogLevel,
 }
 
 /// Global [Log] handle
@@ -50,10 +82,10 @@ struct LogHandle {
     log_to_stdout: bool,
     /// If send messages to the logger
     log_to_rpc: bool,
-    /// Logging level
-    level: Arc<AtomicUsize>,
-    /// Sending part of the log messages channel
-    log_sender: Sender<LogMessage>,
+    /// Log filter
+    filter: Arc<RwLock<Filter>>,
+    /// Log messages queue
+    queue: Arc<LogQueue>,
 }
 
 impl Logger {
@@ -62,47 +94,175 @@ impl Logger {
     /// **log_to_stdout** sets if logger should log to stdout. If set, library
     /// logs to stdout even if it then sends messages to the logger.
     /// **logger_socket_path** sets logger path. If is some, logging lib tries to connect
-    /// to the logger at the provided path.
+    /// to the logger at the provided path. If the logger is not running yet, messages are
+    /// spooled and sent once [Logger::run] connects to it.
+    ///
+    /// If [LOG_ENV_VAR] environment variable is set, its directives are used instead of the **level**.
     pub async fn new(
         service_name: &str,
         level: LevelFilter,
         log_to_stdout: bool,
         logger_socket_path: Option<PathBuf>,
+    ) -> Result<Logger> {
+        Self::with_filter(
+            service_name,
+            Self::initial_level(level, None),
+            log_to_stdout,
+            logger_socket_path,
+        )
+        .await
+    }
+
+    /// Same as [Logger::new], but filters messages using `env_filter` **directives**,
+    /// e.g. `info,my_crate::net=trace,hyper=warn`. Targets, which don't match any of
+    /// the directives, are not logged.
+    ///
+    /// If [LOG_ENV_VAR] environment variable is set, its directives are used instead of the **directives**.
+    pub async fn new_with_directives(
+        service_name: &str,
+        directives: &str,
+        log_to_stdout: bool,
+        logger_socket_path: Option<PathBuf>,
+    ) -> Result<Logger> {
+        Self::with_filter(
+            service_name,
+            Self::initial_level(LevelFilter::Off, Some(directives)),
+            log_to_stdout,
+            logger_socket_path,
+        )
+        .await
+    }
+
+    /// Initial log level. [LOG_ENV_VAR] directives take precedence over the code provided ones
+    fn initial_level(level: LevelFilter, directives: Option<&str>) -> LogLevel {
+        match std::env::var(LOG_ENV_VAR) {
+            Ok(env_directives) => LogLevel {
+                level: LevelFilter::Off,
+                directives: Some(env_directives),
+            },
+            Err(_) => LogLevel {
+                level,
+                directives: directives.map(String::from),
+            },
+        }
+    }
+
+    /// Build log filter. If not **strict**, invalid directives are reported and skipped
+    fn build_filter(log_level: &LogLevel, strict: bool) -> std::result::Result<Filter, ParseError> {
+        let mut builder = Builder::new();
+        builder.filter_level(log_level.level);
+
+        if let Some(directives) = &log_level.directives {
+            if strict {
+                builder.try_parse(directives)?;
+            } else {
+                builder.parse(directives);
+            }
+        }
+
+        Ok(builder.build())
+    }
+
+    async fn with_filter(
+        service_name: &str,
+        log_level: LogLevel,
+        log_to_stdout: bool,
+        logger_socket_path: Option<PathBuf>,
     ) -> Result<Logger> {
         let log_to_rpc = logger_socket_path.is_some();
 
-        let rpc = if logger_socket_path.is_none() {
-            None
-        } else {
-            Some(Self::connect(&service_name, logger_socket_path.clone().unwrap()).await?)
+        let rpc = match &logger_socket_path {
+            Some(socket_path) => {
+                let rpc = Self::connect(service_name, socket_path.clone()).await.ok();
+
+                if rpc.is_none() {
+                    Self::log_to_stdout(&Self::internal_log_message(
+                        "Logger is not running. Spooling messages until connected",
+                    ));
+                }
+
+                rpc
+            }
+            None => None,
         };
 
-        let (log_sender, log_receiver) = channel(LOG_BUFFER_SIZE);
-        let arc_level = Arc::new(AtomicUsize::new(level as usize));
+        let queue = Arc::new(LogQueue::new(LOG_BUFFER_SIZE));
+        let filter = Self::build_filter(&log_level, false).unwrap();
+        let max_level = filter.filter();
+        let arc_filter = Arc::new(RwLock::new(filter));
 
         let this = Self {
             service_name: service_name.into(),
-            _level: arc_level.clone(),
+            filter: arc_filter.clone(),
+            initial_level: log_level.clone(),
+            log_level,
             rpc,
-            last_connect_ts_ms: SystemTime::now(),
-            logger_socket_path: logger_socket_path,
-            log_receiver,
+            logger_socket_path,
+            spool: Spool::new(SPOOL_SIZE),
+            queue: queue.clone(),
+            next_seq: 0,
+            batch: Vec::new(),
+            max_batch_size: BATCH_SIZE,
+            batch_bytes: 0,
+            max_batch_bytes: BATCH_BYTES,
+            batch_latency: Duration::ZERO,
+            batch_deadline: None,
         };
 
-        let log_handle = Box::new(LogHandle::new(
-            log_to_stdout,
-            log_to_rpc,
-            arc_level,
-            log_sender,
-        ));
+        let log_handle = Box::new(LogHandle::new(log_to_stdout, log_to_rpc, arc_filter, queue));
 
         log::set_boxed_logger(log_handle)
-            .map(|()| log::set_max_level(level))
+            .map(|()| log::set_max_level(max_level))
             .unwrap();
 
         Ok(this)
     }
 
+    /// Set the log queue **capacity** and what to do with new messages if the queue is full.
+    /// By default, the queue holds 100 messages and [OverflowPolicy::Block] is used.
+    ///
+    /// Dropped messages are counted and reported to the logger as a single
+    /// `N messages dropped` warning once the queue has space again.
+    pub fn with_overflow_policy(self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.queue.set_overflow_policy(capacity, policy);
+        self
+    }
+
+    /// Set how many messages are spooled while there's no logger connection.
+    /// By default, 1000 messages are kept in the memory. If **location** is some, messages
+    /// are spooled into a file at the location instead. Messages left in the file, e.g. by
+    /// a previous run, which couldn't connect to the logger, are replayed first.
+    ///
+    /// If the spool is full, new messages are dropped and reported as a
+    /// `N messages dropped` warning after the spooled ones.
+    pub fn with_spool(mut self, capacity: usize, location: Option<&Path>) -> io::Result<Self> {
+        self.spool = match location {
+            Some(location) => Spool::with_file(capacity, location)?,
+            None => Spool::new(capacity),
+        };
+
+        Ok(self)
+    }
+
+    /// Set how messages are batched. Messages are sent to the logger in frames of up to
+    /// **max_messages** messages and up to **max_bytes** serialized message bytes. A message
+    /// larger than **max_bytes** is sent in a frame of its own. A frame waits up to
+    /// **max_latency** for more messages to arrive. By default, up to 100 messages or 64 KiB
+    /// are sent in a frame as soon as there're no more messages in the queue.
+    ///
+    /// Older loggers don't accept batches. Messages are sent to them one by one.
+    pub fn with_batching(
+        mut self,
+        max_messages: usize,
+        max_bytes: usize,
+        max_latency: Duration,
+    ) -> Self {
+        self.max_batch_size = max_messages.max(1);
+        self.max_batch_bytes = max_bytes;
+        self.batch_latency = max_latency;
+        self
+    }
+
     async fn connect(service_name: &str, socket_path: PathBuf) -> Result<Rpc> {
         let socket = UnixStream::connect(socket_path)
             .await
@@ -115,7 +275,9 @@ impl Logger {
 
         match call.data {
             RpcData::Response(res) => {
-                res?;
+                // Older loggers respond with an empty value
+                let response: RegisterResponse = bson::from_bson(res?).unwrap_or_default();
+                rpc.set_log_batch(response.log_batch);
             }
             m => {
                 return Err(Error::InternalError(format!(
@@ -128,30 +290,108 @@ impl Logger {
     }
 
     /// Run logger message sending. Can be ommited if set to log only to stdout.
+    /// While there's no logger connection, reconnects every second and replays
+    /// spooled messages once connected.
     pub async fn run(mut self) {
+        let mut reconnect_timer = time::interval(RECONNECT_PERIOD);
+        reconnect_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
         loop {
+            let reconnect = if self.rpc.is_none() && self.logger_socket_path.is_some() {
+                reconnect_timer.tick().boxed()
+            } else {
+                future::pending().boxed()
+            };
+
+            let flush = match self.batch_deadline {
+                Some(deadline) => time::sleep_until(deadline).boxed(),
+                None => future::pending().boxed(),
+            };
+
+            let incoming = match self.rpc.as_mut() {
+                Some(rpc) => rpc.read_message().boxed(),
+                None => future::pending().boxed(),
+            };
+
             select! {
-                message = self.log_receiver.recv().fuse() => {
-                    if let Some(message) = message {
-                        self.send_rpc_message(&message).await
-                    } else {
-                        eprintln!("Log handle closed");
-                        break;
+                _ = self.queue.wait().fuse() => {
+                    while let Some(message) = self.queue.pop() {
+                        self.batch_message(message).await
                     }
-                }
-                incoming = self.rpc.as_mut().unwrap().read_message().fuse() => {
-                    eprintln!("Incoming command: {incoming:?}");
 
-                    if let Err(e) = incoming {
-                        warn!("No logger connection logger: {e:?}");
+                    let num_dropped = self.queue.take_num_dropped();
+                    if num_dropped > 0 {
+                        self.batch_message(LogMessage::dropped(num_dropped)).await
+                    }
 
-                        tokio::time::sleep(RECONNECT_PERIOD).await;
+                    self.schedule_flush().await
+                }
+                _ = flush.fuse() => self.flush().await,
+                incoming = incoming.fuse() => {
+                    match incoming {
+                        Ok(message) => self.handle_incoming_message(message).await,
+                        Err(_) => self.on_disconnected(),
                     }
                 }
+                _ = reconnect.fuse() => self.reconnect().await
             };
         }
     }
 
+    /// Handle a logger command
+    async fn handle_incoming_message(&mut self, message: RpcMessage) {
+        let (endpoint, body, call_id) = match message.data {
+            RpcData::Message { endpoint, body } => (endpoint, body, None),
+            RpcData::Call { endpoint, params } => (endpoint, params, Some(message.id)),
+            data => {
+                warn!("Unexpected message from the logger: {data:?}");
+                return;
+            }
+        };
+
+        let result = match endpoint.as_str() {
+            SET_LOG_LEVEL_METHOD_NAME => bson::from_bson::<SetLogLevel>(body)
+                .map_err(|e| Error::ParamsTypeError(e.to_string()))
+                .and_then(|message| {
+                    let log_level = if message.reset {
+                        self.initial_level.clone()
+                    } else {
+                        message.log_level()
+                    };
+
+                    self.set_level(log_level)
+                        .map(|_| Bson::Null)
+                        .map_err(|e| Error::ParamsTypeError(e.to_string()))
+                }),
+            GET_LOG_LEVEL_METHOD_NAME => {
+                bson::to_bson(&self.log_level).map_err(|e| Error::ResultTypeError(e.to_string()))
+            }
+            _ => Err(Error::NoEndpoint),
+        };
+
+        if let Err(e) = &result {
+            warn!("Failed to handle logger '{endpoint}' command: {e}");
+        }
+
+        // Messages don't expect a response
+        if let Some(id) = call_id {
+            if let Err(e) = self.rpc.as_mut().unwrap().respond(id, result).await {
+                warn!("Failed to respond to the logger: {e}");
+            }
+        }
+    }
+
+    /// Replace log filter
+    fn set_level(&mut self, log_level: LogLevel) -> std::result::Result<(), ParseError> {
+        let filter = Self::build_filter(&log_level, true)?;
+
+        log::set_max_level(filter.filter());
+        *self.filter.write().unwrap() = filter;
+        self.log_level = log_level;
+
+        Ok(())
+    }
+
     fn log_to_stdout(message: &LogMessage) {
         let colored_level = match message.level {
             Level::Error => "ERROR".bright_red(),
@@ -161,62 +401,179 @@ impl Logger {
             Level::Trace => "TRACE".bright_white(),
         };
 
-        println!(
-            "{}: {} > {}",
-            colored_level,
-            message.target.bright_white(),
-            message.message
-        );
+        if message.fields.is_empty() {
+            println!(
+                "{}: {} > {}",
+                colored_level,
+                message.target.bright_white(),
+                message.message
+            );
+        } else {
+            println!(
+                "{}: {} > {} {}",
+                colored_level,
+                message.target.bright_white(),
+                message.message,
+                FieldsDisplay(&message.fields).to_string().bright_black()
+            );
+        }
     }
 
-    async fn send_rpc_message(&mut self, log_message: &LogMessage) {
-        let internal_log_message = |message: String| -> LogMessage {
-            LogMessage {
-                timestamp: Local::now(),
-                level: Level::Info,
-                target: "logger".to_owned(),
-                message: message,
+    /// Assign the next sequence number to the **message** and add it to the batch.
+    /// The batch is sent first if the message doesn't fit into it
+    async fn batch_message(&mut self, mut message: LogMessage) {
+        message.seq = Some(self.next_seq);
+        self.next_seq += 1;
+
+        let num_bytes = Self::message_size(&message);
+        if !self.batch.is_empty() && self.batch_bytes + num_bytes > self.max_batch_bytes {
+            self.flush().await
+        }
+
+        self.batch.push(message);
+        self.batch_bytes += num_bytes;
+
+        if self.batch.len() >= self.max_batch_size || self.batch_bytes >= self.max_batch_bytes {
+            self.flush().await
+        }
+    }
+
+    /// Serialized **message** size
+    fn message_size(message: &LogMessage) -> usize {
+        bson::to_vec(message).map(|data| data.len()).unwrap_or(0)
+    }
+
+    /// Leading **messages**, which fit into the batch byte limit. Keeps at least one message
+    fn fit_batch_bytes(&self, mut messages: Vec<LogMessage>) -> Vec<LogMessage> {
+        let mut num_bytes = 0;
+        let num_messages = messages
+            .iter()
+            .take_while(|message| {
+                num_bytes += Self::message_size(message);
+                num_bytes <= self.max_batch_bytes
+            })
+            .count();
+
+        messages.truncate(num_messages.max(1));
+        messages
+    }
+
+    /// Send the batch right away, or wait for more messages if there's a latency threshold
+    async fn schedule_flush(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+
+        if self.batch_latency.is_zero() {
+            self.flush().await
+        } else if self.batch_deadline.is_none() {
+            self.batch_deadline = Some(Instant::now() + self.batch_latency)
+        }
+    }
+
+    fn internal_log_message(message: &str) -> LogMessage {
+        LogMessage::new(Level::Info, "logger".to_owned(), message.to_owned())
+    }
+
+    /// Send the batch to the logger. If there's no logger connection, or there are
+    /// messages to replay, the batch is spooled
+    async fn flush(&mut self) {
+        self.batch_deadline = None;
+
+        if self.batch.is_empty() {
+            return;
+        }
+
+        let batch = std::mem::take(&mut self.batch);
+        self.batch_bytes = 0;
+
+        if self.spool.is_empty() {
+            if let Some(rpc) = self.rpc.as_mut() {
+                if rpc.send_log_batch(&batch).await.is_ok() {
+                    return;
+                }
+
+                self.on_disconnected()
             }
-        };
+        }
 
-        let rpc = self.rpc.as_mut().unwrap();
-
-        // Failed to send message to logger. Check if we already want to reconnect
-        if rpc.send_log(&log_message).await.is_err() {
-            // We want to reconnect
-            if (SystemTime::now() - RECONNECT_PERIOD) > self.last_connect_ts_ms {
-                Self::log_to_stdout(&internal_log_message(
-                    "Logger is down. Trying to reconnect".into(),
-                ));
-
-                // Update last reconnect time, so we don't retry too often
-                self.last_connect_ts_ms = SystemTime::now();
-
-                // Succesfully reconnected
-                if let Ok(new_rpc) =
-                    Self::connect(&self.service_name, self.logger_socket_path.clone().unwrap())
-                        .await
-                {
-                    Self::log_to_stdout(&internal_log_message(
-                        "Succesfully reconnected to a loger. Sending source message".into(),
-                    ));
+        for message in batch {
+            if let Err(e) = self.spool.push(message) {
+                Self::log_to_stdout(&Self::internal_log_message(&format!(
+                    "Failed to spool log message: {e}"
+                )))
+            }
+        }
+    }
 
-                    rpc.replace_stream(new_rpc);
+    fn on_disconnected(&mut self) {
+        Self::log_to_stdout(&Self::internal_log_message(
+            "Logger is down. Spooling messages until reconnected",
+        ));
 
-                    let _ = rpc.send_log(&log_message).await;
-                // Failed to reconnect
-                } else {
-                    Self::log_to_stdout(&internal_log_message(
-                        "Failed to reconnect to a logger".into(),
-                    ));
+        self.rpc = None;
+    }
+
+    async fn reconnect(&mut self) {
+        let socket_path = self.logger_socket_path.clone().unwrap();
+
+        if let Ok(rpc) = Self::connect(&self.service_name, socket_path).await {
+            Self::log_to_stdout(&Self::internal_log_message(
+                "Succesfully connected to a logger. Replaying spooled messages",
+            ));
+
+            self.rpc = Some(rpc);
+            self.replay().await
+        }
+    }
 
-                    Self::log_to_stdout(&log_message)
+    /// Send spooled messages in the order they were logged. Messages keep their
+    /// original timestamps and sequence numbers
+    async fn replay(&mut self) {
+        loop {
+            let messages = match self.spool.front(self.max_batch_size) {
+                Ok(messages) if messages.is_empty() => break,
+                Ok(messages) => self.fit_batch_bytes(messages),
+                Err(e) => {
+                    Self::log_to_stdout(&Self::internal_log_message(&format!(
+                        "Failed to read spooled messages: {e}"
+                    )));
+
+                    self.spool.discard();
+                    break;
                 }
-            // It's not time to reconnect. Log into stdout
-            } else {
-                Self::log_to_stdout(&log_message)
+            };
+
+            if self
+                .rpc
+                .as_mut()
+                .unwrap()
+                .send_log_batch(&messages)
+                .await
+                .is_err()
+            {
+                self.on_disconnected();
+                return;
+            }
+
+            if self.spool.pop(messages.len()).is_err() {
+                self.spool.discard();
             }
         }
+
+        let num_dropped = self.spool.take_num_dropped();
+        if num_dropped > 0 {
+            self.batch_message(LogMessage::dropped(num_dropped)).await
+        }
+
+        self.schedule_flush().await
+    }
+}
+
+impl Drop for Logger {
+    fn drop(&mut self) {
+        // Nobody sends queued messages anymore. Don't block callers
+        self.queue.close()
     }
 }
 
@@ -224,13 +581,13 @@ impl LogHandle {
     pub fn new(
         log_to_stdout: bool,
         log_to_rpc: bool,
-        level: Arc<AtomicUsize>,
-        log_sender: Sender<LogMessage>,
+        filter: Arc<RwLock<Filter>>,
+        queue: Arc<LogQueue>,
     ) -> Self {
         Self {
             log_to_stdout,
-            level,
-            log_sender,
+            filter,
+            queue,
             log_to_rpc,
         }
     }
@@ -238,37 +595,20 @@ impl LogHandle {
 
 impl Log for LogHandle {
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        metadata.level() as usize <= self.level.load(Ordering::Relaxed)
+        self.filter.read().unwrap().enabled(metadata)
     }
 
     fn log(&self, record: &Record) {
-        if self.enabled(record.metadata()) {
-            let log_message = LogMessage {
-                timestamp: Local::now(),
-                level: record.level(),
-                target: record.metadata().target().to_owned(),
-                message: format!("{}", record.args()),
-            };
+        if self.filter.read().unwrap().matches(record) {
+            let log_message = LogMessage::from_record(record);
 
             if self.log_to_stdout {
                 Logger::log_to_stdout(&log_message)
             }
 
+            // Queue the message right away to keep the order of the messages
             if self.log_to_rpc {
-                // If we're inside Tokio runtime, we spawn a task. Otherwise we'll block to send
-                if let Ok(handle) = Handle::try_current() {
-                    let sender = self.log_sender.clone();
-
-                    handle.spawn(async move {
-                        if sender.send(log_message).await.is_err() {
-                            eprintln!("Failed to send log message into channel");
-                        }
-                    });
-                } else {
-                    if self.log_sender.blocking_send(log_message).is_err() {
-                        eprintln!("Failed to send log message into channel");
-                    }
-                }
+                self.queue.push(log_message)
             }
         }
     }
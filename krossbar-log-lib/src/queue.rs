@@ -0,0 +1,176 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        RwLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use crossbeam_queue::SegQueue;
+use log::Level;
+use tokio::{runtime::Handle, sync::Notify};
+
+use krossbar_log_common::log_message::LogMessage;
+
+/// How many times a blocked producer yields before it starts sleeping between attempts
+/// to push into a full queue
+const FULL_QUEUE_NUM_YIELDS: usize = 100;
+/// How long a blocked producer sleeps before retrying to push into a full queue
+const FULL_QUEUE_BACKOFF: Duration = Duration::from_micros(100);
+/// Messages, which can't wait for space, are queued over the capacity up to this
+/// many times the capacity. Messages over the limit are dropped
+pub const OVERFLOW_LIMIT_FACTOR: usize = 100;
+
+/// What to do with a new message if the log queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the logger sends queued messages. Callers inside a Tokio runtime
+    /// can't block the runtime, so their messages are queued over the capacity up to
+    /// [OVERFLOW_LIMIT_FACTOR] times the capacity, and dropped after that
+    Block,
+    /// Drop the new message
+    DropNewest,
+    /// Drop the oldest queued message to make space for the new one
+    DropOldest,
+    /// Drop new messages below the level, e.g. `DropBelow(Level::Warn)` drops info,
+    /// debug, and trace messages. Messages at the level or above are queued over the capacity
+    /// up to [OVERFLOW_LIMIT_FACTOR] times the capacity, and dropped after that
+    DropBelow(Level),
+}
+
+/// Lock-free queue of log messages. Producers are the `log` macros callers,
+/// the consumer is [crate::Logger::run]. Producers also pop the oldest messages
+/// with [OverflowPolicy::DropOldest]. Messages are pushed synchronously,
+/// so messages from one thread are received in the order they were logged
+pub struct LogQueue {
+    messages: SegQueue<LogMessage>,
+    /// Number of queued messages
+    len: AtomicUsize,
+    capacity: AtomicUsize,
+    /// Read only if the queue is full
+    policy: RwLock<OverflowPolicy>,
+    /// Dropped messages, which are not reported to the logger yet
+    num_dropped: AtomicU64,
+    /// Wakes up the consumer
+    notify: Notify,
+    /// Set when the consumer is gone, so producers don't wait for space
+    closed: AtomicBool,
+}
+
+impl LogQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            messages: SegQueue::new(),
+            len: AtomicUsize::new(0),
+            capacity: AtomicUsize::new(capacity),
+            policy: RwLock::new(OverflowPolicy::Block),
+            num_dropped: AtomicU64::new(0),
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set_overflow_policy(&self, capacity: usize, policy: OverflowPolicy) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        *self.policy.write().unwrap() = policy;
+    }
+
+    pub fn push(&self, message: LogMessage) {
+        if self.over_limit() {
+            return self.drop_message();
+        }
+
+        if self.is_full() {
+            let policy = *self.policy.read().unwrap();
+
+            match policy {
+                OverflowPolicy::Block => {
+                    if Handle::try_current().is_err() && !self.wait_space() {
+                        return self.drop_message();
+                    }
+                }
+                OverflowPolicy::DropNewest => return self.drop_message(),
+                OverflowPolicy::DropOldest => {
+                    if self.pop().is_some() {
+                        self.drop_message()
+                    }
+                }
+                OverflowPolicy::DropBelow(level) => {
+                    if message.level > level {
+                        return self.drop_message();
+                    }
+                }
+            }
+        }
+
+        self.len.fetch_add(1, Ordering::AcqRel);
+        self.messages.push(message);
+        self.notify.notify_one();
+    }
+
+    fn is_full(&self) -> bool {
+        self.len.load(Ordering::Acquire) >= self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// If the queue reached the limit for the messages queued over the capacity
+    fn over_limit(&self) -> bool {
+        let limit = self
+            .capacity
+            .load(Ordering::Relaxed)
+            .saturating_mul(OVERFLOW_LIMIT_FACTOR);
+
+        self.len.load(Ordering::Acquire) >= limit
+    }
+
+    fn drop_message(&self) {
+        self.num_dropped.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Wait for the consumer to make space. Returns `false` if the queue is closed
+    fn wait_space(&self) -> bool {
+        let mut num_attempts = 0;
+
+        while self.is_full() {
+            if self.closed.load(Ordering::Acquire) {
+                return false;
+            }
+
+            if num_attempts < FULL_QUEUE_NUM_YIELDS {
+                num_attempts += 1;
+                thread::yield_now()
+            } else {
+                thread::sleep(FULL_QUEUE_BACKOFF)
+            }
+        }
+
+        true
+    }
+
+    pub fn pop(&self) -> Option<LogMessage> {
+        let message = self.messages.pop();
+
+        if message.is_some() {
+            self.len.fetch_sub(1, Ordering::AcqRel);
+        }
+
+        message
+    }
+
+    /// Number of dropped messages since the last call
+    pub fn take_num_dropped(&self) -> u64 {
+        self.num_dropped.swap(0, Ordering::AcqRel)
+    }
+
+    /// Wait until there are messages in the queue
+    pub async fn wait(&self) {
+        if self.messages.is_empty() {
+            self.notify.notified().await
+        }
+    }
+
+    /// Stop producers waiting for space
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release)
+    }
+}
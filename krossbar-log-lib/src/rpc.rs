@@ -9,29 +9,56 @@ use tokio::{
 
 use krossbar_rpc::{Error, Result, RpcData, RpcMessage};
 
-use krossbar_log_common::{log_message::LogMessage, logger_interface::LOG_METHOD_NAME};
+use krossbar_log_common::{
+    log_message::LogMessage,
+    logger_interface::{LOG_BATCH_METHOD_NAME, LOG_METHOD_NAME},
+};
 
 pub struct Rpc {
     stream: UnixStream,
+    /// Incoming data, which doesn't make a full message yet
+    read_buffer: Vec<u8>,
+    /// If the logger accepts batches. Older loggers accept messages one by one
+    log_batch: bool,
 }
 
 impl Rpc {
     pub fn new(stream: UnixStream) -> Self {
-        Self { stream }
+        Self {
+            stream,
+            read_buffer: Vec::new(),
+            log_batch: false,
+        }
+    }
+
+    /// Set if the logger accepts batches as reported on registration
+    pub fn set_log_batch(&mut self, log_batch: bool) {
+        self.log_batch = log_batch;
     }
 
-    pub fn replace_stream(&mut self, rpc: Rpc) {
-        self.stream = rpc.stream
+    /// Send **messages** in a single frame. If the logger doesn't accept batches,
+    /// messages are sent one by one
+    pub async fn send_log_batch(&mut self, messages: &[LogMessage]) -> Result<()> {
+        if self.log_batch {
+            return self.send_message(LOG_BATCH_METHOD_NAME, messages).await;
+        }
+
+        for message in messages {
+            self.send_message(LOG_METHOD_NAME, message).await?;
+        }
+
+        Ok(())
     }
 
-    pub async fn send_log(&mut self, message: &LogMessage) -> Result<()> {
-        let data = bson::to_bson(message).map_err(|e| Error::ParamsTypeError(e.to_string()))?;
+    /// Send one-way message, which doesn't expect a response
+    async fn send_message<T: Serialize>(&mut self, endpoint: &str, data: T) -> Result<()> {
+        let body = bson::to_bson(&data).map_err(|e| Error::ParamsTypeError(e.to_string()))?;
 
         let message = RpcMessage {
             id: -1,
             data: RpcData::Message {
-                endpoint: LOG_METHOD_NAME.to_owned(),
-                body: data,
+                endpoint: endpoint.into(),
+                body,
             },
         };
 
@@ -72,29 +99,77 @@ impl Rpc {
         self.read_message().await
     }
 
-    pub async fn read_message(&mut self) -> Result<RpcMessage> {
-        // Read BSON len
-        let mut len_buf = [0u8; 4];
+    pub async fn respond<T: Serialize>(&mut self, id: i64, data: Result<T>) -> Result<()> {
+        let data = data.and_then(|value| {
+            bson::to_bson(&value).map_err(|e| Error::ResultTypeError(e.to_string()))
+        });
+
+        let message = RpcMessage {
+            id,
+            data: RpcData::Response(data),
+        };
+
+        let doc = bson::to_document(&message).map_err(|e| Error::InternalError(e.to_string()))?;
+
+        let mut buffer: Vec<u8> = Vec::new();
+        doc.to_writer(&mut buffer)
+            .map_err(|e| Error::InternalError(e.to_string()))?;
 
         self.stream
-            .read_exact(&mut len_buf)
+            .write_all(&buffer)
             .await
-            .map_err(|_| Error::PeerDisconnected)?;
+            .map_err(|_| Error::PeerDisconnected)
+    }
+
+    /// Read next incoming message.
+    /// Cancel safe: partially read message is kept in the buffer until the next call
+    pub async fn read_message(&mut self) -> Result<RpcMessage> {
+        loop {
+            if let Some(message) = self.take_buffered_message()? {
+                return Ok(message);
+            }
+
+            let num_read = self
+                .stream
+                .read_buf(&mut self.read_buffer)
+                .await
+                .map_err(|_| Error::PeerDisconnected)?;
+
+            if num_read == 0 {
+                return Err(Error::PeerDisconnected);
+            }
+        }
+    }
+
+    /// Take a message from the read buffer if it's already fully read
+    fn take_buffered_message(&mut self) -> Result<Option<RpcMessage>> {
+        // Read BSON len
+        let len_buf: [u8; 4] = match self.read_buffer.get(..4) {
+            Some(len_buf) => len_buf.try_into().unwrap(),
+            None => return Ok(None),
+        };
 
         let len = i32::from_le_bytes(len_buf);
+        if len < len_buf.len() as i32 {
+            self.read_buffer.clear();
+            return Err(Error::InternalError(format!("Invalid message len: {len}")));
+        }
 
-        // Read BSON body. Prepend BSON len to the rest of the data
-        let mut data: Vec<u8> = len_buf.into();
-        (&mut self.stream)
-            .take((len - 4) as u64)
-            .read_to_end(&mut data)
-            .await
-            .map_err(|_| Error::PeerDisconnected)?;
+        let len = len as usize;
+
+        if self.read_buffer.len() < len {
+            return Ok(None);
+        }
+
+        // BSON len is a part of the document
+        let data: Vec<u8> = self.read_buffer.drain(..len).collect();
 
         let mut cursor = Cursor::new(data);
         let doc =
             Document::from_reader(&mut cursor).map_err(|e| Error::InternalError(e.to_string()))?;
 
-        Ok(bson::from_document(doc).map_err(|e| Error::InternalError(e.to_string()))?)
+        Ok(Some(
+            bson::from_document(doc).map_err(|e| Error::InternalError(e.to_string()))?,
+        ))
     }
 }
@@ -0,0 +1,316 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use bson::Document;
+
+use krossbar_log_common::log_message::LogMessage;
+
+/// Messages, which are logged while there's no logger connection.
+/// Replayed in order once the library reconnects to the logger.
+///
+/// The spool is bounded. If it's full, new messages are dropped, so the earliest messages,
+/// e.g. logged at boot before the logger started, are kept
+pub struct Spool {
+    capacity: usize,
+    storage: Storage,
+    /// Number of spooled messages
+    len: usize,
+    /// Dropped messages, which are not reported to the logger yet
+    num_dropped: u64,
+}
+
+enum Storage {
+    Memory(VecDeque<LogMessage>),
+    File(SpoolFile),
+}
+
+/// Spool file header size. The header is the offset of the next message to replay,
+/// so messages replayed before a restart are not replayed again
+const HEADER_SIZE: u64 = 8;
+/// Replayed messages are removed from the spool file once they take this many bytes
+/// and at least a half of the file
+const COMPACT_MIN_BYTES: u64 = 64 * 1024;
+/// Min BSON document size
+const MIN_DOCUMENT_SIZE: u64 = 5;
+
+/// Spool file: a header followed by BSON documents one after another.
+/// Empty spool file has no header
+struct SpoolFile {
+    location: PathBuf,
+    file: File,
+    /// Offset of the next message to replay
+    read_offset: u64,
+    /// Number and size of the next messages to replay, if they're already read
+    front_size: Option<(usize, u64)>,
+}
+
+impl Spool {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            storage: Storage::Memory(VecDeque::new()),
+            len: 0,
+            num_dropped: 0,
+        }
+    }
+
+    /// Spool messages into a file at **location** instead of the memory. Messages already
+    /// in the file, e.g. spooled before a restart, are replayed first
+    pub fn with_file(capacity: usize, location: &Path) -> io::Result<Self> {
+        let (spool_file, len) = SpoolFile::open(location)?;
+
+        Ok(Self {
+            capacity,
+            storage: Storage::File(spool_file),
+            len,
+            num_dropped: 0,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, message: LogMessage) -> io::Result<()> {
+        if self.len >= self.capacity {
+            self.num_dropped += 1;
+            return Ok(());
+        }
+
+        match &mut self.storage {
+            Storage::Memory(messages) => messages.push_back(message),
+            Storage::File(spool_file) => {
+                if let Err(e) = spool_file.push(&message) {
+                    self.num_dropped += 1;
+                    return Err(e);
+                }
+            }
+        }
+
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Up to **max_num** oldest spooled messages. Messages stay in the spool until [Spool::pop]
+    pub fn front(&mut self, max_num: usize) -> io::Result<Vec<LogMessage>> {
+        let num = max_num.min(self.len);
+
+        match &mut self.storage {
+            Storage::Memory(messages) => Ok(messages.iter().take(num).cloned().collect()),
+            Storage::File(spool_file) => spool_file.front(num),
+        }
+    }
+
+    /// Remove **num** oldest spooled messages
+    pub fn pop(&mut self, num: usize) -> io::Result<()> {
+        let num = num.min(self.len);
+
+        match &mut self.storage {
+            Storage::Memory(messages) => {
+                messages.drain(..num);
+            }
+            Storage::File(spool_file) => spool_file.pop(num, num == self.len)?,
+        }
+
+        self.len -= num;
+        Ok(())
+    }
+
+    /// Drop all spooled messages, e.g. if the spool file can't be read
+    pub fn discard(&mut self) {
+        match &mut self.storage {
+            Storage::Memory(messages) => messages.clear(),
+            Storage::File(spool_file) => {
+                let _ = spool_file.pop(self.len, true);
+            }
+        }
+
+        self.num_dropped += self.len as u64;
+        self.len = 0;
+    }
+
+    /// Number of dropped messages since the last call
+    pub fn take_num_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.num_dropped)
+    }
+}
+
+impl SpoolFile {
+    /// Open spool file. Returns the file and the number of messages to replay.
+    /// Invalid file is cleared
+    fn open(location: &Path) -> io::Result<(Self, usize)> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(location)?;
+
+        let mut spool_file = Self {
+            location: location.into(),
+            file,
+            read_offset: HEADER_SIZE,
+            front_size: None,
+        };
+
+        match spool_file.load() {
+            Ok(len) => Ok((spool_file, len)),
+            Err(e) => {
+                eprintln!("Invalid log spool file {location:?}: {e}. Clearing");
+
+                spool_file.clear()?;
+                Ok((spool_file, 0))
+            }
+        }
+    }
+
+    /// Read the header and count messages to replay. Partially written message at the end
+    /// of the file, e.g. after a power loss, is removed
+    fn load(&mut self) -> io::Result<usize> {
+        let file_len = self.file.metadata()?.len();
+        if file_len == 0 {
+            return Ok(0);
+        }
+
+        let mut header = [0u8; HEADER_SIZE as usize];
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_exact(&mut header)?;
+
+        self.read_offset = u64::from_le_bytes(header);
+        if self.read_offset < HEADER_SIZE || self.read_offset > file_len {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "replay offset is out of the file",
+            ));
+        }
+
+        let mut offset = self.read_offset;
+        let mut len = 0;
+
+        while offset < file_len {
+            let mut len_buf = [0u8; 4];
+            self.file.seek(SeekFrom::Start(offset))?;
+
+            match self.file.read_exact(&mut len_buf) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+
+            let size = u32::from_le_bytes(len_buf) as u64;
+            if size < MIN_DOCUMENT_SIZE {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "invalid message size",
+                ));
+            }
+
+            if offset + size > file_len {
+                break;
+            }
+
+            offset += size;
+            len += 1;
+        }
+
+        self.file.set_len(offset)?;
+        Ok(len)
+    }
+
+    /// Remove all messages
+    fn clear(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.read_offset = HEADER_SIZE;
+        self.front_size = None;
+
+        Ok(())
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&self.read_offset.to_le_bytes())
+    }
+
+    fn push(&mut self, message: &LogMessage) -> io::Result<()> {
+        let doc = bson::to_document(message).map_err(io::Error::other)?;
+
+        if self.file.seek(SeekFrom::End(0))? == 0 {
+            self.write_header()?;
+        }
+
+        doc.to_writer(&mut self.file).map_err(io::Error::other)
+    }
+
+    fn front(&mut self, num: usize) -> io::Result<Vec<LogMessage>> {
+        self.file.seek(SeekFrom::Start(self.read_offset))?;
+
+        let mut messages = Vec::with_capacity(num);
+        let mut size = 0;
+
+        for _ in 0..num {
+            // BSON len is a part of the document
+            let mut len_buf = [0u8; 4];
+            self.file.read_exact(&mut len_buf)?;
+            let len = u32::from_le_bytes(len_buf) as usize;
+
+            let mut data = len_buf.to_vec();
+            data.resize(len.max(len_buf.len()), 0);
+            self.file.read_exact(&mut data[len_buf.len()..])?;
+
+            let doc = Document::from_reader(&mut data.as_slice()).map_err(io::Error::other)?;
+            messages.push(bson::from_document(doc).map_err(io::Error::other)?);
+            size += data.len() as u64;
+        }
+
+        self.front_size = Some((num, size));
+        Ok(messages)
+    }
+
+    /// Skip **num** next messages. If they're the **last** ones, the file is truncated
+    fn pop(&mut self, num: usize, last: bool) -> io::Result<()> {
+        if last {
+            return self.clear();
+        }
+
+        if self.front_size.map(|(front_num, _)| front_num) != Some(num) {
+            self.front(num)?;
+        }
+
+        self.read_offset += self.front_size.take().unwrap().1;
+
+        let replayed_size = self.read_offset - HEADER_SIZE;
+        if replayed_size >= COMPACT_MIN_BYTES && replayed_size * 2 >= self.file.metadata()?.len() {
+            self.compact()
+        } else {
+            self.write_header()
+        }
+    }
+
+    /// Remove replayed messages from the file. Messages to replay are copied into a new file,
+    /// which replaces the current one, so the spool stays valid if interrupted
+    fn compact(&mut self) -> io::Result<()> {
+        let mut temp_location = self.location.clone().into_os_string();
+        temp_location.push(".tmp");
+
+        let mut compacted = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&temp_location)?;
+
+        compacted.write_all(&HEADER_SIZE.to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(self.read_offset))?;
+        io::copy(&mut self.file, &mut compacted)?;
+
+        fs::rename(&temp_location, &self.location)?;
+
+        self.file = compacted;
+        self.read_offset = HEADER_SIZE;
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+// Each test binary uses only a part of the fake logger
+#![allow(dead_code)]
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use tokio::{
+    net::UnixListener,
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        Notify,
+    },
+};
+
+use krossbar_log_common::{
+    log_message::LogMessage,
+    logger_interface::{decode_log_messages, RegisterResponse, REGISTER_METHOD_NAME},
+};
+use krossbar_rpc::{request::Body, rpc::Rpc};
+
+/// Messages received in a single client request
+pub struct Frame {
+    pub endpoint: String,
+    pub messages: Vec<LogMessage>,
+}
+
+pub async fn receive<T>(receiver: &mut UnboundedReceiver<T>) -> T {
+    tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+        .await
+        .expect("Timeout waiting for log messages")
+        .unwrap()
+}
+
+/// Fake logger, which forwards received messages into the **sender**.
+/// Accepts message batches if **log_batch** is set. Drops client connection on **disconnect**
+pub fn start_logger(
+    socket_path: &PathBuf,
+    log_batch: bool,
+    sender: UnboundedSender<LogMessage>,
+    disconnect: Arc<Notify>,
+) {
+    serve(
+        socket_path,
+        log_batch,
+        move |frame| {
+            for message in frame.messages {
+                let _ = sender.send(message);
+            }
+        },
+        disconnect,
+    );
+}
+
+/// Fake logger, which forwards each received request messages into the **sender**.
+/// Accepts message batches if **log_batch** is set
+pub fn start_frame_logger(socket_path: &PathBuf, log_batch: bool, sender: UnboundedSender<Frame>) {
+    serve(
+        socket_path,
+        log_batch,
+        move |frame| {
+            let _ = sender.send(frame);
+        },
+        Arc::new(Notify::new()),
+    );
+}
+
+fn serve(
+    socket_path: &PathBuf,
+    log_batch: bool,
+    mut on_frame: impl FnMut(Frame) + Send + 'static,
+    disconnect: Arc<Notify>,
+) {
+    let listener = UnixListener::bind(socket_path).unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut rpc = Rpc::new(stream, "krossbar.logger");
+
+            let request = rpc.poll().await.unwrap();
+            assert_eq!(request.endpoint(), REGISTER_METHOD_NAME);
+            // Older loggers respond with an empty body
+            if log_batch {
+                request
+                    .respond(Ok(RegisterResponse { log_batch: true }))
+                    .await;
+            } else {
+                request.respond(Ok(())).await;
+            }
+
+            loop {
+                tokio::select! {
+                    request = rpc.poll() => {
+                        let Some(mut request) = request else { break };
+                        let endpoint = request.endpoint().to_owned();
+
+                        if let Some(Body::Message(body)) = request.take_body() {
+                            let messages = decode_log_messages(&endpoint, body).unwrap();
+                            on_frame(Frame { endpoint, messages });
+                        }
+                    }
+                    _ = disconnect.notified() => break
+                }
+            }
+        }
+    });
+}
@@ -47,6 +47,7 @@ impl Fixture {
                 .into_string()
                 .unwrap(),
             log_level: LevelFilter::Trace,
+            ..Default::default()
         };
 
         let token = self.cancel_token.clone();
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+use log::*;
+use tempdir::TempDir;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use krossbar_log_common::logger_interface::LOG_BATCH_METHOD_NAME;
+use krossbar_log_lib::Logger as ClientLogger;
+
+mod fake_logger;
+use fake_logger::{start_frame_logger, Frame};
+
+const BATCH_SIZE: usize = 10;
+const BATCH_BYTES: usize = 4096;
+const BATCH_LATENCY: Duration = Duration::from_millis(200);
+
+async fn receive(receiver: &mut UnboundedReceiver<Frame>) -> Vec<String> {
+    let frame = fake_logger::receive(receiver).await;
+    assert_eq!(frame.endpoint, LOG_BATCH_METHOD_NAME);
+
+    frame
+        .messages
+        .into_iter()
+        .map(|message| message.message)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_batching() {
+    let socket_dir = TempDir::new("logger_socket_dir").expect("Failed to create socket tempdir");
+    let socket_path = socket_dir.path().join("krossbar_logger.socket");
+
+    let (frame_sender, mut frame_receiver) = unbounded_channel::<Frame>();
+    start_frame_logger(&socket_path, true, frame_sender);
+
+    let logger = ClientLogger::new(
+        "test.batching.service",
+        LevelFilter::Info,
+        false,
+        Some(socket_path),
+    )
+    .await
+    .unwrap()
+    .with_batching(BATCH_SIZE, BATCH_BYTES, BATCH_LATENCY);
+
+    for i in 0..25 {
+        info!("{i}");
+    }
+
+    let start = Instant::now();
+    tokio::spawn(logger.run());
+
+    // Full batches are sent right away
+    let expected =
+        |range: std::ops::Range<usize>| -> Vec<String> { range.map(|i| i.to_string()).collect() };
+
+    assert_eq!(receive(&mut frame_receiver).await, expected(0..10));
+    assert_eq!(receive(&mut frame_receiver).await, expected(10..20));
+    assert!(start.elapsed() < BATCH_LATENCY);
+
+    // The rest waits for more messages
+    info!("25");
+    assert_eq!(receive(&mut frame_receiver).await, expected(20..26));
+    assert!(start.elapsed() >= BATCH_LATENCY);
+
+    // A message, which doesn't fit into the batch, sends the batch right away
+    let large = "x".repeat(BATCH_BYTES / 2);
+    info!("{large}");
+    info!("{large}");
+
+    let start = Instant::now();
+    assert_eq!(receive(&mut frame_receiver).await, vec![large.clone()]);
+    assert!(start.elapsed() < BATCH_LATENCY);
+
+    assert_eq!(receive(&mut frame_receiver).await, vec![large]);
+}
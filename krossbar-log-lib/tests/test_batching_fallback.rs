@@ -0,0 +1,42 @@
+use log::*;
+use tempdir::TempDir;
+use tokio::sync::mpsc::unbounded_channel;
+
+use krossbar_log_common::logger_interface::LOG_METHOD_NAME;
+use krossbar_log_lib::Logger as ClientLogger;
+
+mod fake_logger;
+use fake_logger::{receive, start_frame_logger, Frame};
+
+#[tokio::test]
+async fn test_batching_fallback() {
+    let socket_dir = TempDir::new("logger_socket_dir").expect("Failed to create socket tempdir");
+    let socket_path = socket_dir.path().join("krossbar_logger.socket");
+
+    // Older logger, which doesn't accept batches
+    let (frame_sender, mut frame_receiver) = unbounded_channel::<Frame>();
+    start_frame_logger(&socket_path, false, frame_sender);
+
+    let logger = ClientLogger::new(
+        "test.batching.fallback.service",
+        LevelFilter::Info,
+        false,
+        Some(socket_path),
+    )
+    .await
+    .unwrap();
+
+    for i in 0..5 {
+        info!("{i}");
+    }
+
+    tokio::spawn(logger.run());
+
+    for i in 0..5 {
+        let frame = receive(&mut frame_receiver).await;
+
+        assert_eq!(frame.endpoint, LOG_METHOD_NAME);
+        assert_eq!(frame.messages.len(), 1);
+        assert_eq!(frame.messages[0].message, i.to_string());
+    }
+}
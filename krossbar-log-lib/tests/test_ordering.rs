@@ -0,0 +1,104 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use log::*;
+use tempdir::TempDir;
+use tokio::sync::{mpsc::unbounded_channel, Notify};
+
+use krossbar_log_common::log_message::LogMessage;
+use krossbar_log_lib::Logger as ClientLogger;
+
+mod fake_logger;
+use fake_logger::start_logger;
+
+const NUM_THREADS: usize = 8;
+const NUM_TASKS: usize = 8;
+const NUM_MESSAGES: usize = 1000;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_message_order_under_load() {
+    let socket_dir = TempDir::new("logger_socket_dir").expect("Failed to create socket tempdir");
+    let socket_path = socket_dir.path().join("krossbar_logger.socket");
+
+    let (message_sender, mut message_receiver) = unbounded_channel::<LogMessage>();
+    start_logger(&socket_path, false, message_sender, Arc::new(Notify::new()));
+
+    let logger = ClientLogger::new(
+        "test.ordering.service",
+        LevelFilter::Info,
+        false,
+        Some(socket_path),
+    )
+    .await
+    .unwrap();
+    tokio::spawn(logger.run());
+
+    // Plain threads
+    let threads: Vec<_> = (0..NUM_THREADS)
+        .map(|thread| {
+            std::thread::spawn(move || {
+                for i in 0..NUM_MESSAGES {
+                    info!(target: "stress", "thread-{thread} {i}");
+                }
+            })
+        })
+        .collect();
+
+    // Tasks inside the runtime
+    let tasks: Vec<_> = (0..NUM_TASKS)
+        .map(|task| {
+            tokio::spawn(async move {
+                for i in 0..NUM_MESSAGES {
+                    info!(target: "stress", "task-{task} {i}");
+
+                    if i % 100 == 0 {
+                        tokio::task::yield_now().await;
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let total = (NUM_THREADS + NUM_TASKS) * NUM_MESSAGES;
+    let mut last_messages: HashMap<String, usize> = HashMap::new();
+    let mut last_seq = None;
+    let mut received = 0;
+
+    while received < total {
+        let message = tokio::time::timeout(Duration::from_secs(10), message_receiver.recv())
+            .await
+            .expect("Timeout waiting for log messages")
+            .unwrap();
+
+        // Sequence numbers increase without gaps
+        let seq = message.seq.expect("Missing message sequence number");
+        assert_eq!(seq, last_seq.map(|last_seq| last_seq + 1).unwrap_or(0));
+        last_seq = Some(seq);
+
+        if message.target != "stress" {
+            continue;
+        }
+
+        // Messages of each producer are received in the order they were logged
+        let (producer, number) = message.message.split_once(' ').unwrap();
+        let number: usize = number.parse().unwrap();
+        let expected = last_messages
+            .get(producer)
+            .map(|last| last + 1)
+            .unwrap_or(0);
+        assert_eq!(number, expected, "Reordered message from {producer}");
+
+        last_messages.insert(producer.into(), number);
+        received += 1;
+    }
+
+    assert_eq!(last_messages.len(), NUM_THREADS + NUM_TASKS);
+    assert!(last_messages.values().all(|last| *last == NUM_MESSAGES - 1));
+}
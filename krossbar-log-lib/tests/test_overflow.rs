@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use log::*;
+use tempdir::TempDir;
+use tokio::sync::{mpsc::unbounded_channel, Notify};
+
+use krossbar_log_common::log_message::{LogMessage, DROPPED_MESSAGES_TARGET};
+use krossbar_log_lib::{Logger as ClientLogger, OverflowPolicy};
+
+mod fake_logger;
+use fake_logger::{receive, start_logger};
+
+const CAPACITY: usize = 10;
+const NUM_MESSAGES: usize = 25;
+
+#[tokio::test]
+async fn test_drop_oldest() {
+    let socket_dir = TempDir::new("logger_socket_dir").expect("Failed to create socket tempdir");
+    let socket_path = socket_dir.path().join("krossbar_logger.socket");
+
+    let (message_sender, mut message_receiver) = unbounded_channel::<LogMessage>();
+    start_logger(&socket_path, false, message_sender, Arc::new(Notify::new()));
+
+    let logger = ClientLogger::new(
+        "test.overflow.service",
+        LevelFilter::Info,
+        false,
+        Some(socket_path),
+    )
+    .await
+    .unwrap()
+    .with_overflow_policy(CAPACITY, OverflowPolicy::DropOldest);
+
+    // Nobody sends messages yet, so the queue overflows
+    for i in 0..NUM_MESSAGES {
+        info!(target: "overflow", "{i}");
+    }
+
+    tokio::spawn(logger.run());
+
+    // Only the newest messages are kept
+    for (seq, i) in (NUM_MESSAGES - CAPACITY..NUM_MESSAGES).enumerate() {
+        let message = receive(&mut message_receiver).await;
+
+        assert_eq!(message.target, "overflow");
+        assert_eq!(message.message, i.to_string());
+        assert_eq!(message.seq, Some(seq as u64));
+    }
+
+    let report = receive(&mut message_receiver).await;
+    assert_eq!(report.target, DROPPED_MESSAGES_TARGET);
+    assert_eq!(report.level, Level::Warn);
+    assert_eq!(report.message, "15 messages dropped");
+    assert_eq!(report.dropped, Some((NUM_MESSAGES - CAPACITY) as u64));
+    assert_eq!(report.seq, Some(CAPACITY as u64));
+
+    // Dropped messages are reported once
+    info!(target: "overflow", "After overflow");
+
+    let message = receive(&mut message_receiver).await;
+    assert_eq!(message.message, "After overflow");
+    assert_eq!(message.seq, Some(CAPACITY as u64 + 1));
+}
@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use log::*;
+use tempdir::TempDir;
+use tokio::sync::{mpsc::unbounded_channel, Notify};
+
+use krossbar_log_common::log_message::{LogMessage, DROPPED_MESSAGES_TARGET};
+use krossbar_log_lib::{Logger as ClientLogger, OverflowPolicy, OVERFLOW_LIMIT_FACTOR};
+
+mod fake_logger;
+use fake_logger::{receive, start_logger};
+
+const CAPACITY: usize = 2;
+const NUM_MESSAGES: usize = 300;
+
+#[tokio::test]
+async fn test_block_in_runtime() {
+    let socket_dir = TempDir::new("logger_socket_dir").expect("Failed to create socket tempdir");
+    let socket_path = socket_dir.path().join("krossbar_logger.socket");
+
+    let (message_sender, mut message_receiver) = unbounded_channel::<LogMessage>();
+    start_logger(&socket_path, true, message_sender, Arc::new(Notify::new()));
+
+    let logger = ClientLogger::new(
+        "test.overflow.service",
+        LevelFilter::Info,
+        false,
+        Some(socket_path),
+    )
+    .await
+    .unwrap()
+    .with_overflow_policy(CAPACITY, OverflowPolicy::Block);
+
+    // Nobody sends messages yet and the runtime can't block, so the queue grows up to the limit
+    for i in 0..NUM_MESSAGES {
+        info!(target: "overflow", "{i}");
+    }
+
+    tokio::spawn(logger.run());
+
+    let limit = CAPACITY * OVERFLOW_LIMIT_FACTOR;
+    for i in 0..limit {
+        let message = receive(&mut message_receiver).await;
+
+        assert_eq!(message.target, "overflow");
+        assert_eq!(message.message, i.to_string());
+    }
+
+    let report = receive(&mut message_receiver).await;
+    assert_eq!(report.target, DROPPED_MESSAGES_TARGET);
+    assert_eq!(report.dropped, Some((NUM_MESSAGES - limit) as u64));
+}
@@ -3,10 +3,24 @@ use std::time::Duration;
 use fork::{fork, Fork};
 use log::*;
 use rstest::rstest;
+use tempdir::TempDir;
+use tokio::{
+    net::UnixListener,
+    runtime::Runtime,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+};
+
+use krossbar_log_common::{
+    log_message::LogMessage,
+    logger_interface::{
+        decode_log_messages, LogLevel, SetLogLevel, GET_LOG_LEVEL_METHOD_NAME,
+        REGISTER_METHOD_NAME, SET_LOG_LEVEL_METHOD_NAME,
+    },
+};
+use krossbar_rpc::{request::Body, rpc::Rpc};
 
 mod fixture;
 use fixture::{init_client_logger, make_fixture, Fixture};
-use tokio::runtime::Runtime;
 
 #[rstest]
 fn test_simple_log(#[from(make_fixture)] fixture: Fixture) {
@@ -39,17 +53,27 @@ fn test_simple_log(#[from(make_fixture)] fixture: Fixture) {
                 warn!("Warning message");
                 info!("Info message");
                 debug!("Debug message");
+                info!(request_id = "abc", device_id = 42, online = true; "Structured message");
 
                 // Wait for logger to write file
-                tokio::time::sleep(Duration::from_millis(1)).await;
+                let mut log_file_text = String::new();
+                for _ in 0..100 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
 
-                let log_file_text = std::fs::read_to_string(fixture.log_file_path()).unwrap();
+                    log_file_text = std::fs::read_to_string(fixture.log_file_path()).unwrap();
+                    if log_file_text.contains("Structured message") {
+                        break;
+                    }
+                }
                 println!("Log text:\n{log_file_text}");
 
                 assert!(log_file_text.contains("Error message"));
                 assert!(log_file_text.contains("Warning message"));
                 assert!(log_file_text.contains("Info message"));
                 assert!(log_file_text.contains("Debug message"));
+                assert!(log_file_text.contains(
+                    r#"Structured message {device_id=42, online=true, request_id="abc"}"#
+                ));
 
                 fixture.cancel();
             });
@@ -57,3 +81,196 @@ fn test_simple_log(#[from(make_fixture)] fixture: Fixture) {
         Err(e) => panic!("Failed to fork: {e}"),
     }
 }
+
+/// Wait for the next message of a `level` level with a `target` target
+async fn next_message(
+    receiver: &mut UnboundedReceiver<LogMessage>,
+    level: Level,
+    target: &str,
+) -> LogMessage {
+    while let Some(message) = receiver.recv().await {
+        if message.level == level && message.target == target {
+            return message;
+        }
+    }
+
+    panic!("Client disconnected")
+}
+
+#[test]
+fn test_set_log_level() {
+    let socket_dir = TempDir::new("logger_socket_dir").expect("Failed to create socket tempdir");
+    let socket_path = socket_dir.path().join("krossbar_logger.socket");
+
+    // Bind before forking, so the client doesn't race the logger
+    let listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+    listener.set_nonblocking(true).unwrap();
+
+    match fork() {
+        Ok(Fork::Child) => {
+            drop(listener);
+            let rt = Runtime::new().unwrap();
+
+            rt.block_on(async move {
+                init_client_logger(socket_path).await;
+
+                for _ in 0..100 {
+                    debug!("Debug message");
+                    trace!("Trace message");
+                    trace!(target: "net", "Net trace message");
+
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+            });
+
+            std::process::exit(0)
+        }
+        Ok(Fork::Parent(child)) => {
+            eprintln!("Child PID: {child}");
+
+            let rt = Runtime::new().unwrap();
+
+            rt.block_on(async move {
+                let listener = UnixListener::from_std(listener).unwrap();
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut rpc = Rpc::new(stream, "test.log.service");
+
+                // Client registration
+                let request = rpc.poll().await.unwrap();
+                assert_eq!(request.endpoint(), REGISTER_METHOD_NAME);
+                request.respond(Ok(())).await;
+
+                // Polling resolves call responses, so we poll in a separate task
+                let writer = rpc.writer().clone();
+                let (message_sender, mut message_receiver) = unbounded_channel();
+                tokio::spawn(async move {
+                    while let Some(mut request) = rpc.poll().await {
+                        let endpoint = request.endpoint().to_owned();
+
+                        if let Some(Body::Message(body)) = request.take_body() {
+                            for message in decode_log_messages(&endpoint, body).unwrap() {
+                                let _ = message_sender.send(message);
+                            }
+                        }
+                    }
+                });
+
+                // Client starts with the debug level
+                let message = tokio::time::timeout(
+                    Duration::from_secs(1),
+                    next_message(&mut message_receiver, Level::Debug, "test_rpc"),
+                )
+                .await
+                .unwrap();
+                assert_eq!(message.message, "Debug message");
+
+                let set_log_level = |level, directives: Option<&str>| {
+                    let writer = writer.clone();
+                    let message = SetLogLevel {
+                        service_name: "test.log.service".into(),
+                        level,
+                        directives: directives.map(String::from),
+                        revert_after: None,
+                        reset: false,
+                    };
+
+                    async move {
+                        writer
+                            .call::<_, ()>(SET_LOG_LEVEL_METHOD_NAME, &message)
+                            .await
+                            .unwrap()
+                            .await
+                    }
+                };
+
+                let get_log_level = || {
+                    let writer = writer.clone();
+
+                    async move {
+                        writer
+                            .call::<_, LogLevel>(GET_LOG_LEVEL_METHOD_NAME, &())
+                            .await
+                            .unwrap()
+                            .await
+                            .unwrap()
+                    }
+                };
+
+                assert_eq!(
+                    get_log_level().await,
+                    LogLevel {
+                        level: LevelFilter::Debug,
+                        directives: None
+                    }
+                );
+
+                // Client acknowledges the level change
+                set_log_level(LevelFilter::Info, Some("net=trace"))
+                    .await
+                    .unwrap();
+
+                let message = tokio::time::timeout(
+                    Duration::from_secs(1),
+                    next_message(&mut message_receiver, Level::Trace, "net"),
+                )
+                .await
+                .unwrap();
+                assert_eq!(message.message, "Net trace message");
+
+                assert_eq!(
+                    get_log_level().await,
+                    LogLevel {
+                        level: LevelFilter::Info,
+                        directives: Some("net=trace".into())
+                    }
+                );
+
+                set_log_level(LevelFilter::Trace, None).await.unwrap();
+
+                let message = tokio::time::timeout(
+                    Duration::from_secs(1),
+                    next_message(&mut message_receiver, Level::Trace, "test_rpc"),
+                )
+                .await
+                .unwrap();
+                assert_eq!(message.message, "Trace message");
+
+                // Invalid directives
+                assert!(set_log_level(LevelFilter::Info, Some("net=loud"))
+                    .await
+                    .is_err());
+
+                // Invalid directives don't change the level
+                assert_eq!(get_log_level().await.level, LevelFilter::Trace);
+
+                // Invalid message
+                assert!(writer
+                    .call::<_, ()>(SET_LOG_LEVEL_METHOD_NAME, &LevelFilter::Trace)
+                    .await
+                    .unwrap()
+                    .await
+                    .is_err());
+
+                // Reset restores the initial level
+                writer
+                    .call::<_, ()>(
+                        SET_LOG_LEVEL_METHOD_NAME,
+                        &SetLogLevel::reset("test.log.service"),
+                    )
+                    .await
+                    .unwrap()
+                    .await
+                    .unwrap();
+
+                assert_eq!(
+                    get_log_level().await,
+                    LogLevel {
+                        level: LevelFilter::Debug,
+                        directives: None
+                    }
+                );
+            });
+        }
+        Err(e) => panic!("Failed to fork: {e}"),
+    }
+}
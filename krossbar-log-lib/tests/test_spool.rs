@@ -0,0 +1,73 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Local;
+use log::*;
+use tempdir::TempDir;
+use tokio::sync::{mpsc::unbounded_channel, Notify};
+
+use krossbar_log_common::log_message::{LogMessage, DROPPED_MESSAGES_TARGET};
+use krossbar_log_lib::Logger as ClientLogger;
+
+mod fake_logger;
+use fake_logger::{receive, start_logger};
+
+const SPOOL_SIZE: usize = 5;
+const NUM_MESSAGES: usize = 8;
+
+#[tokio::test]
+async fn test_spool_replay() {
+    let socket_dir = TempDir::new("logger_socket_dir").expect("Failed to create socket tempdir");
+    let socket_path = socket_dir.path().join("krossbar_logger.socket");
+    let spool_path = socket_dir.path().join("krossbar.spool");
+
+    // Logger is not running yet
+    let logger = ClientLogger::new(
+        "test.spool.service",
+        LevelFilter::Info,
+        false,
+        Some(socket_path.clone()),
+    )
+    .await
+    .unwrap()
+    .with_spool(SPOOL_SIZE, Some(&spool_path))
+    .unwrap();
+
+    for i in 0..NUM_MESSAGES {
+        info!(target: "boot", "{i}");
+    }
+
+    tokio::spawn(logger.run());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let logger_start = Local::now();
+    let (message_sender, mut message_receiver) = unbounded_channel::<LogMessage>();
+    let disconnect = Arc::new(Notify::new());
+    start_logger(&socket_path, true, message_sender, disconnect.clone());
+
+    // Library reconnects without new messages. Earliest messages are kept
+    for i in 0..SPOOL_SIZE {
+        let message = receive(&mut message_receiver).await;
+
+        assert_eq!(message.target, "boot");
+        assert_eq!(message.message, i.to_string());
+        assert_eq!(message.seq, Some(i as u64));
+        assert!(message.timestamp < logger_start);
+    }
+
+    let report = receive(&mut message_receiver).await;
+    assert_eq!(report.target, DROPPED_MESSAGES_TARGET);
+    assert_eq!(report.dropped, Some((NUM_MESSAGES - SPOOL_SIZE) as u64));
+
+    // Logger restart
+    disconnect.notify_one();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    info!(target: "restart", "While logger is down");
+
+    let message = receive(&mut message_receiver).await;
+    assert_eq!(message.message, "While logger is down");
+    assert_eq!(message.seq, Some(NUM_MESSAGES as u64 + 1));
+
+    // Spooled messages are removed after replay
+    assert_eq!(std::fs::metadata(&spool_path).unwrap().len(), 0);
+}
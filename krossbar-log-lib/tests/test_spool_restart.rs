@@ -0,0 +1,72 @@
+use std::{fs, io::Write, sync::Arc};
+
+use log::*;
+use tempdir::TempDir;
+use tokio::sync::{mpsc::unbounded_channel, Notify};
+
+use krossbar_log_common::log_message::LogMessage;
+use krossbar_log_lib::Logger as ClientLogger;
+
+mod fake_logger;
+use fake_logger::{receive, start_logger};
+
+const SPOOL_SIZE: usize = 5;
+
+#[tokio::test]
+async fn test_spool_restart() {
+    let socket_dir = TempDir::new("logger_socket_dir").expect("Failed to create socket tempdir");
+    let socket_path = socket_dir.path().join("krossbar_logger.socket");
+    let spool_path = socket_dir.path().join("krossbar.spool");
+
+    // Spool file left by a previous run: the first message is already replayed,
+    // the last one is partially written
+    let mut documents = vec![];
+    for i in 0..3 {
+        bson::to_document(&LogMessage::new(Level::Info, "boot".into(), i.to_string()))
+            .unwrap()
+            .to_writer(&mut documents)
+            .unwrap();
+    }
+
+    let first_size = u32::from_le_bytes(documents[..4].try_into().unwrap()) as u64;
+
+    let mut spool_file = fs::File::create(&spool_path).unwrap();
+    spool_file
+        .write_all(&(8 + first_size).to_le_bytes())
+        .unwrap();
+    spool_file.write_all(&documents).unwrap();
+    spool_file.write_all(&documents[..10]).unwrap();
+    drop(spool_file);
+
+    let logger = ClientLogger::new(
+        "test.spool.service",
+        LevelFilter::Info,
+        false,
+        Some(socket_path.clone()),
+    )
+    .await
+    .unwrap()
+    .with_spool(SPOOL_SIZE, Some(&spool_path))
+    .unwrap();
+
+    info!(target: "restart", "After restart");
+
+    let (message_sender, mut message_receiver) = unbounded_channel::<LogMessage>();
+    start_logger(&socket_path, true, message_sender, Arc::new(Notify::new()));
+    tokio::spawn(logger.run());
+
+    // Messages from the previous run go first and aren't replayed twice
+    for i in 1..3 {
+        let message = receive(&mut message_receiver).await;
+
+        assert_eq!(message.target, "boot");
+        assert_eq!(message.message, i.to_string());
+    }
+
+    let message = receive(&mut message_receiver).await;
+    assert_eq!(message.target, "restart");
+    assert_eq!(message.message, "After restart");
+
+    // Spooled messages are removed after replay
+    assert_eq!(fs::metadata(&spool_path).unwrap().len(), 0);
+}
@@ -1,5 +1,6 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, rc::Rc};
 
+use krossbar_log_common::line_template::{LineTemplate, Segment};
 use palette::{FromColor, Hsv, Srgb};
 use termion::color;
 
@@ -10,37 +11,112 @@ const ROTATION_ANGLE: f32 = 55.;
 pub struct Colorizer {
     service_colors: HashMap<String, color::Rgb>,
     color_rotator: f32,
+    /// Template to split log lines into components
+    line_template: Rc<LineTemplate>,
+    /// If show source location and thread metadata
+    show_location: bool,
 }
 
 impl Colorizer {
-    pub fn new() -> Self {
+    pub fn new(line_template: LineTemplate) -> Self {
         Self {
             service_colors: HashMap::new(),
             color_rotator: 0.,
+            line_template: Rc::new(line_template),
+            show_location: false,
         }
     }
 
+    /// Show or hide source location and thread metadata
+    pub fn set_show_location(&mut self, show: bool) {
+        self.show_location = show
+    }
+
+    pub fn show_location(&self) -> bool {
+        self.show_location
+    }
+
     pub fn colorize(&mut self, log_line: &String) -> String {
         if log_line == "\n" {
             return log_line.clone();
         }
 
-        let components: Vec<&str> = log_line.splitn(7, ' ').collect();
+        let (line, line_end) = match log_line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (log_line.as_str(), ""),
+        };
 
-        if components.len() != 7 {
+        // Template is shared to colorize components while parsed line borrows it
+        let line_template = self.line_template.clone();
+        let Some(components) = line_template.parse_line(line) else {
             eprintln!("Failed to split log line");
             return log_line.clone();
+        };
+
+        let mut result = String::new();
+        for (segment, value) in components {
+            result += &match segment {
+                Segment::Service => self.colorize_service(value),
+                Segment::Level => self.colorize_level(value),
+                Segment::Target => self.dim(value),
+                // Fields block starts with a space
+                Segment::Fields if !value.is_empty() => {
+                    format!(" {}", self.colorize_fields(&value[1..]))
+                }
+                // Hide source location unless requested
+                Segment::Location if !self.show_location => String::new(),
+                Segment::Location => self.dim(value),
+                _ => value.into(),
+            }
+        }
+
+        result + line_end
+    }
+
+    /// Highlight field names, and dim the values
+    fn colorize_fields(&self, fields: &str) -> String {
+        let mut result = String::new();
+        let mut key = String::new();
+        let mut in_key = false;
+        let mut in_string = false;
+        let mut escaped = false;
+
+        for c in fields.chars() {
+            if in_key {
+                if c == '=' {
+                    result += &format!(
+                        "{}{}{}=",
+                        color::Fg(color::LightCyan),
+                        key,
+                        LIGHT_GREY.fg_string()
+                    );
+
+                    key.clear();
+                    in_key = false;
+                } else {
+                    key.push(c);
+                }
+
+                continue;
+            }
+
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                '{' if !in_string => in_key = true,
+                ' ' if !in_string && result.ends_with(',') => in_key = true,
+                _ => {}
+            }
+
+            result.push(c);
         }
 
         format!(
-            "{} {} {} {} {} {} {}",
-            components[0],
-            components[1],
-            self.colorize_service(components[2]),
-            self.colorize_level(components[3]),
-            self.dim(components[4]),
-            self.dim(components[5]),
-            components[6]
+            "{}{}{}",
+            LIGHT_GREY.fg_string(),
+            result,
+            color::Fg(color::Reset)
         )
     }
 
@@ -56,31 +132,12 @@ impl Colorizer {
             random_color
         };
 
-        let name_components: Vec<&str> = service_name.splitn(2, '#').collect();
-
-        // Succesfully split service_name and pid
-        if name_components.len() == 2 {
-            let _darker_rgb = color::Rgb(
-                fg_color.0.saturating_sub(40),
-                fg_color.1.saturating_sub(40),
-                fg_color.2.saturating_sub(60),
-            );
-
-            format!(
-                "{}{}{}#{}",
-                fg_color.fg_string(),
-                name_components[0],
-                color::Fg(color::Reset),
-                name_components[1],
-            )
-        } else {
-            format!(
-                "{}{}{}",
-                fg_color.fg_string(),
-                service_name,
-                color::Fg(color::Reset)
-            )
-        }
+        format!(
+            "{}{}{}",
+            fg_color.fg_string(),
+            service_name,
+            color::Fg(color::Reset)
+        )
     }
 
     fn randomize_service_color(&mut self) -> color::Rgb {
@@ -98,28 +155,28 @@ impl Colorizer {
 
     fn colorize_level(&self, level: &str) -> String {
         match level {
-            "[ERROR]" => format!(
-                "{}[ERROR]{}",
+            "ERROR" => format!(
+                "{}ERROR{}",
                 color::Fg(color::LightRed),
                 color::Fg(color::Reset)
             ),
-            "[WARN]" => format!(
-                "{}[WARN]{}",
+            "WARN" => format!(
+                "{}WARN{}",
                 color::Fg(color::LightYellow),
                 color::Fg(color::Reset)
             ),
-            "[INFO]" => format!(
-                "{}[INFO]{}",
+            "INFO" => format!(
+                "{}INFO{}",
                 color::Fg(color::LightGreen),
                 color::Fg(color::Reset)
             ),
-            "[DEBUG]" => format!(
-                "{}[DEBUG]{}",
+            "DEBUG" => format!(
+                "{}DEBUG{}",
                 color::Fg(color::LightBlue),
                 color::Fg(color::Reset)
             ),
-            "[TRACE]" => format!(
-                "{}[TRACE]{}",
+            "TRACE" => format!(
+                "{}TRACE{}",
                 color::Fg(color::LightWhite),
                 color::Fg(color::Reset)
             ),
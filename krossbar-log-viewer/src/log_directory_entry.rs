@@ -1,10 +1,10 @@
 use std::{cmp::Ordering, path::PathBuf};
 
-use chrono::NaiveDateTime;
+use krossbar_log_common::naming::RotatedLogId;
 
 #[derive(Eq, PartialEq)]
 pub enum LogFileType {
-    Rotated(NaiveDateTime),
+    Rotated(RotatedLogId),
     Live,
 }
 
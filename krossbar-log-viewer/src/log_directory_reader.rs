@@ -1,9 +1,7 @@
 use std::path::PathBuf;
 
-use chrono::NaiveDateTime;
-use krossbar_log_common::ROTATED_LOG_TIMESTAMP_FORMAT;
+use krossbar_log_common::naming::LogNaming;
 use log::{debug, error, warn};
-use regex::Regex;
 
 use crate::log_directory_entry::{LogFileEntry, LogFileType};
 
@@ -24,48 +22,31 @@ impl DirectoryReader {
         let live_log_name: String = log_path.file_name().unwrap().to_string_lossy().into();
         debug!("Live log file name: {}", live_log_name);
 
-        let rotated_log_regex = match Self::get_rotated_log_regex(&live_log_name) {
-            Some(regx) => regx,
-            None => {
-                error!("Failed to make a regex for rotated logs");
-                return result;
-            }
-        };
+        let naming = LogNaming::new(&log_path);
 
         let log_dir = log_path.parent().unwrap().to_owned();
-        let dir_entries = match log_dir.read_dir() {
-            Ok(entries) => entries,
+
+        // A log, which is being compressed, is listed once
+        let rotated_logs = match naming.list_rotated_logs(&log_dir) {
+            Ok(rotated_logs) => rotated_logs,
             Err(err) => {
                 error!("Failed to read log directory: {}", err.to_string());
                 return result;
             }
         };
 
-        for entry in dir_entries {
-            match entry {
-                Ok(dir_entry) => {
-                    let log_file_name: String = dir_entry.file_name().to_string_lossy().into();
-
-                    // Skip dirs and live log
-                    if !dir_entry.file_type().map(|e| e.is_file()).unwrap_or(false)
-                        || live_log_name == log_file_name
-                    {
-                        continue;
-                    }
-
-                    if let Some(ts) = Self::get_log_timestamp(&dir_entry.path(), &rotated_log_regex)
-                    {
-                        result.push(LogFileEntry {
-                            log_file_name: log_file_name.clone(),
-                            full_path: log_dir.join(log_file_name),
-                            log_type: LogFileType::Rotated(ts),
-                        })
-                    }
-                }
-                Err(err) => {
-                    warn!("Failed to read log dir entry: {}", err.to_string())
-                }
-            }
+        for (id, full_path) in rotated_logs {
+            let log_file_name: String = full_path.file_name().unwrap().to_string_lossy().into();
+            debug!(
+                "Succesfully parsed rotated log '{}': {}",
+                log_file_name, id.timestamp
+            );
+
+            result.push(LogFileEntry {
+                log_file_name,
+                full_path,
+                log_type: LogFileType::Rotated(id),
+            })
         }
 
         if result.is_empty() {
@@ -82,53 +63,4 @@ impl DirectoryReader {
 
         result
     }
-
-    fn get_log_timestamp(
-        path: &PathBuf,
-        rotated_log_timestamp_regex: &Regex,
-    ) -> Option<NaiveDateTime> {
-        let log_file_name = path.file_name().unwrap().to_string_lossy();
-
-        debug!("Found file in the log directory: {}", log_file_name);
-
-        // Try to match filename with rotated log regex
-        if let Some(caps) = rotated_log_timestamp_regex.captures(&log_file_name) {
-            let rotated_log_datetime = &caps[1];
-
-            debug!("Rotated log timestamp: {}", rotated_log_datetime);
-
-            // Parse rotation timestamp
-            match NaiveDateTime::parse_from_str(rotated_log_datetime, ROTATED_LOG_TIMESTAMP_FORMAT)
-            {
-                Ok(ts) => {
-                    debug!("Succesfully parsed rotated log '{}'", log_file_name);
-                    return Some(ts);
-                }
-                Err(_) => {
-                    error!(
-                        "Failed to parse rotated log timestamp '{}'",
-                        rotated_log_datetime
-                    );
-                }
-            }
-        }
-
-        None
-    }
-
-    /// Make rotated log regex out of the live log file name.
-    /// The regex allows to parse rotated log timestamp
-    fn get_rotated_log_regex(live_log_name: &str) -> Option<Regex> {
-        if let Some((file_name, ext)) = live_log_name.rsplit_once('.') {
-            let rotate_file_regex = format!(r"{}_([^\.]+)\.{}", file_name, ext);
-
-            Regex::new(&rotate_file_regex).ok()
-        } else {
-            warn!(
-                "Failed to split live log name '{}' into file_name and extension",
-                live_log_name
-            );
-            None
-        }
-    }
 }
@@ -1,11 +1,13 @@
 use std::{
     collections::VecDeque,
     fs::File as FsFile,
-    io::{Read, Seek, SeekFrom},
+    io::{Cursor, Read, Seek, SeekFrom},
     path::PathBuf,
 };
 
 use chrono::NaiveDateTime;
+use flate2::read::GzDecoder;
+use krossbar_log_common::{GZIP_EXTENSION, ZSTD_EXTENSION};
 use log::*;
 
 use crate::log_files::{
@@ -16,14 +18,19 @@ use crate::log_files::{
 // Chunk size which we read first and than split into lines
 const READ_CHUNK_SIZE_BYTES: u64 = 1_000;
 
+/// Seekable log file handle
+trait LogReader: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> LogReader for T {}
+
 /// Rotated log file to show in generic mode
 pub struct RotatedLogFile {
     /// Rotated file path
     file_path: PathBuf,
     /// Time at whihc the file was rotated
     timestamp: NaiveDateTime,
-    /// Open file handle
-    handle: Option<FsFile>,
+    /// Open file handle. Compressed files are unpacked into memory to be able to seek
+    handle: Option<Box<dyn LogReader>>,
     /// File len in bytes
     file_len: u64,
     /// Currently shown file section
@@ -46,9 +53,22 @@ impl RotatedLogFile {
     }
 
     fn open_log_file(&mut self) -> Option<()> {
-        self.handle = Some(FsFile::open(&self.file_path).ok()?);
+        let file = FsFile::open(&self.file_path).ok()?;
 
-        self.file_len = self.file_len()?;
+        let mut handle: Box<dyn LogReader> =
+            match self.file_path.extension().and_then(|e| e.to_str()) {
+                Some(GZIP_EXTENSION) => {
+                    let mut buffer = vec![];
+                    GzDecoder::new(file).read_to_end(&mut buffer).ok()?;
+
+                    Box::new(Cursor::new(buffer))
+                }
+                Some(ZSTD_EXTENSION) => Box::new(Cursor::new(zstd::decode_all(file).ok()?)),
+                _ => Box::new(file),
+            };
+
+        self.file_len = handle.seek(SeekFrom::End(0)).ok()?;
+        self.handle = Some(handle);
 
         Some(())
     }
@@ -57,13 +77,6 @@ impl RotatedLogFile {
         self.handle = None
     }
 
-    fn file_len(&self) -> Option<u64> {
-        self.handle
-            .as_ref()
-            .and_then(|f| f.metadata().ok())
-            .map(|m| m.len())
-    }
-
     /// Clamps reading chunk inside the file
     fn get_chunk_to_read(&self, direction: ShiftDirection) -> (u64, u64) {
         match direction {
@@ -159,7 +172,7 @@ impl RotatedLogFile {
             return vec![];
         }
 
-        let mut handle = self.handle.as_ref().unwrap();
+        let handle = self.handle.as_mut().unwrap();
 
         if handle.seek(SeekFrom::Start(chunk_start)).is_err() {
             warn!("Failed to seek log file");
@@ -168,6 +181,7 @@ impl RotatedLogFile {
 
         let mut read_buf = String::new();
         if handle
+            .by_ref()
             .take(chunk_end - chunk_start)
             .read_to_string(&mut read_buf)
             .is_err()
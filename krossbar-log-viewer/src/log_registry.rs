@@ -28,8 +28,8 @@ impl LogRegistry {
                 .into_iter()
                 .map(|log_entry| {
                     let mut log_file = match log_entry.log_type {
-                        LogFileType::Rotated(ts) => {
-                            Box::new(RotatedLogFile::new(log_entry.full_path, ts))
+                        LogFileType::Rotated(id) => {
+                            Box::new(RotatedLogFile::new(log_entry.full_path, id.timestamp))
                                 as Box<dyn LogFile>
                         }
                         LogFileType::Live => {
@@ -15,6 +15,8 @@
 //! -l, --log-level <LOG_LEVEL>        Log level: OFF, ERROR, WARN, INFO, DEBUG, TRACE [default: INFO]
 //!     --log-location <LOG_LOCATION>  Log files location [default: /var/log/krossbar/krossbar.log]
 //! -f, --follow                       Output appended data as the file grows
+//! -m, --metadata                     Show source location and thread of the messages. Can be toggled with `m` key
+//!     --line-template <LINE_TEMPLATE>  Log line template. Should match the logger template [default: "<{ts:%d-%m-%Y %H:%M:%S%.3f}> {service}#{pid} [{level}] {target} > {message}{fields}{location}"]
 //! -h, --help                         Print help
 //! -V, --version                      Print version
 //! ```
@@ -33,7 +35,7 @@ use krossbar_log_viewer::{
 };
 use log::LevelFilter;
 
-use krossbar_log_common::DEFAULT_LOG_LOCATION;
+use krossbar_log_common::{line_template::LineTemplate, DEFAULT_LOG_LOCATION};
 use notify::{Error, EventKind, RecursiveMode, Watcher};
 use screen::Screen;
 use termion::{event::Key, input::TermRead};
@@ -53,6 +55,14 @@ pub struct Args {
     /// Output appended data as the file grows
     #[clap(short, long, value_parser, default_value_t = false)]
     pub follow: bool,
+
+    /// Show source location and thread of the messages. Can be toggled with `m` key
+    #[clap(short, long, value_parser, default_value_t = false)]
+    pub metadata: bool,
+
+    /// Log line template. Should match the logger template
+    #[clap(long, default_value_t = LineTemplate::default())]
+    pub line_template: LineTemplate,
 }
 
 fn render(
@@ -122,6 +132,17 @@ fn interactive(mut screen: Screen, mut registry: LogRegistry, mut colorizer: Col
     for c in stdin.keys() {
         match c.unwrap() {
             Key::Char('q') | Key::Ctrl('c') => break,
+            Key::Char('m') => {
+                colorizer.set_show_location(!colorizer.show_location());
+
+                render(
+                    &mut screen,
+                    &mut registry,
+                    &mut colorizer,
+                    ShiftDirection::Left,
+                    0,
+                );
+            }
             Key::Up => {
                 render(
                     &mut screen,
@@ -170,7 +191,8 @@ fn main() {
 
     let mut screen = Screen::new();
     let mut registry = LogRegistry::new(&args.log_location);
-    let mut colorizer = Colorizer::new();
+    let mut colorizer = Colorizer::new(args.line_template.clone());
+    colorizer.set_show_location(args.metadata);
 
     render(
         &mut screen,
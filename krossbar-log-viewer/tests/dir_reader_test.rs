@@ -40,6 +40,28 @@ fn create_log_files(path: &Path) {
         .format(ROTATED_LOG_TIMESTAMP_FORMAT))))
     .unwrap();
 
+    // Compressed rotated logs
+    File::create(path.join(format!(
+        "krossbar_{}.log.gz",
+        DateTime::parse_from_rfc2822("Thu, 19 Feb 2015 10:00:00 GMT")
+            .unwrap()
+            .format(ROTATED_LOG_TIMESTAMP_FORMAT))))
+    .unwrap();
+
+    File::create(path.join(format!(
+        "krossbar_{}.log.zst",
+        DateTime::parse_from_rfc2822("Fri, 20 Feb 2015 10:00:00 GMT")
+            .unwrap()
+            .format(ROTATED_LOG_TIMESTAMP_FORMAT))))
+    .unwrap();
+
+    File::create(path.join(format!(
+        "krossbar_{}.log.bak",
+        DateTime::parse_from_rfc2822("Sat, 21 Feb 2015 10:00:00 GMT")
+            .unwrap()
+            .format(ROTATED_LOG_TIMESTAMP_FORMAT))))
+    .unwrap();
+
     File::create(path.join("krossbar.log")).unwrap();
 }
 
@@ -65,6 +87,12 @@ fn dir_reader_test() {
     first_file = read_result.next().unwrap();
     assert_eq!(first_file.log_file_name, "krossbar_2015_02_18_23_16_09.log");
 
+    first_file = read_result.next().unwrap();
+    assert_eq!(first_file.log_file_name, "krossbar_2015_02_19_10_00_00.log.gz");
+
+    first_file = read_result.next().unwrap();
+    assert_eq!(first_file.log_file_name, "krossbar_2015_02_20_10_00_00.log.zst");
+
     first_file = read_result.next().unwrap();
     assert_eq!(first_file.log_file_name, "krossbar_2015_02_23_01_00_00.log");
 
@@ -73,3 +101,31 @@ fn dir_reader_test() {
 
     assert!(read_result.next().is_none());
 }
+
+#[test]
+fn dir_reader_compressing_test() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let path = log_dir.path();
+
+    // Log being compressed has both plain and compressed files
+    File::create(path.join("krossbar_2015_02_19_10_00_00.log")).unwrap();
+    File::create(path.join("krossbar_2015_02_19_10_00_00.log.gz")).unwrap();
+    File::create(path.join("krossbar_2015_02_20_10_00_00.log.zst")).unwrap();
+    File::create(path.join("krossbar.log")).unwrap();
+
+    let live_log_location: String = path.join("krossbar.log").to_string_lossy().into();
+    let file_names: Vec<String> = DirectoryReader::read_dir_logs(&live_log_location)
+        .into_iter()
+        .map(|entry| entry.log_file_name)
+        .collect();
+
+    // Plain file is listed. Compressed one is incomplete until the plain one is removed
+    assert_eq!(
+        file_names,
+        vec![
+            "krossbar_2015_02_19_10_00_00.log",
+            "krossbar_2015_02_20_10_00_00.log.zst",
+            "krossbar.log"
+        ]
+    );
+}
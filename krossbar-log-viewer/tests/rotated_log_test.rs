@@ -1,6 +1,12 @@
-use std::{collections::VecDeque, fs::OpenOptions, io::Write, path::Path};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+};
 
 use chrono::DateTime;
+use flate2::{write::GzEncoder, Compression};
 use krossbar_log_viewer::log_files::{
     log_file_trait::{LogFile, ShiftDirection},
     rotated_log_file::RotatedLogFile,
@@ -149,3 +155,59 @@ fn test_rotated_log() {
         ])
     );
 }
+
+fn check_compressed_log(log_file_path: &Path) {
+    let mut rotated = RotatedLogFile::new(
+        log_file_path.to_path_buf(),
+        DateTime::from_timestamp(0, 42_000_000)
+            .unwrap()
+            .naive_local(),
+    );
+
+    // [0, 1, x, x, x]
+    rotated.read_and_shift(ShiftDirection::Right, 2, 0);
+    assert_eq!(
+        rotated.lines(),
+        &VecDeque::from_iter(["log0\n".to_owned(), "log1\n".to_owned()])
+    );
+
+    // [x, x, x, x, x]
+    rotated.rev();
+    assert_eq!(rotated.lines(), &VecDeque::from_iter([]));
+
+    // [x, x, x, 3, 4]
+    rotated.read_and_shift(ShiftDirection::Left, 2, 0);
+    assert_eq!(
+        rotated.lines(),
+        &VecDeque::from_iter(["log3\n".to_owned(), "log4".to_owned()])
+    );
+
+    // [x, 1, 2, x, x]
+    rotated.read_and_shift(ShiftDirection::Left, 2, 2);
+    assert_eq!(
+        rotated.lines(),
+        &VecDeque::from_iter(["log1\n".to_owned(), "log2\n".to_owned()])
+    );
+}
+
+#[test]
+fn test_compressed_rotated_log() {
+    let _ = pretty_env_logger::formatted_builder()
+        .filter_level(LevelFilter::Trace)
+        .try_init();
+
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let content = "log0\nlog1\nlog2\nlog3\nlog4".as_bytes();
+
+    let gzip_path = log_dir.path().join("test.log.gz");
+    let mut encoder = GzEncoder::new(File::create(&gzip_path).unwrap(), Compression::default());
+    encoder.write_all(content).unwrap();
+    encoder.finish().unwrap();
+
+    check_compressed_log(&gzip_path);
+
+    let zstd_path = log_dir.path().join("test.log.zst");
+    zstd::stream::copy_encode(content, File::create(&zstd_path).unwrap(), 0).unwrap();
+
+    check_compressed_log(&zstd_path);
+}
@@ -1,20 +1,111 @@
-use clap::Parser;
-use log::LevelFilter;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
-use krossbar_log_common::DEFAULT_LOG_LOCATION;
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser, ValueEnum};
+use log::{Level, LevelFilter};
+use serde::Deserialize;
+
+use krossbar_log_common::{
+    duration::parse_duration, line_template::LineTemplate, logger_interface::LogLevel,
+    DEFAULT_LEVELS_LOCATION, DEFAULT_LOGGER_SOCKET_PATH, DEFAULT_LOG_LOCATION, GZIP_EXTENSION,
+    ZSTD_EXTENSION,
+};
+
+use crate::{config::Config, trigger::TriggerPolicy};
+
+/// Log file format
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable text lines
+    Text,
+    /// JSON Lines: a JSON object per log message
+    Json,
+}
+
+/// Rotated log files compression
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Keep rotated files as is
+    None,
+    /// Gzip rotated files into `.gz`
+    Gzip,
+    /// Compress rotated files with Zstandard into `.zst`
+    Zstd,
+}
+
+impl Compression {
+    /// Compressed file extension
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Gzip => Some(GZIP_EXTENSION),
+            Compression::Zstd => Some(ZSTD_EXTENSION),
+        }
+    }
+}
+
+/// What to do with client messages if the logger queue is full
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueOverflow {
+    /// Stop reading the client until the queue has space, so the client queue fills up
+    Block,
+    /// Drop new messages. Dropped messages are reported as the client
+    /// `N messages dropped` warning once the queue has space again
+    Drop,
+}
+
+/// Per-service settings. Set in the config file only
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServiceSettings {
+    pub service_name: String,
+    /// Default service log level. Applied when the service registers unless
+    /// a level is set with `krossbar-log-control`
+    pub log_level: Option<LogLevel>,
+    /// Dedicated service log file. Written in addition to the combined log
+    pub log_location: Option<String>,
+    /// Max service log file size in bytes. Global `num_bytes_rotate` if not set
+    pub num_bytes_rotate: Option<u64>,
+    /// How many rotated service log files to keep. Global `keep_num_files` if not set
+    pub keep_num_files: Option<usize>,
+    /// Max rotated service log files age. Global `max_age` if not set
+    pub max_age: Option<Duration>,
+    /// Max total size of the service log files in bytes. Global `max_total_bytes` if not set
+    pub max_total_bytes: Option<u64>,
+}
 
 /// Krossbar logger
 #[derive(Parser, Debug, Clone)]
 #[clap(version, about, long_about = None)]
 pub struct Args {
+    /// TOML config file. Reloaded on SIGHUP. Command line flags take precedence over
+    /// the config file settings
+    #[clap(short, long)]
+    pub config: Option<String>,
+
     /// Logger self log level: OFF, ERROR, WARN, INFO, DEBUG, TRACE
     #[clap(short, long, default_value_t = LevelFilter::Debug)]
     pub log_level: log::LevelFilter,
 
+    /// Logger socket path
+    #[clap(long, default_value_t = DEFAULT_LOGGER_SOCKET_PATH.into())]
+    pub socket_path: String,
+
     /// Log file location
     #[clap(long, default_value_t = DEFAULT_LOG_LOCATION.into())]
     pub log_location: String,
 
+    /// Persisted service log levels location. Levels set with `krossbar-log-control`
+    /// are stored here and re-applied when services reconnect
+    #[clap(long, default_value_t = DEFAULT_LEVELS_LOCATION.into())]
+    pub levels_location: String,
+
     /// Max log file size in bytes
     #[clap(short, long, default_value_t = 1_000_000)]
     pub num_bytes_rotate: u64,
@@ -22,4 +113,246 @@ pub struct Args {
     /// How many rotated log files to keep
     #[clap(short, long, default_value_t = 10)]
     pub keep_num_files: usize,
+
+    /// Rotate log file periodically even if size limit is not reached, e.g. 30m, 1h, 1d
+    #[clap(long, value_parser = parse_duration)]
+    pub rotate_period: Option<Duration>,
+
+    /// Remove rotated log files older than the given age, e.g. 12h, 7d
+    #[clap(long, value_parser = parse_duration)]
+    pub max_age: Option<Duration>,
+
+    /// Max total size of the live and rotated log files in bytes. Takes precedence
+    /// over other retention policies
+    #[clap(long)]
+    pub max_total_bytes: Option<u64>,
+
+    /// Fsync log files at least this often if there's unsynced data, e.g. 100ms, 1s
+    #[clap(long, value_parser = parse_duration)]
+    pub fsync_period: Option<Duration>,
+
+    /// Fsync log files once the given number of bytes is written since the last fsync
+    #[clap(long)]
+    pub fsync_bytes: Option<u64>,
+
+    /// Fsync log files right after writing a message at the given level or above,
+    /// e.g. ERROR guarantees errors survive a power cut
+    #[clap(long)]
+    pub fsync_level: Option<Level>,
+
+    /// Log file format
+    #[clap(short, long, value_enum, default_value_t = LogFormat::Text)]
+    pub format: LogFormat,
+
+    /// Text log line template. Placeholders: {ts:FORMAT}, {ts_utc:FORMAT}, {service}, {pid},
+    /// {level}, {target}, {message}, {fields}, {location}
+    #[clap(long, default_value_t = LineTemplate::default())]
+    pub line_template: LineTemplate,
+
+    /// Additionally write each service messages into a separate `<service name>.<ext>` file
+    /// next to the combined log. Each file is rotated separately. Services, which can't have
+    /// own files, e.g. because of file name conflicts, are written into the combined log
+    #[clap(long)]
+    pub per_service_files: bool,
+
+    /// Don't write the combined log file in per-service files mode
+    #[clap(long, requires = "per_service_files")]
+    pub no_combined_log: bool,
+
+    /// Compress rotated log files in the background
+    #[clap(long, value_enum, default_value_t = Compression::None)]
+    pub compress: Compression,
+
+    /// Compression level: 0-9 for gzip, 1-22 for zstd. Codec default if not set
+    #[clap(long)]
+    pub compress_level: Option<u32>,
+
+    /// Number of recent messages to keep in memory for `krossbar-log-control dump`.
+    /// 0 disables the buffer
+    #[clap(long, default_value_t = 1000)]
+    pub ring_size: usize,
+
+    /// Max approximate size of the messages kept in memory in bytes
+    #[clap(long)]
+    pub ring_bytes: Option<u64>,
+
+    /// Minimum level of messages written into log files. Messages below the level are
+    /// kept in memory only: OFF, ERROR, WARN, INFO, DEBUG, TRACE
+    #[clap(long, default_value_t = LevelFilter::Trace)]
+    pub file_level: LevelFilter,
+
+    /// Max number of client messages queued to be written. Change requires restart
+    #[clap(long, default_value_t = 100)]
+    pub queue_size: usize,
+
+    /// What to do with client messages if the queue is full
+    #[clap(long, value_enum, default_value_t = QueueOverflow::Block)]
+    pub queue_overflow: QueueOverflow,
+
+    /// Per-service trigger flush policy: `<SERVICE>[:file_level=<LEVEL>,trigger=<LEVEL>,context=<NUM>]`.
+    /// Service messages below `file_level` [default: INFO] are kept in memory, and the last
+    /// `context` [default: 100] of them are written into the log files right before a message
+    /// at `trigger` level [default: ERROR] or above. Overrides `--file-level` for the service.
+    /// Flushed messages keep their timestamps, but are written after the messages logged since
+    /// they were buffered, so the log files are not in chronological order around them.
+    /// Can be repeated
+    #[clap(long = "trigger-flush", value_parser = TriggerPolicy::from_str)]
+    pub trigger_flush: Vec<TriggerPolicy>,
+
+    /// Per-service settings from the config file
+    #[clap(skip)]
+    pub services: Vec<ServiceSettings>,
+
+    /// Args given on the command line. The config file doesn't override them
+    #[clap(skip)]
+    pub cli_overrides: Vec<String>,
+}
+
+impl Args {
+    /// Parse command line args
+    pub fn from_command_line() -> Self {
+        let matches = Self::command().get_matches();
+
+        let mut args = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        args.cli_overrides = matches
+            .ids()
+            .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+            .map(|id| id.to_string())
+            .collect();
+
+        args
+    }
+
+    /// Args with the config file settings applied if the config file is set
+    pub fn with_config(&self) -> Result<Args, String> {
+        match self.config {
+            Some(ref config) => Config::load(Path::new(config))?.apply(self.clone()),
+            None => Ok(self.clone()),
+        }
+    }
+
+    /// Default service levels from the per-service settings
+    pub fn service_levels(&self) -> BTreeMap<String, LogLevel> {
+        self.services
+            .iter()
+            .filter_map(|service| Some((service.service_name.clone(), service.log_level.clone()?)))
+            .collect()
+    }
+
+    /// Dedicated service log files from the per-service settings
+    pub fn service_sinks(&self) -> HashMap<String, PathBuf> {
+        self.services
+            .iter()
+            .filter_map(|service| {
+                Some((
+                    service.service_name.clone(),
+                    PathBuf::from(service.log_location.as_ref()?),
+                ))
+            })
+            .collect()
+    }
+
+    /// Args for the **service_name** log file with the per-service rotation
+    /// and retention settings applied
+    pub fn service_args(&self, service_name: &str) -> Args {
+        let mut args = self.clone();
+
+        if let Some(service) = self
+            .services
+            .iter()
+            .find(|service| service.service_name == service_name)
+        {
+            args.num_bytes_rotate = service.num_bytes_rotate.unwrap_or(args.num_bytes_rotate);
+            args.keep_num_files = service.keep_num_files.unwrap_or(args.keep_num_files);
+            args.max_age = service.max_age.or(args.max_age);
+            args.max_total_bytes = service.max_total_bytes.or(args.max_total_bytes);
+        }
+
+        args
+    }
+
+    /// Human readable list of settings changed in **other** args
+    pub fn diff(&self, other: &Args) -> Vec<String> {
+        let mut result = vec![];
+
+        macro_rules! diff {
+            ($($field:ident),*) => {
+                $(
+                    if self.$field != other.$field {
+                        result.push(format!(
+                            "{}: {:?} -> {:?}",
+                            stringify!($field),
+                            self.$field,
+                            other.$field
+                        ));
+                    }
+                )*
+            };
+        }
+
+        diff!(
+            log_level,
+            socket_path,
+            log_location,
+            levels_location,
+            num_bytes_rotate,
+            keep_num_files,
+            rotate_period,
+            max_age,
+            max_total_bytes,
+            fsync_period,
+            fsync_bytes,
+            fsync_level,
+            format,
+            line_template,
+            per_service_files,
+            no_combined_log,
+            compress,
+            compress_level,
+            ring_size,
+            ring_bytes,
+            file_level,
+            queue_size,
+            queue_overflow,
+            trigger_flush,
+            services
+        );
+
+        result
+    }
+}
+
+impl Default for Args {
+    /// Same as the command line defaults
+    fn default() -> Self {
+        Self {
+            config: None,
+            log_level: LevelFilter::Debug,
+            socket_path: DEFAULT_LOGGER_SOCKET_PATH.into(),
+            log_location: DEFAULT_LOG_LOCATION.into(),
+            levels_location: DEFAULT_LEVELS_LOCATION.into(),
+            num_bytes_rotate: 1_000_000,
+            keep_num_files: 10,
+            rotate_period: None,
+            max_age: None,
+            max_total_bytes: None,
+            fsync_period: None,
+            fsync_bytes: None,
+            fsync_level: None,
+            format: LogFormat::Text,
+            line_template: LineTemplate::default(),
+            per_service_files: false,
+            no_combined_log: false,
+            compress: Compression::None,
+            compress_level: None,
+            ring_size: 1000,
+            ring_bytes: None,
+            file_level: LevelFilter::Trace,
+            queue_size: 100,
+            queue_overflow: QueueOverflow::Block,
+            trigger_flush: vec![],
+            services: vec![],
+            cli_overrides: vec![],
+        }
+    }
 }
@@ -1,29 +1,140 @@
+use std::sync::{
+    atomic::{AtomicI64, AtomicU64, Ordering},
+    Arc,
+};
+
+use chrono::{DateTime, Local, TimeZone};
 use futures::{channel::mpsc::Sender, SinkExt};
-use log::{trace, warn};
-use tokio::net::unix;
+use log::{trace, warn, Level};
+use tokio::net::unix::{self, UCred};
+
+use krossbar_rpc::{rpc::Rpc, writer::RpcWriter, Error};
+
+use krossbar_log_common::{
+    log_message::LogMessage,
+    logger_interface::{
+        decode_log_messages, ClientInfo, MessageCounters, LOG_BATCH_METHOD_NAME, LOG_METHOD_NAME,
+    },
+};
+
+use crate::{args::QueueOverflow, LogEvent};
+
+/// Registered client
+pub struct ClientHandle {
+    pub writer: RpcWriter,
+    pub stats: Arc<ClientStats>,
+}
+
+/// Client connection statistics. Updated by the client task and the log writer
+pub struct ClientStats {
+    pub pid: unix::pid_t,
+    uid: unix::uid_t,
+    gid: unix::gid_t,
+    exe: Option<String>,
+    connected_at: DateTime<Local>,
+    /// Received messages per level. Indexed by [Level]
+    messages: [AtomicU64; 5],
+    bytes_written: AtomicU64,
+    /// Last message timestamp in milliseconds. Zero if no messages received yet
+    last_message_ms: AtomicI64,
+    /// Messages dropped by the client as reported by the client, and
+    /// messages dropped by the logger, because its queue was full
+    dropped: AtomicU64,
+}
+
+impl ClientStats {
+    pub fn new(credentials: &UCred) -> Self {
+        let pid = credentials.pid().unwrap();
+
+        Self {
+            pid,
+            uid: credentials.uid(),
+            gid: credentials.gid(),
+            exe: std::fs::read_link(format!("/proc/{pid}/exe"))
+                .ok()
+                .map(|exe| exe.to_string_lossy().into()),
+            connected_at: Local::now(),
+            messages: Default::default(),
+            bytes_written: AtomicU64::new(0),
+            last_message_ms: AtomicI64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    fn on_message(&self, message: &LogMessage) {
+        self.last_message_ms
+            .store(Local::now().timestamp_millis(), Ordering::Relaxed);
+
+        // Dropped reports are not client messages. Count them only as dropped
+        match message.dropped {
+            Some(num_dropped) => self.dropped.fetch_add(num_dropped, Ordering::Relaxed),
+            None => self.messages[message.level as usize - 1].fetch_add(1, Ordering::Relaxed),
+        };
+    }
 
-use krossbar_rpc::{rpc::Rpc, Error};
+    fn on_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
 
-use krossbar_log_common::{log_message::LogMessage, logger_interface::LOG_METHOD_NAME};
+    pub fn on_written(&self, num_bytes: usize) {
+        self.bytes_written
+            .fetch_add(num_bytes as u64, Ordering::Relaxed);
+    }
 
-use crate::LogEvent;
+    pub fn info(&self, service_name: &str) -> ClientInfo {
+        let messages = |level: Level| self.messages[level as usize - 1].load(Ordering::Relaxed);
+
+        ClientInfo {
+            service_name: service_name.into(),
+            pid: self.pid,
+            uid: self.uid,
+            gid: self.gid,
+            exe: self.exe.clone(),
+            connected_at: self.connected_at,
+            messages: MessageCounters {
+                error: messages(Level::Error),
+                warn: messages(Level::Warn),
+                info: messages(Level::Info),
+                debug: messages(Level::Debug),
+                trace: messages(Level::Trace),
+            },
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            last_message_at: match self.last_message_ms.load(Ordering::Relaxed) {
+                0 => None,
+                ms => Local.timestamp_millis_opt(ms).single(),
+            },
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
 
 pub struct Client {
-    pid: unix::pid_t,
     service_name: String,
     rpc: Rpc,
+    stats: Arc<ClientStats>,
     log_sender: Sender<LogEvent>,
+    queue_overflow: QueueOverflow,
+    /// Messages dropped because the queue was full, which are not reported yet
+    num_dropped: u64,
 }
 
 impl Client {
     pub async fn run(
-        (pid, service_name, rpc, log_sender): (unix::pid_t, String, Rpc, Sender<LogEvent>),
+        (service_name, rpc, stats, log_sender, queue_overflow): (
+            String,
+            Rpc,
+            Arc<ClientStats>,
+            Sender<LogEvent>,
+            QueueOverflow,
+        ),
     ) -> std::result::Result<String, ()> {
         let this = Self {
-            pid,
             rpc,
             service_name: service_name.clone(),
+            stats,
             log_sender,
+            queue_overflow,
+            num_dropped: 0,
         };
 
         this.client_loop().await;
@@ -35,22 +146,24 @@ impl Client {
         loop {
             match self.rpc.poll().await {
                 Some(mut request) => {
-                    if request.endpoint() != LOG_METHOD_NAME {
+                    let endpoint = request.endpoint().to_owned();
+
+                    if endpoint != LOG_METHOD_NAME && endpoint != LOG_BATCH_METHOD_NAME {
                         request
                             .respond::<()>(Err(Error::InternalError(format!(
-                                "Expected log from a client. Got {}",
-                                request.endpoint()
+                                "Expected log from a client. Got {endpoint}"
                             ))))
                             .await;
                     }
 
                     match request.take_body().unwrap() {
-                        // Valid one-way message
+                        // Valid one-way message. Older clients send messages one by one
                         krossbar_rpc::request::Body::Message(bson) => {
-                            // Valid Auth message
-                            match bson::from_bson::<LogMessage>(bson) {
-                                Ok(log_message) => {
-                                    self.handle_log_message(log_message).await;
+                            match decode_log_messages(&endpoint, bson) {
+                                Ok(log_messages) => {
+                                    for log_message in log_messages {
+                                        self.handle_log_message(log_message).await;
+                                    }
                                 }
                                 // Message deserialization error
                                 Err(e) => {
@@ -81,13 +194,44 @@ impl Client {
             self.service_name
         );
 
-        let _ = self
-            .log_sender
-            .send(LogEvent {
-                pid: self.pid,
-                service_name: self.service_name.clone(),
-                message,
-            })
-            .await;
+        self.stats.on_message(&message);
+
+        let event = self.log_event(message);
+        match self.queue_overflow {
+            QueueOverflow::Block => {
+                let _ = self.log_sender.send(event).await;
+            }
+            QueueOverflow::Drop => self.try_send(event),
+        }
+    }
+
+    /// Queue the event if there's space. Dropped messages are reported before the next
+    /// queued message
+    fn try_send(&mut self, event: LogEvent) {
+        if self.num_dropped > 0 {
+            let report = self.log_event(LogMessage::dropped(self.num_dropped));
+
+            if self.log_sender.try_send(report).is_err() {
+                self.num_dropped += 1;
+                self.stats.on_dropped();
+                return;
+            }
+
+            self.num_dropped = 0;
+        }
+
+        if self.log_sender.try_send(event).is_err() {
+            self.num_dropped += 1;
+            self.stats.on_dropped();
+        }
+    }
+
+    fn log_event(&self, message: LogMessage) -> LogEvent {
+        LogEvent {
+            pid: self.stats.pid,
+            service_name: self.service_name.clone(),
+            message,
+            stats: Some(self.stats.clone()),
+        }
     }
 }
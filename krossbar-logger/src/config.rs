@@ -0,0 +1,259 @@
+use std::{fs, path::Path};
+
+use log::{Level, LevelFilter};
+use serde::Deserialize;
+
+use krossbar_log_common::duration::parse_duration;
+
+use krossbar_log_common::logger_interface::LogLevel;
+
+use crate::{
+    args::{Args, Compression, LogFormat, QueueOverflow, ServiceSettings},
+    trigger::TriggerPolicy,
+};
+
+/// Logger config file. Every setting is optional and matches a command line flag.
+/// Flags given on the command line take precedence over the file settings
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Logger self log level
+    log_level: Option<LevelFilter>,
+    /// Logger socket path
+    socket_path: Option<String>,
+    /// Persisted service log levels location
+    levels_location: Option<String>,
+    /// Minimum level of messages written into log files
+    file_level: Option<LevelFilter>,
+    #[serde(default)]
+    output: OutputConfig,
+    #[serde(default)]
+    rotation: RotationConfig,
+    #[serde(default)]
+    retention: RetentionConfig,
+    #[serde(default)]
+    durability: DurabilityConfig,
+    #[serde(default)]
+    ring: RingConfig,
+    #[serde(default)]
+    queue: QueueConfig,
+    /// Per-service trigger flush policies
+    trigger_flush: Option<Vec<TriggerConfig>>,
+    /// Per-service levels and log files
+    services: Option<Vec<ServiceConfig>>,
+}
+
+/// Log files and routing of the messages into them
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct OutputConfig {
+    log_location: Option<String>,
+    format: Option<LogFormat>,
+    line_template: Option<String>,
+    per_service_files: Option<bool>,
+    no_combined_log: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RotationConfig {
+    num_bytes: Option<u64>,
+    period: Option<String>,
+    keep_num_files: Option<usize>,
+    compress: Option<Compression>,
+    compress_level: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RetentionConfig {
+    max_age: Option<String>,
+    max_total_bytes: Option<u64>,
+}
+
+/// When written log messages are fsynced
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct DurabilityConfig {
+    fsync_period: Option<String>,
+    fsync_bytes: Option<u64>,
+    fsync_level: Option<Level>,
+}
+
+/// In-memory buffer of recent messages
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RingConfig {
+    size: Option<usize>,
+    bytes: Option<u64>,
+}
+
+/// Queue of client messages to be written
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct QueueConfig {
+    size: Option<usize>,
+    overflow: Option<QueueOverflow>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct TriggerConfig {
+    service: String,
+    file_level: Option<LevelFilter>,
+    trigger: Option<Level>,
+    context: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ServiceConfig {
+    service: String,
+    level: Option<LevelFilter>,
+    directives: Option<String>,
+    log_location: Option<String>,
+    num_bytes_rotate: Option<u64>,
+    keep_num_files: Option<usize>,
+    max_age: Option<String>,
+    max_total_bytes: Option<u64>,
+}
+
+impl ServiceConfig {
+    fn settings(&self) -> Result<ServiceSettings, String> {
+        // Same as `krossbar-log-control set-log-level`: directives only disable other targets
+        let log_level = match (self.level, &self.directives) {
+            (None, None) => None,
+            (level, directives) => Some(LogLevel {
+                level: level.unwrap_or(LevelFilter::Off),
+                directives: directives.clone(),
+            }),
+        };
+
+        Ok(ServiceSettings {
+            service_name: self.service.clone(),
+            log_level,
+            log_location: self.log_location.clone(),
+            num_bytes_rotate: self.num_bytes_rotate,
+            keep_num_files: self.keep_num_files,
+            max_age: self.max_age.as_deref().map(parse_duration).transpose()?,
+            max_total_bytes: self.max_total_bytes,
+        })
+    }
+}
+
+impl TriggerConfig {
+    fn policy(&self) -> Result<TriggerPolicy, String> {
+        let mut policy: TriggerPolicy = self.service.parse()?;
+
+        if let Some(file_level) = self.file_level {
+            policy.file_level = file_level;
+        }
+
+        if let Some(trigger_level) = self.trigger {
+            policy.trigger_level = trigger_level;
+        }
+
+        if let Some(context) = self.context {
+            policy.context = context;
+        }
+
+        Ok(policy)
+    }
+}
+
+/// Set the arg from the config unless the flag was given on the command line
+macro_rules! apply {
+    ($args:ident.$field:ident, $value:expr) => {
+        if !$args
+            .cli_overrides
+            .iter()
+            .any(|id| id == stringify!($field))
+        {
+            if let Some(value) = $value {
+                $args.$field = value;
+            }
+        }
+    };
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config file {path:?}: {e}"))?;
+
+        toml::from_str(&contents).map_err(|e| format!("Invalid config file {path:?}: {e}"))
+    }
+
+    /// Merge the config into the command line **args**
+    pub fn apply(&self, mut args: Args) -> Result<Args, String> {
+        let duration = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(|value| parse_duration(value).map(Some))
+                .transpose()
+        };
+
+        apply!(args.log_level, self.log_level);
+        apply!(args.socket_path, self.socket_path.clone());
+        apply!(args.levels_location, self.levels_location.clone());
+        apply!(args.file_level, self.file_level);
+
+        let output = &self.output;
+        apply!(args.log_location, output.log_location.clone());
+        apply!(args.format, output.format);
+        apply!(
+            args.line_template,
+            output
+                .line_template
+                .as_deref()
+                .map(str::parse)
+                .transpose()?
+        );
+        apply!(args.per_service_files, output.per_service_files);
+        apply!(args.no_combined_log, output.no_combined_log);
+
+        let rotation = &self.rotation;
+        apply!(args.num_bytes_rotate, rotation.num_bytes);
+        apply!(args.rotate_period, duration(&rotation.period)?);
+        apply!(args.keep_num_files, rotation.keep_num_files);
+        apply!(args.compress, rotation.compress);
+        apply!(args.compress_level, rotation.compress_level.map(Some));
+
+        let retention = &self.retention;
+        apply!(args.max_age, duration(&retention.max_age)?);
+        apply!(args.max_total_bytes, retention.max_total_bytes.map(Some));
+
+        let durability = &self.durability;
+        apply!(args.fsync_period, duration(&durability.fsync_period)?);
+        apply!(args.fsync_bytes, durability.fsync_bytes.map(Some));
+        apply!(args.fsync_level, durability.fsync_level.map(Some));
+
+        apply!(args.ring_size, self.ring.size);
+        apply!(args.ring_bytes, self.ring.bytes.map(Some));
+
+        apply!(args.queue_size, self.queue.size);
+        apply!(args.queue_overflow, self.queue.overflow);
+
+        apply!(
+            args.trigger_flush,
+            self.trigger_flush
+                .as_ref()
+                .map(|policies| policies.iter().map(TriggerConfig::policy).collect())
+                .transpose()?
+        );
+
+        apply!(
+            args.services,
+            self.services
+                .as_ref()
+                .map(|services| services.iter().map(ServiceConfig::settings).collect())
+                .transpose()?
+        );
+
+        if args.no_combined_log && !args.per_service_files {
+            return Err("`no_combined_log` requires `per_service_files`".into());
+        }
+
+        Ok(args)
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use log::warn;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use krossbar_log_common::logger_interface::{LogLevel, SetLogLevel};
+
+/// Active temporary level change
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TemporaryLevel {
+    pub log_level: LogLevel,
+    /// Level to restore. The client initial level if none
+    pub original: Option<LogLevel>,
+    /// When to restore the original level
+    pub revert_at: DateTime<Local>,
+    /// Change id to match revert timers. Not persisted
+    #[serde(skip)]
+    pub id: u64,
+}
+
+impl TemporaryLevel {
+    /// Time left until the revert. Zero if the change has expired
+    pub fn remaining(&self) -> Duration {
+        (self.revert_at - Local::now()).to_std().unwrap_or_default()
+    }
+
+    /// Message to restore the original **service_name** level
+    pub fn revert_message(&self, service_name: &str) -> SetLogLevel {
+        match self.original {
+            Some(ref original) => SetLogLevel::new(service_name, original.clone()),
+            None => SetLogLevel::reset(service_name),
+        }
+    }
+}
+
+/// Persisted table of service log levels. Levels are re-applied when services reconnect.
+/// Services without a stored level get the config file default level if set.
+/// Active temporary level changes are persisted into a separate `<location>.temporary` file,
+/// so they're reverted even if the logger restarts. Temporary levels are reverted
+/// to the level the service had before the change, or to the client initial level if there's none.
+///
+/// Changes return a [Save] to write the table after the store lock is released
+pub struct LevelStore {
+    levels: BTreeMap<String, LogLevel>,
+    levels_file: TableFile,
+    /// Default levels from the config file. Not persisted
+    defaults: BTreeMap<String, LogLevel>,
+    temporary: BTreeMap<String, TemporaryLevel>,
+    temporary_file: TableFile,
+    next_temporary_id: u64,
+}
+
+/// Table file and the number of the latest table change
+struct TableFile {
+    location: PathBuf,
+    version: u64,
+    /// Latest written table change. Shared with pending saves
+    written: Arc<Mutex<u64>>,
+}
+
+/// Table change to write into the table file. Saves are written in the order the changes
+/// were made. Outdated ones are skipped
+#[must_use]
+pub struct Save {
+    location: PathBuf,
+    /// Table JSON. The file is removed if none
+    data: Option<String>,
+    version: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl LevelStore {
+    /// Load levels table. Missing or invalid file results into an empty table
+    pub fn load(location: PathBuf) -> Self {
+        let mut temporary_location = location.clone().into_os_string();
+        temporary_location.push(".temporary");
+        let temporary_location = PathBuf::from(temporary_location);
+
+        let levels = load_table(&location);
+
+        let mut temporary: BTreeMap<String, TemporaryLevel> = load_table(&temporary_location);
+        for (id, temporary_level) in temporary.values_mut().enumerate() {
+            temporary_level.id = id as u64;
+        }
+
+        Self {
+            levels,
+            levels_file: TableFile::new(location),
+            defaults: BTreeMap::new(),
+            temporary_file: TableFile::new(temporary_location),
+            next_temporary_id: temporary.len() as u64,
+            temporary,
+        }
+    }
+
+    /// Stored level message for a service, or the default level if there's none
+    pub fn get(&self, service_name: &str) -> Option<SetLogLevel> {
+        self.levels
+            .get(service_name)
+            .or_else(|| self.defaults.get(service_name))
+            .map(|log_level| SetLogLevel::new(service_name, log_level.clone()))
+    }
+
+    /// Config file default level message for a service
+    pub fn default_level(&self, service_name: &str) -> Option<SetLogLevel> {
+        self.defaults
+            .get(service_name)
+            .map(|log_level| SetLogLevel::new(service_name, log_level.clone()))
+    }
+
+    /// Replace default service levels, e.g. on config reload
+    pub fn set_defaults(&mut self, defaults: BTreeMap<String, LogLevel>) {
+        self.defaults = defaults;
+    }
+
+    /// Store service level
+    pub fn set(&mut self, message: &SetLogLevel) -> Save {
+        self.levels
+            .insert(message.service_name.clone(), message.log_level());
+
+        self.levels_file.save(&self.levels)
+    }
+
+    /// Remove stored service level. The service gets the default level if there's one
+    pub fn remove(&mut self, service_name: &str) -> Save {
+        self.levels.remove(service_name);
+
+        self.levels_file.save(&self.levels)
+    }
+
+    /// Register a temporary level change for **revert_after**. Replaces previous temporary change
+    /// of the service, but keeps its original level. Returns an id to use in [LevelStore::take_temporary]
+    pub fn set_temporary(&mut self, message: &SetLogLevel, revert_after: Duration) -> (u64, Save) {
+        let id = self.next_temporary_id;
+        self.next_temporary_id += 1;
+
+        let original = match self.temporary.get(&message.service_name) {
+            Some(previous) => previous.original.clone(),
+            None => self
+                .get(&message.service_name)
+                .map(|stored_level| stored_level.log_level()),
+        };
+
+        self.temporary.insert(
+            message.service_name.clone(),
+            TemporaryLevel {
+                log_level: message.log_level(),
+                original,
+                revert_at: Local::now()
+                    + chrono::Duration::from_std(revert_after).unwrap_or(chrono::Duration::MAX),
+                id,
+            },
+        );
+
+        (id, self.temporary_file.save(&self.temporary))
+    }
+
+    /// Active temporary change of the service
+    pub fn temporary(&self, service_name: &str) -> Option<TemporaryLevel> {
+        self.temporary.get(service_name).cloned()
+    }
+
+    /// Remove a temporary change. If **id** is some, removes only the matching
+    /// change, so outdated timers don't revert later changes.
+    /// Returns the change if the service had a matching one
+    pub fn take_temporary(
+        &mut self,
+        service_name: &str,
+        id: Option<u64>,
+    ) -> Option<(TemporaryLevel, Save)> {
+        match self.temporary.get(service_name) {
+            Some(temporary) if id.map(|id| id == temporary.id).unwrap_or(true) => {
+                let temporary = self.temporary.remove(service_name)?;
+
+                Some((temporary, self.temporary_file.save(&self.temporary)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl TableFile {
+    fn new(location: PathBuf) -> Self {
+        Self {
+            location,
+            version: 0,
+            written: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    /// Make a save of the **table**. Empty table removes the file
+    fn save<T: Serialize>(&mut self, table: &BTreeMap<String, T>) -> Save {
+        self.version += 1;
+
+        Save {
+            location: self.location.clone(),
+            // Tables of plain structs are always serializable
+            data: (!table.is_empty()).then(|| serde_json::to_string_pretty(table).unwrap()),
+            version: self.version,
+            written: self.written.clone(),
+        }
+    }
+}
+
+impl Save {
+    /// Write the table. Blocks on the file system
+    pub fn write(self) -> io::Result<()> {
+        let mut written = self.written.lock().unwrap();
+
+        // A later change is already written
+        if *written >= self.version {
+            return Ok(());
+        }
+
+        match self.data {
+            Some(ref data) => write_file(&self.location, data)?,
+            None => remove_file(&self.location)?,
+        }
+
+        *written = self.version;
+        Ok(())
+    }
+
+    /// Write the table in a blocking task, and log the error if failed
+    pub async fn write_in_background(self) {
+        let location = self.location.clone();
+
+        match tokio::task::spawn_blocking(move || self.write()).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => warn!("Failed to persist log levels into {location:?}: {err}"),
+            Err(err) => warn!("Failed to persist log levels into {location:?}: {err}"),
+        }
+    }
+}
+
+/// Load a table from a JSON file. Missing or invalid file results into an empty table
+fn load_table<T: DeserializeOwned>(location: &Path) -> BTreeMap<String, T> {
+    match fs::read_to_string(location) {
+        Ok(data) => serde_json::from_str(&data).unwrap_or_else(|err| {
+            warn!("Invalid log levels file {location:?}: {err}. Ignoring");
+            BTreeMap::new()
+        }),
+        Err(err) if err.kind() == ErrorKind::NotFound => BTreeMap::new(),
+        Err(err) => {
+            warn!("Failed to read log levels file {location:?}: {err}. Ignoring");
+            BTreeMap::new()
+        }
+    }
+}
+
+/// Directory of the file to fsync after renames
+fn parent_dir(location: &Path) -> &Path {
+    match location.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Write into a temporary file first to not leave a broken table on a power loss.
+/// Both the file and the rename are synced
+fn write_file(location: &Path, data: &str) -> io::Result<()> {
+    let dir = parent_dir(location);
+    fs::create_dir_all(dir)?;
+
+    let mut temp_location = location.to_path_buf().into_os_string();
+    temp_location.push(".tmp");
+
+    let mut file = File::create(&temp_location)?;
+    file.write_all(data.as_bytes())?;
+    file.sync_all()?;
+
+    fs::rename(&temp_location, location)?;
+    File::open(dir)?.sync_all()
+}
+
+fn remove_file(location: &Path) -> io::Result<()> {
+    match fs::remove_file(location) {
+        Ok(_) => File::open(parent_dir(location))?.sync_all(),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
@@ -1,16 +1,29 @@
+use std::sync::Arc;
+
 use krossbar_log_common::log_message::LogMessage;
 use tokio::net::unix;
 
+use client::ClientStats;
+
 pub mod args;
 mod client;
+pub mod config;
+pub mod levels;
 pub mod logger;
+pub mod query;
+pub mod ring;
 pub mod rotator;
+mod router;
 mod service;
 mod self_logger;
+pub mod tail;
+pub mod trigger;
 mod writer;
 
 pub struct LogEvent {
     pub pid: unix::pid_t,
     pub service_name: String,
     pub message: LogMessage,
+    /// Sender stats to count written bytes. [None] for the logger own messages
+    pub stats: Option<Arc<ClientStats>>,
 }
@@ -1,77 +1,169 @@
 use std::{
     collections::HashMap, fs, os::unix::fs::PermissionsExt, path::PathBuf, pin::Pin, sync::Arc,
+    time::Duration,
 };
 
 use futures::{
-    channel::mpsc::{channel, Receiver, Sender},
+    channel::{
+        mpsc::{channel, Receiver, Sender},
+        oneshot,
+    },
     future::{pending, FutureExt as _},
     lock::Mutex,
     stream::FuturesUnordered,
     Future, SinkExt, StreamExt as _,
 };
 
-use krossbar_log_common::logger_interface::REGISTER_METHOD_NAME;
+use krossbar_log_common::{
+    log_message::LogRecord,
+    logger_interface::{
+        LogRotated, RegisterResponse, SetLogLevel, REGISTER_METHOD_NAME, SET_LOG_LEVEL_METHOD_NAME,
+        TAIL_SUBSCRIPTION_TIMEOUT,
+    },
+};
 use log::{debug, error, info, warn};
 use tokio::{
-    net::{
-        unix::{self, UCred},
-        UnixListener,
-    },
+    net::{unix::UCred, UnixListener},
     select,
+    signal::unix::{signal, SignalKind},
+    time,
 };
 
-use krossbar_rpc::{request::RpcRequest, rpc::Rpc, writer::RpcWriter, Error, Result};
+use krossbar_rpc::{request::RpcRequest, rpc::Rpc, Error, Result};
 use krossbar_state_machine::Machine;
 
-use crate::{args::Args, client::Client, service::LoggerService, writer::Writer, LogEvent};
+use crate::{
+    args::{Args, QueueOverflow},
+    client::{Client, ClientHandle, ClientStats},
+    levels::LevelStore,
+    query::LogSearch,
+    ring::RingBuffer,
+    rotator::Rotation,
+    router::Router,
+    service::LoggerService,
+    tail::TailSubscriptions,
+    trigger::TriggerFilter,
+    LogEvent,
+};
 
 use crate::self_logger::SelfLogger;
 use log::set_boxed_logger;
 
 const CHANNEL_SIZE: usize = 100;
+/// Period to check if log file should be rotated by time
+const ROTATE_CHECK_PERIOD: Duration = Duration::from_secs(1);
+/// Period to check if rotated logs are too old
+const RETENTION_CHECK_PERIOD: Duration = Duration::from_secs(60);
 
 type TasksMapType = FuturesUnordered<Pin<Box<dyn Future<Output = Option<String>> + Send>>>;
-type ClientRegistryType = Arc<Mutex<HashMap<String, RpcWriter>>>;
+type ClientRegistryType = Arc<Mutex<HashMap<String, ClientHandle>>>;
+type LevelStoreType = Arc<Mutex<LevelStore>>;
+type TailSubscriptionsType = Arc<Mutex<TailSubscriptions>>;
+type RingBufferType = Arc<Mutex<RingBuffer>>;
+type LogSearchType = Arc<Mutex<LogSearch>>;
+/// Rotate command reply with the rotated file path
+type RotateReply = oneshot::Sender<std::result::Result<String, String>>;
+/// Rotations waiting for the rotated file compression
+type CompressionsType = FuturesUnordered<Pin<Box<dyn Future<Output = LogRotated> + Send>>>;
 
 pub enum Event {
-    Rotated(String),
+    Rotated(LogRotated),
+    Removed(String),
+}
+
+/// Live tail message for the subscribers whose filters match it
+pub struct TailEvent {
+    pub subscribers: Vec<String>,
+    pub record: LogRecord,
+}
+
+/// Logger main loop events for the logger service. Live tail events have own channel,
+/// so a busy tail can't stall rotation events
+pub struct EventReceivers {
+    pub events: Receiver<Event>,
+    pub tail: Receiver<TailEvent>,
+}
+
+/// Logger service requests to the logger main loop
+pub enum Command {
+    /// Rotate the combined log file, or a service log file. Replies with the rotated file path
+    /// once the rotated file is compressed
+    Rotate {
+        service_name: Option<String>,
+        reply: RotateReply,
+    },
 }
 
 pub struct Logger {
+    /// Command line args to apply the config file on reload
+    cli_args: Args,
+    /// Current settings
+    args: Args,
     tasks: TasksMapType,
     socket_path: PathBuf,
     clients: ClientRegistryType,
+    levels: LevelStoreType,
+    tail: TailSubscriptionsType,
+    search: LogSearchType,
+    ring: RingBufferType,
     log_receiver: Receiver<LogEvent>,
     log_sender: Sender<LogEvent>,
-    writer: Writer,
+    command_receiver: Receiver<Command>,
+    command_sender: Sender<Command>,
+    router: Router,
+    triggers: TriggerFilter,
+    compressions: CompressionsType,
 }
 
 impl Logger {
-    pub fn new(args: Args, socket_path: PathBuf) -> Self {
+    /// Create logger. The config file is applied to the command line **args** if set
+    pub fn new(cli_args: Args, socket_path: PathBuf) -> Self {
+        let args = cli_args
+            .with_config()
+            .unwrap_or_else(|e| panic!("Failed to load config: {e}"));
+
         let tasks: TasksMapType = FuturesUnordered::new();
         tasks.push(Box::pin(pending()));
 
-        let (log_sender, log_receiver) = channel(CHANNEL_SIZE);
+        let (log_sender, log_receiver) = channel(args.queue_size);
+        let (command_sender, command_receiver) = channel(CHANNEL_SIZE);
 
-        set_boxed_logger(Box::new(SelfLogger::new(
-            args.log_level,
-            log_sender.clone(),
-        )))
-        .map(|()| log::set_max_level(args.log_level))
-        .unwrap();
+        set_boxed_logger(Box::new(SelfLogger::new(log_sender.clone())))
+            .map(|()| log::set_max_level(args.log_level))
+            .unwrap();
 
         let clients = Arc::new(Mutex::new(HashMap::new()));
 
+        let mut levels = LevelStore::load(args.levels_location.clone().into());
+        levels.set_defaults(args.service_levels());
+
         Self {
+            cli_args,
             tasks,
             socket_path,
             clients: clients.clone(),
+            levels: Arc::new(Mutex::new(levels)),
+            tail: Arc::new(Mutex::new(TailSubscriptions::new(
+                TAIL_SUBSCRIPTION_TIMEOUT,
+            ))),
+            search: Arc::new(Mutex::new(LogSearch::new(&args))),
+            ring: Arc::new(Mutex::new(RingBuffer::new(args.ring_size, args.ring_bytes))),
             log_receiver,
             log_sender,
-            writer: Writer::new(&args),
+            command_receiver,
+            command_sender,
+            router: Router::new(&args).unwrap_or_else(|e| panic!("{e}")),
+            triggers: TriggerFilter::new(args.file_level, &args.trigger_flush),
+            compressions: FuturesUnordered::new(),
+            args,
         }
     }
 
+    /// Logger main loop commands sender. The logger service forwards bus requests through it
+    pub fn command_sender(&self) -> Sender<Command> {
+        self.command_sender.clone()
+    }
+
     /// Hub main loop
     pub async fn run(mut self) {
         println!("Logger socket path: {:?}", self.socket_path);
@@ -88,11 +180,32 @@ impl Logger {
         fs::set_permissions(&self.socket_path, socket_permissions).unwrap();
 
         let (mut event_sender, event_receiver) = channel(CHANNEL_SIZE);
+        let (mut tail_sender, tail_receiver) = channel(CHANNEL_SIZE);
+
+        LoggerService::run(
+            self.clients.clone(),
+            self.levels.clone(),
+            self.tail.clone(),
+            self.search.clone(),
+            self.ring.clone(),
+            EventReceivers {
+                events: event_receiver,
+                tail: tail_receiver,
+            },
+            self.command_sender(),
+        )
+        .await;
 
-        LoggerService::run(self.clients.clone(), event_receiver).await;
+        let mut rotate_check = time::interval(ROTATE_CHECK_PERIOD);
+        let mut retention_check = time::interval(RETENTION_CHECK_PERIOD);
+        let mut reload_signal =
+            signal(SignalKind::hangup()).expect("Failed to subscribe to SIGHUP");
+        let socket_path = self.socket_path.clone();
 
         async move {
             loop {
+                let sync_deadline = self.router.sync_deadline();
+
                 select! {
                     // Accept new connection requests
                     client = listener.accept().fuse() => {
@@ -105,7 +218,7 @@ impl Logger {
                                     Ok(credentials) => {
                                         info!("New connection request: {credentials:?}");
 
-                                        let client_machine = Machine::init((rpc, credentials, self.clients.clone(), self.log_sender.clone()))
+                                        let client_machine = Machine::init((rpc, credentials, self.clients.clone(), self.levels.clone(), self.log_sender.clone(), self.args.queue_overflow))
                                             .then(Self::authorize)
                                             .then(Client::run)
                                             .unwrap(Self::client_name);
@@ -141,35 +254,299 @@ impl Logger {
                     log_message = self.log_receiver.next() => {
                         match log_message {
                             Some(message) => {
-                                if let Some(rotated_file) = self.writer.log_message(message) {
+                                self.handle_log_event(message, &mut event_sender, &mut tail_sender).await;
 
-                                if event_sender.send(Event::Rotated(rotated_file)).await.is_err() {
-                                    error!("Event channel receiver is closed");
+                                // Coalesce queued messages into a single file write
+                                for _ in 1..self.args.queue_size {
+                                    match self.log_receiver.try_recv() {
+                                        Ok(message) => self.handle_log_event(message, &mut event_sender, &mut tail_sender).await,
+                                        _ => break,
+                                    }
                                 }
-                            }},
+
+                                self.router.flush();
+                            },
                             _ => warn!("Failed to receive log message through the channel")
                         }
                     },
-                    _ = tokio::signal::ctrl_c().fuse() => return
+                    _ = Self::sleep_until(sync_deadline).fuse() => {
+                        self.router.sync_expired();
+                    },
+                    _ = rotate_check.tick().fuse() => {
+                        for (service_name, rotation) in self.router.check_rotate_period() {
+                            self.handle_rotation(service_name, rotation, None, &mut event_sender).await;
+                        }
+                    },
+                    Some(rotated) = self.compressions.next() => {
+                        Self::send_rotated(rotated, &mut event_sender).await;
+
+                        // Compressed logs are skipped by the retention until ready
+                        Self::handle_removed_files(self.router.remove_old_logs(), &mut event_sender).await;
+                    },
+                    Some(command) = self.command_receiver.next() => {
+                        self.handle_command(command, &mut event_sender).await;
+                    },
+                    _ = retention_check.tick().fuse() => {
+                        self.router.close_idle_files();
+                        Self::handle_removed_files(self.router.remove_old_logs(), &mut event_sender).await;
+                    },
+                    _ = reload_signal.recv().fuse() => {
+                        self.reload().await;
+                    },
+                    _ = tokio::signal::ctrl_c().fuse() => {
+                        self.router.sync();
+                        return
+                    }
                 }
             }
         }
         .await;
 
         // Cleanup socket
-        let _ = std::fs::remove_file(&self.socket_path);
+        let _ = std::fs::remove_file(&socket_path);
+    }
+
+    /// Reload the config file and reopen log files. Client connections are kept.
+    /// Current settings are kept if the config is invalid or log files can't be opened
+    async fn reload(&mut self) {
+        info!("Reloading config");
+
+        let mut args = match self.cli_args.with_config() {
+            Ok(args) => args,
+            Err(e) => {
+                error!("Failed to reload config. Keeping current settings: {e}");
+                return;
+            }
+        };
+
+        // Reopens log files, which also picks up files moved by external log rotation
+        let mut router = match Router::new(&args) {
+            Ok(router) => router,
+            Err(e) => {
+                error!("Failed to reload config. Keeping current settings: {e}");
+                return;
+            }
+        };
+        router.keep_file_starts(&self.router);
+
+        let changes = self.args.diff(&args);
+        if changes.is_empty() {
+            info!("Config is unchanged");
+        }
+
+        for change in changes {
+            info!("Config changed: {change}");
+        }
+
+        if args.socket_path != self.args.socket_path {
+            warn!("Socket path change requires restart");
+        }
+
+        if args.levels_location != self.args.levels_location {
+            warn!("Levels location change requires restart");
+        }
+
+        if args.queue_size != self.args.queue_size
+            || args.queue_overflow != self.args.queue_overflow
+        {
+            warn!("Queue settings change requires restart");
+            args.queue_size = self.args.queue_size;
+            args.queue_overflow = self.args.queue_overflow;
+        }
+
+        log::set_max_level(args.log_level);
+
+        self.router = router;
+        self.levels.lock().await.set_defaults(args.service_levels());
+        *self.search.lock().await = LogSearch::new(&args);
+        self.ring
+            .lock()
+            .await
+            .set_limits(args.ring_size, args.ring_bytes);
+
+        // Keep buffered trigger context if the policies haven't changed
+        if args.file_level != self.args.file_level || args.trigger_flush != self.args.trigger_flush
+        {
+            self.triggers = TriggerFilter::new(args.file_level, &args.trigger_flush);
+        }
+
+        self.args = args;
+    }
+
+    /// Write log message into the ring buffer and the log files. Messages written into the log
+    /// files are sent to the tail subscribers. Written lines are buffered until [Router::flush]
+    async fn handle_log_event(
+        &mut self,
+        message: LogEvent,
+        event_sender: &mut Sender<Event>,
+        tail_sender: &mut Sender<TailEvent>,
+    ) {
+        {
+            let mut ring = self.ring.lock().await;
+            if ring.is_enabled() {
+                ring.push(Self::record(&message));
+            }
+        }
+
+        for message in self.triggers.filter(message) {
+            // Tail the messages written into the log files
+            let tail_event = Self::tail_event(&message, &self.tail).await;
+
+            for (service_name, rotation) in self.router.log_message(message) {
+                self.handle_rotation(service_name, rotation, None, event_sender)
+                    .await;
+            }
+
+            // Drop tail events if subscribers can't keep up rather than blocking log writing
+            if let Some(tail_event) = tail_event {
+                let _ = tail_sender.try_send(tail_event);
+            }
+        }
+    }
+
+    /// Sleep until the **deadline** if set, or forever otherwise
+    async fn sleep_until(deadline: Option<std::time::Instant>) {
+        match deadline {
+            Some(deadline) => time::sleep_until(deadline.into()).await,
+            None => pending().await,
+        }
+    }
+
+    /// Make a live tail event for the subscribers who want the message
+    async fn tail_event(message: &LogEvent, tail: &TailSubscriptionsType) -> Option<TailEvent> {
+        let subscribers = tail
+            .lock()
+            .await
+            .subscribers(&message.service_name, &message.message);
+
+        if subscribers.is_empty() {
+            return None;
+        }
+
+        Some(TailEvent {
+            subscribers,
+            record: Self::record(message),
+        })
+    }
+
+    fn record(message: &LogEvent) -> LogRecord {
+        LogRecord {
+            pid: message.pid,
+            service_name: message.service_name.clone(),
+            message: message.message.clone(),
+        }
+    }
+
+    async fn handle_command(&mut self, command: Command, event_sender: &mut Sender<Event>) {
+        match command {
+            Command::Rotate {
+                service_name,
+                reply,
+            } => {
+                info!("Log rotation requested for {service_name:?}");
+
+                match self.router.rotate(service_name.as_deref()) {
+                    Ok(rotation) => {
+                        self.handle_rotation(service_name, rotation, Some(reply), event_sender)
+                            .await;
+                    }
+                    Err(e) => {
+                        warn!("Failed to rotate log: {e}");
+                        let _ = reply.send(Err(e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Notify subscribers and the **reply** sender about log rotation, and subscribers about
+    /// removed old logs. If the rotated file is being compressed, rotation is reported once
+    /// the compressed file is ready
+    async fn handle_rotation(
+        &mut self,
+        service_name: Option<String>,
+        rotation: Rotation,
+        reply: Option<RotateReply>,
+        event_sender: &mut Sender<Event>,
+    ) {
+        let rotated_file = rotation.rotated_file;
+
+        match rotation.compression {
+            Some(compression) => self.compressions.push(Box::pin(async move {
+                // Compression thread is gone. The file is kept uncompressed
+                let file_name = compression.await.unwrap_or(rotated_file);
+
+                if let Some(reply) = reply {
+                    let _ = reply.send(Ok(file_name.clone()));
+                }
+
+                LogRotated {
+                    service_name,
+                    file_name,
+                }
+            })),
+            None => {
+                if let Some(reply) = reply {
+                    let _ = reply.send(Ok(rotated_file.clone()));
+                }
+
+                let rotated = LogRotated {
+                    service_name,
+                    file_name: rotated_file,
+                };
+
+                Self::send_rotated(rotated, event_sender).await
+            }
+        }
+
+        Self::handle_removed_files(rotation.removed_files, event_sender).await;
+    }
+
+    async fn send_rotated(rotated: LogRotated, event_sender: &mut Sender<Event>) {
+        debug!("Log file rotated into '{}'", rotated.file_name);
+
+        if event_sender.send(Event::Rotated(rotated)).await.is_err() {
+            error!("Event channel receiver is closed");
+        }
+    }
+
+    async fn handle_removed_files(removed_files: Vec<String>, event_sender: &mut Sender<Event>) {
+        for removed_file in removed_files {
+            info!("Removed old log file '{removed_file}'");
+
+            if event_sender
+                .send(Event::Removed(removed_file))
+                .await
+                .is_err()
+            {
+                error!("Event channel receiver is closed");
+            }
+        }
     }
 
     async fn authorize(
-        (mut rpc, credentials, clients, log_sender): (
+        (mut rpc, credentials, clients, levels, log_sender, queue_overflow): (
             Rpc,
             UCred,
             ClientRegistryType,
+            LevelStoreType,
+            Sender<LogEvent>,
+            QueueOverflow,
+        ),
+    ) -> std::result::Result<
+        (
+            String,
+            Rpc,
+            Arc<ClientStats>,
             Sender<LogEvent>,
+            QueueOverflow,
         ),
-    ) -> std::result::Result<(unix::pid_t, String, Rpc, Sender<LogEvent>), ()> {
+        (),
+    > {
         debug!("New client connection. Waiting for an auth message");
 
+        let stats = Arc::new(ClientStats::new(&credentials));
+
         // Authorize the client
         let service_name = match rpc.poll().await {
             Some(mut request) => {
@@ -192,13 +569,24 @@ impl Logger {
                                 match Self::handle_auth_request(
                                     &service_name,
                                     &request,
+                                    stats.clone(),
                                     clients.clone(),
                                 )
                                 .await
                                 {
                                     Ok(_) => {
                                         info!("Succesfully authorized {service_name}");
-                                        request.respond(Ok(())).await;
+                                        request
+                                            .respond(Ok(RegisterResponse { log_batch: true }))
+                                            .await;
+
+                                        Self::apply_stored_level(
+                                            &service_name,
+                                            &request,
+                                            clients,
+                                            levels,
+                                        )
+                                        .await;
 
                                         service_name
                                     }
@@ -244,7 +632,63 @@ impl Logger {
             }
         };
 
-        Ok((credentials.pid().unwrap(), service_name, rpc, log_sender))
+        Ok((service_name, rpc, stats, log_sender, queue_overflow))
+    }
+
+    /// Push persisted log level to a just registered client. Active temporary level change
+    /// is applied again, e.g. if the logger restarted during the change, until it expires
+    async fn apply_stored_level(
+        service_name: &str,
+        request: &RpcRequest,
+        clients: ClientRegistryType,
+        levels: LevelStoreType,
+    ) {
+        let (stored_level, save, active_temporary) = {
+            let mut levels = levels.lock().await;
+
+            match levels.temporary(service_name) {
+                Some(temporary) if !temporary.remaining().is_zero() => (
+                    SetLogLevel::new(service_name, temporary.log_level.clone()),
+                    None,
+                    Some(temporary),
+                ),
+                // Expired while the logger was down
+                Some(_) => match levels.take_temporary(service_name, None) {
+                    Some((temporary, save)) => {
+                        (temporary.revert_message(service_name), Some(save), None)
+                    }
+                    None => return,
+                },
+                None => match levels.get(service_name) {
+                    Some(stored_level) => (stored_level, None, None),
+                    None => return,
+                },
+            }
+        };
+
+        if let Some(save) = save {
+            save.write_in_background().await;
+        }
+
+        debug!("Applying stored log level to {service_name}: {stored_level:?}");
+
+        if let Err(e) = request
+            .writer()
+            .send_message(SET_LOG_LEVEL_METHOD_NAME, &stored_level)
+            .await
+        {
+            warn!("Failed to apply stored log level to {service_name}: {e:?}");
+        }
+
+        if let Some(temporary) = active_temporary {
+            LoggerService::schedule_revert(
+                service_name.into(),
+                temporary.id,
+                temporary.remaining(),
+                clients,
+                levels,
+            );
+        }
     }
 
     fn client_name(status: std::result::Result<String, ()>) -> Option<String> {
@@ -258,6 +702,7 @@ impl Logger {
     async fn handle_auth_request(
         service_name: &str,
         request: &RpcRequest,
+        stats: Arc<ClientStats>,
         clients: ClientRegistryType,
     ) -> Result<()> {
         debug!("Service registration request: {}", service_name);
@@ -274,7 +719,13 @@ impl Logger {
             Err(Error::AlreadyRegistered)
         // The only valid Auth request path
         } else {
-            clients_lock.insert(service_name.to_owned(), request.writer().clone());
+            clients_lock.insert(
+                service_name.to_owned(),
+                ClientHandle {
+                    writer: request.writer().clone(),
+                    stats,
+                },
+            );
 
             info!("Client authorized as: {}", service_name);
 
@@ -6,34 +6,160 @@
 //! Usage: krossbar-logger [OPTIONS]
 //!
 //! Options:
+//! -c, --config <CONFIG>
+//!         TOML config file. Reloaded on SIGHUP. Command line flags take precedence over the config file settings
 //! -l, --log-level <LOG_LEVEL>
 //!         Logger self log level: OFF, ERROR, WARN, INFO, DEBUG, TRACE [default: DEBUG]
+//!     --socket-path <SOCKET_PATH>
+//!         Logger socket path [default: /var/run/krossbar.logger.socket]
 //!     --log-location <LOG_LOCATION>
 //!         Log file location [default: /var/log/krossbar/krossbar.log]
+//!     --levels-location <LEVELS_LOCATION>
+//!         Persisted service log levels location. Levels set with `krossbar-log-control` are stored here and re-applied when services reconnect [default: /var/lib/krossbar/log_levels.json]
 //! -n, --num-bytes-rotate <NUM_BYTES_ROTATE>
 //!         Max log file size in bytes [default: 1000000]
 //! -k, --keep-num-files <KEEP_NUM_FILES>
 //!         How many rotated log files to keep [default: 10]
+//!     --rotate-period <ROTATE_PERIOD>
+//!         Rotate log file periodically even if size limit is not reached, e.g. 30m, 1h, 1d
+//!     --max-age <MAX_AGE>
+//!         Remove rotated log files older than the given age, e.g. 12h, 7d
+//!     --max-total-bytes <MAX_TOTAL_BYTES>
+//!         Max total size of the live and rotated log files in bytes. Takes precedence over other retention policies
+//!     --fsync-period <FSYNC_PERIOD>
+//!         Fsync log files at least this often if there's unsynced data, e.g. 100ms, 1s
+//!     --fsync-bytes <FSYNC_BYTES>
+//!         Fsync log files once the given number of bytes is written since the last fsync
+//!     --fsync-level <FSYNC_LEVEL>
+//!         Fsync log files right after writing a message at the given level or above, e.g. ERROR guarantees errors survive a power cut
+//! -f, --format <FORMAT>
+//!         Log file format [default: text] [possible values: text, json]
+//!     --line-template <LINE_TEMPLATE>
+//!         Text log line template. Placeholders: {ts:FORMAT}, {ts_utc:FORMAT}, {service}, {pid}, {level}, {target}, {message}, {fields}, {location} [default: "<{ts:%d-%m-%Y %H:%M:%S%.3f}> {service}#{pid} [{level}] {target} > {message}{fields}{location}"]
+//!     --per-service-files
+//!         Additionally write each service messages into a separate `<service name>.<ext>` file next to the combined log. Each file is rotated separately. Services, which can't have own files, e.g. because of file name conflicts, are written into the combined log
+//!     --no-combined-log
+//!         Don't write the combined log file in per-service files mode
+//!     --compress <COMPRESS>
+//!         Compress rotated log files in the background [default: none] [possible values: none, gzip, zstd]
+//!     --compress-level <COMPRESS_LEVEL>
+//!         Compression level: 0-9 for gzip, 1-22 for zstd. Codec default if not set
+//!     --ring-size <RING_SIZE>
+//!         Number of recent messages to keep in memory for `krossbar-log-control dump`. 0 disables the buffer [default: 1000]
+//!     --ring-bytes <RING_BYTES>
+//!         Max approximate size of the messages kept in memory in bytes
+//!     --file-level <FILE_LEVEL>
+//!         Minimum level of messages written into log files. Messages below the level are kept in memory only: OFF, ERROR, WARN, INFO, DEBUG, TRACE [default: TRACE]
+//!     --queue-size <QUEUE_SIZE>
+//!         Max number of client messages queued to be written. Change requires restart [default: 100]
+//!     --queue-overflow <QUEUE_OVERFLOW>
+//!         What to do with client messages if the queue is full [default: block] [possible values: block, drop]
+//!     --trigger-flush <TRIGGER_FLUSH>
+//!         Per-service trigger flush policy: `<SERVICE>[:file_level=<LEVEL>,trigger=<LEVEL>,context=<NUM>]`. Service messages below `file_level` [default: INFO] are kept in memory, and the last `context` [default: 100] of them are written into the log files right before a message at `trigger` level [default: ERROR] or above. Overrides `--file-level` for the service. Flushed messages keep their timestamps, but are written after the messages logged since they were buffered, so the log files are not in chronological order around them. Can be repeated
 //! -h, --help
 //!         Print help
 //! -V, --version
 //!         Print version
 //! ```
 //!
+//! # Config file
+//!
+//! All settings can be set in a TOML config file passed with `--config`. Flags given on the command line take precedence over the file.
+//! On SIGHUP the logger reloads the config file and reopens log files without dropping client connections, so external log rotation works too.
+//! Changed settings are logged. Socket path, levels location, and queue settings changes require restart.
+//! If the new log files can't be opened, current settings are kept.
+//!
+//! `[[services]]` entries set a default service log level, which is applied when the service registers unless a level is set
+//! with `krossbar-log-control`, and a dedicated service log file, which is written in addition to the combined log.
+//! Service log files, dedicated or per-service, can have own rotation and retention settings. Settings, which are not set, are taken
+//! from the `[rotation]` and `[retention]` sections.
+//!
+//! ```toml
+//! log_level = "info"
+//! socket_path = "/var/run/krossbar.logger.socket"
+//! levels_location = "/var/lib/krossbar/log_levels.json"
+//! file_level = "trace"
+//!
+//! [output]
+//! log_location = "/var/log/krossbar/krossbar.log"
+//! format = "text"
+//! line_template = "<{ts:%d-%m-%Y %H:%M:%S%.3f}> {service}#{pid} [{level}] {target} > {message}{fields}{location}"
+//! per_service_files = true
+//! no_combined_log = false
+//!
+//! [rotation]
+//! num_bytes = 1000000
+//! period = "1d"
+//! keep_num_files = 10
+//! compress = "gzip"
+//! compress_level = 6
+//!
+//! [retention]
+//! max_age = "7d"
+//! max_total_bytes = 100000000
+//!
+//! [durability]
+//! fsync_period = "1s"
+//! fsync_level = "error"
+//!
+//! [ring]
+//! size = 1000
+//! bytes = 1000000
+//!
+//! [queue]
+//! size = 100
+//! overflow = "block"
+//!
+//! [[trigger_flush]]
+//! service = "com.examples.service"
+//! file_level = "info"
+//! trigger = "error"
+//! context = 100
+//!
+//! [[services]]
+//! service = "com.examples.service"
+//! level = "debug"
+//! directives = "net=trace"
+//! log_location = "/var/log/krossbar/com.examples.service.log"
+//! num_bytes_rotate = 5000000
+//! keep_num_files = 3
+//! max_age = "30d"
+//! max_total_bytes = 20000000
+//! ```
+//!
+//! # Durability
+//!
+//! Queued messages are written into log files in batches through a buffer, so high message rates don't cost a write per message.
+//! Log files are not fsynced by default. Use `--fsync-period`, `--fsync-bytes`, and `--fsync-level` to fsync periodically, after a number of written bytes,
+//! or right after important messages. E.g. `--fsync-level error` makes sure errors survive a power cut.
+//! Buffered messages are always written and synced before rotation.
+//!
+//! Client messages wait to be written in a queue of `--queue-size` messages. If the queue is full, the logger stops reading the client
+//! until there's space, or drops new messages with `--queue-overflow drop`. Dropped messages are reported as the client `N messages dropped` warning.
+//!
 
 mod args;
 mod client;
+mod config;
+mod levels;
 mod logger;
+mod query;
+mod ring;
 mod rotator;
+mod router;
 mod self_logger;
 mod service;
+mod tail;
+mod trigger;
 mod writer;
 
-use clap::Parser;
+use std::sync::Arc;
+
 use log::*;
 
-use krossbar_log_common::{log_message::LogMessage, DEFAULT_LOGGER_SOCKET_PATH};
+use krossbar_log_common::log_message::LogMessage;
 
+use client::ClientStats;
 use logger::Logger;
 use tokio::net::unix;
 
@@ -41,15 +167,26 @@ pub struct LogEvent {
     pub pid: unix::pid_t,
     pub service_name: String,
     pub message: LogMessage,
+    /// Sender stats to count written bytes. [None] for the logger own messages
+    pub stats: Option<Arc<ClientStats>>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Starting Krossbar logging service");
 
-    let args = args::Args::parse();
+    let args = args::Args::from_command_line();
+
+    // Validate the config before starting
+    let socket_path = match args.with_config() {
+        Ok(config_args) => config_args.socket_path,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
 
-    let logger = Logger::new(args, DEFAULT_LOGGER_SOCKET_PATH.into());
+    let logger = Logger::new(args, socket_path.into());
     info!("Succesfully started logging service. Listening for messages");
 
     logger.run().await;
@@ -0,0 +1,293 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, TimeDelta, Timelike};
+use flate2::read::GzDecoder;
+use log::warn;
+use regex::Regex;
+use serde::Deserialize;
+
+use krossbar_log_common::{
+    line_template::LineTemplate,
+    log_message::{LogMessage, LogRecord},
+    logger_interface::{LogQuery, QueryCursor, QueryPage, MAX_QUERY_PAGE_SIZE},
+    naming::{LogNaming, RotatedLogId},
+    GZIP_EXTENSION, ZSTD_EXTENSION,
+};
+
+use crate::{
+    args::{Args, LogFormat},
+    router,
+};
+
+/// JSON Lines log record
+#[derive(Deserialize)]
+struct JsonLogLine {
+    pid: i32,
+    service_name: String,
+    #[serde(flatten)]
+    message: LogMessage,
+}
+
+/// Log file to search
+struct LogFile {
+    /// Rotated log identity. [None] for the live log file
+    rotated: Option<RotatedLogId>,
+    path: PathBuf,
+}
+
+/// Compiled query conditions
+struct Matcher<'a> {
+    query: &'a LogQuery,
+    regex: Option<Regex>,
+}
+
+impl<'a> Matcher<'a> {
+    fn new(query: &'a LogQuery) -> Result<Self, String> {
+        let regex = match (&query.pattern, query.regex) {
+            (Some(pattern), true) => Some(
+                Regex::new(pattern).map_err(|e| format!("Invalid query regex '{pattern}': {e}"))?,
+            ),
+            _ => None,
+        };
+
+        Ok(Self { query, regex })
+    }
+
+    fn matches(&self, record: &LogRecord) -> bool {
+        let query = self.query;
+        let message = &record.message;
+
+        if message.level > query.level
+            || query
+                .from
+                .map(|from| message.timestamp < from)
+                .unwrap_or(false)
+            || query.to.map(|to| message.timestamp > to).unwrap_or(false)
+        {
+            return false;
+        }
+
+        if let Some(ref service_name) = query.service_name {
+            if service_name != &record.service_name {
+                return false;
+            }
+        }
+
+        if let Some(ref target_prefix) = query.target_prefix {
+            if !message.target.starts_with(target_prefix) {
+                return false;
+            }
+        }
+
+        match (&self.regex, &query.pattern) {
+            (Some(regex), _) => regex.is_match(&message.message),
+            (None, Some(pattern)) => message.message.contains(pattern),
+            (None, None) => true,
+        }
+    }
+}
+
+/// Searches live and rotated log files, including compressed ones
+#[derive(Clone)]
+pub struct LogSearch {
+    log_location: PathBuf,
+    combined_log: bool,
+    per_service_files: bool,
+    /// Dedicated service log files
+    sinks: HashMap<String, PathBuf>,
+    format: LogFormat,
+    line_template: LineTemplate,
+}
+
+impl LogSearch {
+    pub fn new(args: &Args) -> Self {
+        Self {
+            log_location: PathBuf::from(&args.log_location),
+            combined_log: !(args.per_service_files && args.no_combined_log),
+            per_service_files: args.per_service_files,
+            sinks: args.service_sinks(),
+            format: args.format,
+            line_template: args.line_template.clone(),
+        }
+    }
+
+    /// Find a page of records matching the query. Lines, which can't be parsed, are skipped
+    pub fn query(&self, query: &LogQuery) -> Result<QueryPage, String> {
+        let matcher = Matcher::new(query)?;
+        let page_size = query.page_size.clamp(1, MAX_QUERY_PAGE_SIZE);
+
+        let created_at = Local::now().naive_local();
+        let files = self.log_files(query.service_name.as_deref())?;
+        let (first_file, first_line) = Self::resolve_cursor(&files, query.cursor.as_ref());
+
+        let mut records = vec![];
+
+        for (index, file) in files.iter().enumerate().skip(first_file) {
+            // Records are written before rotation, so rotated file can't have newer records.
+            // Rotation timestamps are truncated to seconds
+            if let (Some(from), Some(rotated)) = (query.from, file.rotated) {
+                if rotated.timestamp + TimeDelta::seconds(1) < from.naive_local() {
+                    continue;
+                }
+            }
+
+            let reader = match Self::open(&file.path) {
+                Ok(reader) => reader,
+                Err(e) => {
+                    warn!("Failed to open log file {:?} for a query: {e}", file.path);
+                    continue;
+                }
+            };
+
+            let skip = if index == first_file { first_line } else { 0 };
+
+            for (line_number, line) in reader.split(b'\n').enumerate().skip(skip as usize) {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        warn!("Failed to read log file {:?}: {e}", file.path);
+                        break;
+                    }
+                };
+
+                let record = match self.parse(&String::from_utf8_lossy(&line)) {
+                    Some(record) if matcher.matches(&record) => record,
+                    _ => continue,
+                };
+
+                if records.len() == page_size {
+                    return Ok(QueryPage {
+                        records,
+                        next: Some(QueryCursor {
+                            rotated_at: file.rotated.map(|rotated| rotated.timestamp),
+                            rotated_index: file.rotated.map_or(0, |rotated| rotated.index),
+                            line: line_number as u64,
+                            created_at,
+                        }),
+                    });
+                }
+
+                records.push(record);
+            }
+        }
+
+        Ok(QueryPage {
+            records,
+            next: None,
+        })
+    }
+
+    fn parse(&self, line: &str) -> Option<LogRecord> {
+        match self.format {
+            LogFormat::Text => self.line_template.parse_record(line),
+            LogFormat::Json => {
+                serde_json::from_str::<JsonLogLine>(line)
+                    .ok()
+                    .map(|line| LogRecord {
+                        pid: line.pid,
+                        service_name: line.service_name,
+                        message: line.message,
+                    })
+            }
+        }
+    }
+
+    /// Log files to search from the oldest to the live one. Service log is searched
+    /// if the query is for a particular service, which has a dedicated log file,
+    /// or per-service files are enabled
+    fn log_files(&self, service_name: Option<&str>) -> Result<Vec<LogFile>, String> {
+        let log_location = match service_name {
+            Some(service_name) if self.sinks.contains_key(service_name) => {
+                self.sinks[service_name].clone()
+            }
+            Some(service_name) if self.per_service_files => {
+                router::service_log_location(&self.log_location, service_name)
+            }
+            _ if self.combined_log => self.log_location.clone(),
+            _ => return Err("Combined log file is disabled. Set a service name to search".into()),
+        };
+
+        let naming = LogNaming::new(&log_location);
+        let logs_dir = log_location
+            .parent()
+            .ok_or_else(|| "Failed to extract log dir from log file path".to_owned())?;
+
+        let rotated_logs = naming
+            .list_rotated_logs(logs_dir)
+            .map_err(|e| format!("Failed to list log dir: {e}"))?;
+
+        let mut files: Vec<LogFile> = rotated_logs
+            .into_iter()
+            .map(|(rotated, path)| LogFile {
+                rotated: Some(rotated),
+                path,
+            })
+            .collect();
+
+        if log_location.exists() {
+            files.push(LogFile {
+                rotated: None,
+                path: log_location,
+            });
+        }
+
+        Ok(files)
+    }
+
+    /// First file index and line to search from
+    fn resolve_cursor(files: &[LogFile], cursor: Option<&QueryCursor>) -> (usize, u64) {
+        let cursor = match cursor {
+            Some(cursor) => cursor,
+            None => return (0, 0),
+        };
+
+        let cursor_rotated = cursor.rotated_at.map(|timestamp| RotatedLogId {
+            timestamp,
+            index: cursor.rotated_index,
+        });
+
+        let position = match cursor_rotated {
+            // Rotated file may be removed by retention policies. Continue with the next one
+            Some(rotated) => files.iter().position(|file| match file.rotated {
+                Some(file_rotated) => file_rotated >= rotated,
+                None => true,
+            }),
+            // If the live log was rotated after the cursor was made, it's the first
+            // file rotated since
+            None => {
+                let created_at = cursor
+                    .created_at
+                    .with_nanosecond(0)
+                    .unwrap_or(cursor.created_at);
+
+                files.iter().position(|file| match file.rotated {
+                    Some(rotated) => rotated.timestamp >= created_at,
+                    None => true,
+                })
+            }
+        };
+
+        match position {
+            Some(position) if files[position].rotated == cursor_rotated => (position, cursor.line),
+            Some(position) if cursor.rotated_at.is_none() => (position, cursor.line),
+            Some(position) => (position, 0),
+            None => (files.len(), 0),
+        }
+    }
+
+    fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+        let file = File::open(path)?;
+
+        match path.extension().and_then(OsStr::to_str) {
+            Some(GZIP_EXTENSION) => Ok(Box::new(BufReader::new(GzDecoder::new(file)))),
+            Some(ZSTD_EXTENSION) => Ok(Box::new(BufReader::new(zstd::Decoder::new(file)?))),
+            _ => Ok(Box::new(BufReader::new(file))),
+        }
+    }
+}
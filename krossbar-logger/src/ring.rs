@@ -0,0 +1,94 @@
+use std::{collections::VecDeque, mem::size_of};
+
+use krossbar_log_common::log_message::{FieldsDisplay, LogRecord};
+
+/// Recent records kept in memory, including the ones not written into log files.
+/// The oldest records are dropped when the buffer exceeds either of the limits
+pub struct RingBuffer {
+    max_records: usize,
+    max_bytes: Option<u64>,
+    num_bytes: u64,
+    /// Records with their approximate size
+    records: VecDeque<(LogRecord, u64)>,
+}
+
+impl RingBuffer {
+    pub fn new(max_records: usize, max_bytes: Option<u64>) -> Self {
+        Self {
+            max_records,
+            max_bytes,
+            num_bytes: 0,
+            records: VecDeque::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_records > 0 && self.max_bytes != Some(0)
+    }
+
+    /// Update the limits dropping the oldest records if the buffer exceeds them
+    pub fn set_limits(&mut self, max_records: usize, max_bytes: Option<u64>) {
+        self.max_records = max_records;
+        self.max_bytes = max_bytes;
+
+        self.shrink();
+    }
+
+    pub fn push(&mut self, record: LogRecord) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let num_bytes = Self::record_num_bytes(&record);
+
+        self.num_bytes += num_bytes;
+        self.records.push_back((record, num_bytes));
+
+        self.shrink();
+    }
+
+    fn shrink(&mut self) {
+        while self.records.len() > self.max_records
+            || self
+                .max_bytes
+                .map(|max_bytes| self.num_bytes > max_bytes)
+                .unwrap_or(false)
+        {
+            match self.records.pop_front() {
+                Some((_, num_bytes)) => self.num_bytes -= num_bytes,
+                None => break,
+            }
+        }
+    }
+
+    /// Buffered records from the oldest. Returns only the service records if **service_name** is set
+    pub fn records(&self, service_name: Option<&str>) -> Vec<LogRecord> {
+        self.records
+            .iter()
+            .map(|(record, _)| record)
+            .filter(|record| {
+                service_name
+                    .map(|service_name| record.service_name == service_name)
+                    .unwrap_or(true)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Approximate record size in memory
+    fn record_num_bytes(record: &LogRecord) -> u64 {
+        let message = &record.message;
+
+        let fields_len = if message.fields.is_empty() {
+            0
+        } else {
+            FieldsDisplay(&message.fields).to_string().len()
+        };
+
+        (size_of::<LogRecord>()
+            + record.service_name.len()
+            + message.target.len()
+            + message.message.len()
+            + fields_len) as u64
+    }
+}
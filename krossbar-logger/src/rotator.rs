@@ -1,109 +1,376 @@
 use std::{
-    ffi::OsStr,
-    fs::{remove_file, rename},
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet},
+    fs::{remove_file, rename, File},
+    io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex, OnceLock},
+    thread,
+    time::{Duration, SystemTime},
 };
 
-use chrono::Local;
-use krossbar_log_common::ROTATED_LOG_TIMESTAMP_FORMAT;
+use chrono::{DateTime, Local};
+use flate2::write::GzEncoder;
+use futures::channel::oneshot;
+use krossbar_log_common::{
+    naming::{LogNaming, RotatedLogId},
+    GZIP_EXTENSION, ZSTD_EXTENSION,
+};
+
+use crate::args::Compression;
+
+/// Rotation result
+pub struct Rotation {
+    /// Rotated file path. If compression is enabled, it's the uncompressed file, which
+    /// is removed after compression
+    pub rotated_file: String,
+    /// Old log files removed by retention policies
+    pub removed_files: Vec<String>,
+    /// Resolves into the final rotated file path once the background compression is finished:
+    /// the compressed file, or the uncompressed one if compression failed.
+    /// [None] if compression is disabled
+    pub compression: Option<oneshot::Receiver<String>>,
+}
+
+/// Rotated files, which are queued or being compressed. Retention skips them
+static COMPRESSING: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Rotated file compression request
+struct CompressionJob {
+    source_path: PathBuf,
+    compression: Compression,
+    level: Option<u32>,
+    /// Receives the final rotated file path
+    done: oneshot::Sender<String>,
+}
+
+/// Background thread, which compresses rotated files one by one to not block log writing
+struct Compressor {
+    jobs: mpsc::Sender<CompressionJob>,
+}
+
+/// Rotated log. Includes both plain and compressed files if the log is being compressed
+#[derive(Default)]
+struct RotatedLog {
+    paths: Vec<PathBuf>,
+    num_bytes: u64,
+    modified: Option<SystemTime>,
+    /// Log is queued or being compressed and can't be removed yet
+    compressing: bool,
+}
 
+#[derive(Clone)]
 pub struct Rotator {
     keep_num_files: usize,
     log_location: PathBuf,
+    naming: LogNaming,
+    compression: Compression,
+    compression_level: Option<u32>,
+    max_age: Option<Duration>,
+    max_total_bytes: Option<u64>,
 }
 
 impl Rotator {
     pub fn new(keep_num_files: usize, log_location: PathBuf) -> Self {
         Self {
             keep_num_files,
+            naming: LogNaming::new(&log_location),
             log_location,
+            compression: Compression::None,
+            compression_level: None,
+            max_age: None,
+            max_total_bytes: None,
         }
     }
 
-    pub fn rotate(&self) -> String {
+    /// Additionally remove rotated logs older than `max_age`, and the oldest logs
+    /// if total log files size exceeds `max_total_bytes`
+    pub fn with_retention(
+        mut self,
+        max_age: Option<Duration>,
+        max_total_bytes: Option<u64>,
+    ) -> Self {
+        self.max_age = max_age;
+        self.max_total_bytes = max_total_bytes;
+        self
+    }
+
+    /// Compress rotated files. Codec default level is used if `level` is [None]
+    pub fn with_compression(mut self, compression: Compression, level: Option<u32>) -> Self {
+        self.compression = compression;
+        self.compression_level = level;
+        self
+    }
+
+    pub fn rotate(&self) -> Rotation {
         let time = Local::now();
 
         let logs_dir = match self.log_location.parent() {
             Some(log_dir) => log_dir.to_path_buf(),
             _ => {
                 eprintln!("Failed to extract log dir from log file path");
-                return "".into();
+                return Rotation {
+                    rotated_file: "".into(),
+                    removed_files: vec![],
+                    compression: None,
+                };
             }
         };
 
-        let rotated_file_name = format!(
-            "{}_{}.{}",
-            self.log_location
-                .as_path()
-                .file_stem()
-                .unwrap_or(OsStr::new("krossbar_log"))
-                .to_string_lossy(),
-            time.format(ROTATED_LOG_TIMESTAMP_FORMAT),
-            self.log_location
-                .as_path()
-                .extension()
-                .unwrap_or(OsStr::new("log"))
-                .to_string_lossy()
-        );
-
-        let rotated_file_path = logs_dir.join(rotated_file_name);
+        let rotated_file_path = self.free_rotated_path(&logs_dir, &time);
 
         if let Err(err) = rename(self.log_location.clone(), rotated_file_path.clone()) {
             eprintln!("Failed to rotate log file: {}", err.to_string())
         }
 
-        self.remove_old_logs(&logs_dir);
+        let compression = match self.compression {
+            Compression::None => None,
+            compression => Some(Compressor::global().compress(
+                rotated_file_path.clone(),
+                compression,
+                self.compression_level,
+            )),
+        };
+
+        Rotation {
+            rotated_file: format!("{}", rotated_file_path.to_string_lossy()),
+            removed_files: self.remove_old_logs(),
+            compression,
+        }
+    }
+
+    /// First rotated log path, which is not taken by a log rotated within the same second
+    fn free_rotated_path(&self, logs_dir: &Path, time: &DateTime<Local>) -> PathBuf {
+        let is_taken = |path: &PathBuf| {
+            path.exists()
+                || [GZIP_EXTENSION, ZSTD_EXTENSION].iter().any(|extension| {
+                    PathBuf::from(format!("{}.{extension}", path.display())).exists()
+                })
+        };
 
-        format!("{}", rotated_file_path.to_string_lossy())
+        (0..=u32::MAX)
+            .map(|index| logs_dir.join(self.naming.rotated_name(time, index)))
+            .find(|path| !is_taken(path))
+            .unwrap_or_else(|| logs_dir.join(self.naming.rotated_name(time, u32::MAX)))
     }
 
-    fn read_log_dir_files(logs_dir: &PathBuf) -> Vec<String> {
+    /// Read rotated logs sorted from the oldest to the newest.
+    /// Plain and compressed files of the same log are grouped together.
+    /// Files, which are not rotated logs of the live log, are ignored
+    fn read_rotated_logs(&self, logs_dir: &Path) -> Vec<RotatedLog> {
         let dir_iter = match logs_dir.read_dir() {
             Ok(dir_iter) => dir_iter,
             Err(err) => {
-                eprintln!(
-                    "Failed to list log dir: {}. Can't remove old logs",
-                    err.to_string()
-                );
+                eprintln!("Failed to list log dir: {err}. Can't remove old logs");
                 return vec![];
             }
         };
 
-        let mut log_files: Vec<String> = dir_iter
-            .into_iter()
-            .filter_map(|entry| match entry {
-                Ok(dir_entry) => {
-                    let metadata = dir_entry.metadata();
-                    if metadata.is_ok() && metadata.unwrap().is_file() {
-                        Some(dir_entry.file_name().to_string_lossy().into_owned())
-                    } else {
-                        None
-                    }
-                }
-                _ => None,
+        let mut rotated_logs: BTreeMap<RotatedLogId, RotatedLog> = BTreeMap::new();
+
+        for dir_entry in dir_iter.flatten() {
+            let metadata = match dir_entry.metadata() {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+
+            let id = match self
+                .naming
+                .parse_rotated_name(&dir_entry.file_name().to_string_lossy())
+            {
+                Some(id) => id,
+                _ => continue,
+            };
+
+            let rotated_log = rotated_logs.entry(id).or_default();
+            rotated_log.paths.push(dir_entry.path());
+            rotated_log.num_bytes += metadata.len();
+            rotated_log.modified = rotated_log.modified.max(metadata.modified().ok());
+        }
+
+        let compressing = COMPRESSING.lock().unwrap();
+
+        rotated_logs
+            .into_values()
+            .map(|mut log| {
+                log.compressing = log.paths.iter().any(|path| compressing.contains(path));
+                log
             })
-            .collect();
+            .collect()
+    }
+
+    /// Remove rotated logs, which don't fit any of the retention policies.
+    /// Logs being compressed are kept until the compression finishes.
+    /// Returns removed files
+    pub fn remove_old_logs(&self) -> Vec<String> {
+        let logs_dir = match self.log_location.parent() {
+            Some(log_dir) => log_dir,
+            _ => {
+                eprintln!("Failed to extract log dir from log file path");
+                return vec![];
+            }
+        };
+
+        let rotated_logs = self.read_rotated_logs(logs_dir);
+
+        let mut num_logs = rotated_logs.len();
+        let mut total_bytes = rotated_logs.iter().map(|log| log.num_bytes).sum::<u64>()
+            + self.log_location.metadata().map(|m| m.len()).unwrap_or(0);
 
-        log_files.sort();
-        log_files
+        let now = SystemTime::now();
+        let mut removed_files = vec![];
+
+        // Logs are sorted from the oldest one, so we stop at the first log to keep
+        for rotated_log in rotated_logs {
+            let too_many = num_logs > self.keep_num_files;
+            let too_old = match (self.max_age, rotated_log.modified) {
+                (Some(max_age), Some(modified)) => now
+                    .duration_since(modified)
+                    .map(|age| age > max_age)
+                    .unwrap_or(false),
+                _ => false,
+            };
+            let too_big = self
+                .max_total_bytes
+                .map(|max_total_bytes| total_bytes > max_total_bytes)
+                .unwrap_or(false);
+
+            if !too_many && !too_old && !too_big {
+                break;
+            }
+
+            // Can't be removed yet. Stop here to not remove newer logs instead of it.
+            // The retention run after the compression removes it
+            if rotated_log.compressing {
+                break;
+            }
+
+            for path in rotated_log.paths {
+                match remove_file(&path) {
+                    Ok(_) => removed_files.push(format!("{}", path.to_string_lossy())),
+                    Err(err) => eprintln!("Failed to remove old log file: {err}"),
+                }
+            }
+
+            num_logs -= 1;
+            total_bytes -= rotated_log.num_bytes;
+        }
+
+        removed_files
     }
+}
 
-    fn remove_old_logs(&self, logs_dir: &PathBuf) {
-        let log_files = Self::read_log_dir_files(logs_dir);
-
-        // Check if have somethig to delete.
-        // Note we've just rotated original log file, so we only have rotated files in the dir
-        if log_files.len() > self.keep_num_files {
-            let num_files_delete = log_files.len() - self.keep_num_files;
-
-            log_files
-                .into_iter()
-                .take(num_files_delete)
-                .for_each(|file| {
-                    if let Err(err) = remove_file(logs_dir.join(file).as_path()) {
-                        eprintln!("Failed to remove old lof file: {}", err.to_string())
-                    }
-                })
+impl Compressor {
+    /// Process-wide compressor. The thread is started on the first use
+    fn global() -> &'static Compressor {
+        static COMPRESSOR: OnceLock<Compressor> = OnceLock::new();
+
+        COMPRESSOR.get_or_init(|| {
+            let (jobs, receiver) = mpsc::channel::<CompressionJob>();
+
+            thread::Builder::new()
+                .name("log-compressor".into())
+                .spawn(move || receiver.into_iter().for_each(CompressionJob::run))
+                .expect("Failed to start log compression thread");
+
+            Self { jobs }
+        })
+    }
+
+    /// Queue rotated file compression. Returns final rotated file path receiver
+    fn compress(
+        &self,
+        source_path: PathBuf,
+        compression: Compression,
+        level: Option<u32>,
+    ) -> oneshot::Receiver<String> {
+        let (done, result) = oneshot::channel();
+        COMPRESSING.lock().unwrap().insert(source_path.clone());
+
+        let job = CompressionJob {
+            source_path,
+            compression,
+            level,
+            done,
+        };
+
+        if let Err(mpsc::SendError(job)) = self.jobs.send(job) {
+            eprintln!("Log compression thread is gone. Keeping rotated log file uncompressed");
+            job.finish(None);
         }
+
+        result
+    }
+}
+
+impl CompressionJob {
+    fn run(self) {
+        match self.compress() {
+            Ok(compressed_path) => self.finish(compressed_path),
+            Err(err) => {
+                eprintln!("Failed to compress rotated log file: {err}. Keeping it uncompressed");
+                self.finish(None)
+            }
+        }
+    }
+
+    /// Report the final rotated file path: **compressed_path** if some, the source otherwise
+    fn finish(self, compressed_path: Option<PathBuf>) {
+        COMPRESSING.lock().unwrap().remove(&self.source_path);
+
+        let path = compressed_path.unwrap_or(self.source_path);
+        let _ = self.done.send(format!("{}", path.to_string_lossy()));
+    }
+
+    /// Compress rotated log file into a temporary file, and move it into place once complete,
+    /// so a compressed file is never seen partially written. Removes the source afterwards.
+    /// Returns the compressed file path, or [None] if compression is disabled
+    fn compress(&self) -> io::Result<Option<PathBuf>> {
+        let Some(extension) = self.compression.extension() else {
+            return Ok(None);
+        };
+
+        let target_path = PathBuf::from(format!("{}.{extension}", self.source_path.display()));
+        let temp_path = PathBuf::from(format!("{}.tmp", target_path.display()));
+
+        if let Err(err) = self.encode(&temp_path) {
+            let _ = remove_file(&temp_path);
+            return Err(err);
+        }
+
+        rename(&temp_path, &target_path)?;
+        remove_file(&self.source_path)?;
+
+        Ok(Some(target_path))
+    }
+
+    fn encode(&self, target_path: &Path) -> io::Result<()> {
+        let mut source = File::open(&self.source_path)?;
+        let target = File::create(target_path)?;
+
+        match self.compression {
+            Compression::None => {}
+            Compression::Gzip => {
+                let level = self
+                    .level
+                    .map(|level| flate2::Compression::new(level.min(9)))
+                    .unwrap_or_default();
+
+                let mut encoder = GzEncoder::new(target, level);
+                io::copy(&mut source, &mut encoder)?;
+                encoder.finish()?.sync_all()?;
+            }
+            Compression::Zstd => {
+                let level = self
+                    .level
+                    .map(|level| level.min(22) as i32)
+                    .unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+
+                let mut encoder = zstd::Encoder::new(target, level)?;
+                io::copy(&mut source, &mut encoder)?;
+                encoder.finish()?.sync_all()?;
+            }
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,368 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use krossbar_log_common::naming::LogNaming;
+
+use crate::{args::Args, rotator::Rotation, writer::Writer, LogEvent};
+
+/// Per-service log files, which are not written for this long, are closed until the next message
+const SERVICE_FILE_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Common file name length limit in bytes
+const MAX_FILE_NAME_LEN: usize = 255;
+/// Room for the rotated log suffix `_<timestamp>_<index>` and the compression extensions
+const MAX_ROTATED_SUFFIX_LEN: usize = 40;
+/// Min service log file stem length to fit the name hash of truncated names
+const MIN_SERVICE_STEM_LEN: usize = 32;
+
+/// Routes log messages into the combined log file, per-service log files if enabled,
+/// and dedicated service log files from the config
+pub struct Router {
+    args: Args,
+    /// Write all messages into the combined log file. Otherwise the combined log file is only
+    /// opened for services, which can't have own log files
+    combined_log: bool,
+    combined_writer: Option<Writer>,
+    /// Per-service writers. Created on the first service message
+    service_writers: HashMap<String, Writer>,
+    /// Services, which can't have own log files. Their messages go into the combined log file
+    combined_services: HashSet<String>,
+    /// Dedicated service log files
+    sinks: HashMap<String, PathBuf>,
+    /// Start time of the log files opened before a config reload
+    file_starts: HashMap<PathBuf, Instant>,
+}
+
+impl Router {
+    /// Create router and open the combined log file. Fails if the file can't be opened
+    pub fn new(args: &Args) -> Result<Self, String> {
+        let combined_log = !(args.per_service_files && args.no_combined_log);
+
+        let combined_writer = if combined_log {
+            Some(
+                Writer::new(args, PathBuf::from(&args.log_location)).map_err(|e| {
+                    format!("Failed to open log file at {:?}: {e}", args.log_location)
+                })?,
+            )
+        } else {
+            None
+        };
+
+        Ok(Self {
+            args: args.clone(),
+            combined_log,
+            combined_writer,
+            service_writers: HashMap::new(),
+            combined_services: HashSet::new(),
+            sinks: args.service_sinks(),
+            file_starts: HashMap::new(),
+        })
+    }
+
+    /// Continue log files of the **previous** router from their start time,
+    /// so reloads don't postpone the periodic rotation
+    pub fn keep_file_starts(&mut self, previous: &Router) {
+        let file_starts: HashMap<PathBuf, Instant> = previous
+            .combined_writer
+            .iter()
+            .chain(previous.service_writers.values())
+            .map(|writer| {
+                (
+                    writer.log_location().to_path_buf(),
+                    writer.current_file_start(),
+                )
+            })
+            .collect();
+
+        for writer in self.writers_mut() {
+            if let Some(start) = file_starts.get(writer.log_location()) {
+                writer.set_current_file_start(*start)
+            }
+        }
+
+        self.file_starts = file_starts;
+    }
+
+    /// Write log message. Returns rotated files with the service names for per-service logs
+    pub fn log_message(&mut self, mut message: LogEvent) -> Vec<(Option<String>, Rotation)> {
+        let mut rotations = vec![];
+
+        if self.combined_log {
+            if let Some(writer) = self.combined_writer.as_mut() {
+                rotations.extend(
+                    writer
+                        .log_message(&message)
+                        .map(|rotation| (None, rotation)),
+                );
+
+                // Count written bytes once per message, not once per log file
+                message.stats = None;
+            }
+        }
+
+        if !self.args.per_service_files && !self.sinks.contains_key(&message.service_name) {
+            return rotations;
+        }
+
+        let service_name = message.service_name.clone();
+        let combined_log = self.combined_log;
+
+        let rotation = match self.service_writer(&service_name) {
+            Some(writer) => writer
+                .log_message(&message)
+                .map(|rotation| (Some(service_name), rotation)),
+            // Already written into the combined log file
+            None if combined_log => None,
+            None => self
+                .fallback_writer()
+                .and_then(|writer| writer.log_message(&message))
+                .map(|rotation| (None, rotation)),
+        };
+
+        rotations.extend(rotation);
+        rotations
+    }
+
+    /// Service log file writer. Opened on the first service message. [None] if the service
+    /// can't have own log file
+    fn service_writer(&mut self, service_name: &str) -> Option<&mut Writer> {
+        if self.combined_services.contains(service_name) {
+            return None;
+        }
+
+        if !self.service_writers.contains_key(service_name) {
+            match self.open_service_writer(service_name) {
+                Ok(writer) => {
+                    self.service_writers.insert(service_name.to_owned(), writer);
+                }
+                Err(e) => {
+                    eprintln!("Can't write '{service_name}' log file: {e}. Using the combined log");
+                    self.combined_services.insert(service_name.to_owned());
+                    return None;
+                }
+            }
+        }
+
+        let writer = self.service_writers.get_mut(service_name)?;
+        match writer.reopen() {
+            Ok(_) => Some(writer),
+            Err(e) => {
+                eprintln!(
+                    "Failed to reopen '{service_name}' log file: {e}. Using the combined log"
+                );
+                None
+            }
+        }
+    }
+
+    fn open_service_writer(&self, service_name: &str) -> Result<Writer, String> {
+        let combined_location = Path::new(&self.args.log_location);
+        let log_location = match self.sinks.get(service_name) {
+            Some(sink) => sink.clone(),
+            None => service_log_location(combined_location, service_name),
+        };
+
+        // Service log file can't replace other log files, or be taken for a rotated log
+        // by the retention policies
+        if log_location == combined_location
+            || looks_rotated(&log_location)
+            || self
+                .service_writers
+                .values()
+                .any(|writer| writer.log_location() == log_location)
+        {
+            return Err(format!(
+                "log file {log_location:?} conflicts with other log files"
+            ));
+        }
+
+        let start = self.file_starts.get(&log_location).copied();
+
+        let args = self.args.service_args(service_name);
+        let mut writer = Writer::new(&args, log_location).map_err(|e| e.to_string())?;
+        if let Some(start) = start {
+            writer.set_current_file_start(start);
+        }
+
+        Ok(writer)
+    }
+
+    /// Combined log file writer for services, which can't have own log files.
+    /// Opened on demand if the combined log file is disabled
+    fn fallback_writer(&mut self) -> Option<&mut Writer> {
+        if self.combined_writer.is_none() {
+            match Writer::new(&self.args, PathBuf::from(&self.args.log_location)) {
+                Ok(writer) => self.combined_writer = Some(writer),
+                Err(e) => eprintln!("Failed to open combined log file: {e}"),
+            }
+        }
+
+        self.combined_writer.as_mut()
+    }
+
+    /// Close per-service log files, which are not written for a while
+    pub fn close_idle_files(&mut self) {
+        for writer in self.service_writers.values_mut() {
+            writer.close_if_idle(SERVICE_FILE_IDLE_TIMEOUT)
+        }
+    }
+
+    /// Rotate log files, which are older than rotation period
+    pub fn check_rotate_period(&mut self) -> Vec<(Option<String>, Rotation)> {
+        let combined = self
+            .combined_writer
+            .as_mut()
+            .and_then(|writer| writer.check_rotate_period())
+            .map(|rotation| (None, rotation));
+
+        let services = self
+            .service_writers
+            .iter_mut()
+            .filter_map(|(service_name, writer)| {
+                writer
+                    .check_rotate_period()
+                    .map(|rotation| (Some(service_name.clone()), rotation))
+            });
+
+        combined.into_iter().chain(services).collect()
+    }
+
+    /// Rotate the combined log file if **service_name** is [None], or the service log file otherwise
+    pub fn rotate(&mut self, service_name: Option<&str>) -> Result<Rotation, String> {
+        let writer = match service_name {
+            Some(service_name) => self
+                .service_writers
+                .get_mut(service_name)
+                .ok_or_else(|| format!("Service '{service_name}' has no log file"))?,
+            None => self
+                .combined_writer
+                .as_mut()
+                .ok_or_else(|| "Combined log file is disabled".to_owned())?,
+        };
+
+        Ok(writer.rotate())
+    }
+
+    fn writers_mut(&mut self) -> impl Iterator<Item = &mut Writer> {
+        self.combined_writer
+            .iter_mut()
+            .chain(self.service_writers.values_mut())
+    }
+
+    /// Write buffered lines into the log files. Fsync if required by the durability policy
+    pub fn flush(&mut self) {
+        self.writers_mut().for_each(Writer::flush)
+    }
+
+    /// Write buffered lines into the log files and fsync them
+    pub fn sync(&mut self) {
+        self.writers_mut().for_each(Writer::sync)
+    }
+
+    /// Earliest time to fsync unsynced data according to the fsync period
+    pub fn sync_deadline(&self) -> Option<Instant> {
+        self.combined_writer
+            .iter()
+            .chain(self.service_writers.values())
+            .filter_map(Writer::sync_deadline)
+            .min()
+    }
+
+    /// Fsync log files, which have unsynced data for longer than the fsync period
+    pub fn sync_expired(&mut self) {
+        let now = Instant::now();
+
+        for writer in self.writers_mut() {
+            if writer
+                .sync_deadline()
+                .is_some_and(|deadline| deadline <= now)
+            {
+                writer.sync()
+            }
+        }
+    }
+
+    /// Remove old logs according to the retention policies. Returns removed files
+    pub fn remove_old_logs(&self) -> Vec<String> {
+        self.combined_writer
+            .iter()
+            .chain(self.service_writers.values())
+            .flat_map(|writer| writer.remove_old_logs())
+            .collect()
+    }
+}
+
+/// Service log file: `<log dir>/<service name>.<log extension>`.
+/// Path separators and control characters in the service name are replaced with `_`.
+/// Names, which don't fit the file name limit with the rotation suffix, are truncated
+/// and suffixed with the name hash
+pub fn service_log_location(log_location: &Path, service_name: &str) -> PathBuf {
+    let extension = log_location
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut stem: String = service_name
+        .chars()
+        .map(|c| {
+            if c == '/' || c == std::path::MAIN_SEPARATOR || c.is_control() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+
+    // Empty name, `.` and `..` are not valid file names
+    if stem.chars().all(|c| c == '.') {
+        stem.insert(0, '_');
+    }
+
+    let max_stem_len = MAX_FILE_NAME_LEN
+        .saturating_sub(MAX_ROTATED_SUFFIX_LEN + extension.len())
+        .max(MIN_SERVICE_STEM_LEN);
+
+    if stem.len() > max_stem_len {
+        let hash = format!("_{:016x}", name_hash(service_name));
+
+        let mut end = max_stem_len - hash.len();
+        while !stem.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        stem = format!("{}{hash}", &stem[..end]);
+    }
+
+    log_location.with_file_name(format!("{stem}{extension}"))
+}
+
+/// If **log_location** looks like a rotated log of another log file,
+/// e.g. `<service>_<timestamp>.<extension>`
+fn looks_rotated(log_location: &Path) -> bool {
+    let Some(file_name) = log_location.file_name().map(|name| name.to_string_lossy()) else {
+        return false;
+    };
+
+    let extension = log_location
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    file_name.match_indices('_').any(|(position, _)| {
+        let live_log =
+            log_location.with_file_name(format!("{}{extension}", &file_name[..position]));
+
+        LogNaming::new(&live_log)
+            .parse_rotated_name(&file_name)
+            .is_some()
+    })
+}
+
+/// 64-bit FNV-1a hash. Unlike the std hasher, it's stable across builds
+fn name_hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
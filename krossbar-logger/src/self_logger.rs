@@ -1,8 +1,7 @@
 use std::sync::Mutex;
 
-use chrono::Local;
 use futures::{channel::mpsc::Sender, executor::block_on, SinkExt};
-use log::{LevelFilter, Metadata, Record};
+use log::{Metadata, Record};
 use tokio::net::unix;
 
 use krossbar_log_common::{log_message::LogMessage, logger_interface::LOGGER_SERVICE_NAME};
@@ -12,16 +11,14 @@ use crate::LogEvent;
 pub struct SelfLogger {
     pid: unix::pid_t,
     service_name: String,
-    level: LevelFilter,
     log_sender: Mutex<Sender<LogEvent>>,
 }
 
 impl SelfLogger {
-    pub fn new(level: LevelFilter, log_sender: Sender<LogEvent>) -> Self {
+    pub fn new(log_sender: Sender<LogEvent>) -> Self {
         Self {
             pid: std::process::id() as unix::pid_t,
             service_name: LOGGER_SERVICE_NAME.into(),
-            level,
             log_sender: Mutex::new(log_sender),
         }
     }
@@ -29,7 +26,8 @@ impl SelfLogger {
 
 impl log::Log for SelfLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= self.level
+        // Max level is updated on config reload
+        metadata.level() <= log::max_level()
     }
 
     fn log(&self, record: &Record) {
@@ -37,12 +35,8 @@ impl log::Log for SelfLogger {
             let _ = block_on(self.log_sender.lock().unwrap().send(LogEvent {
                 pid: self.pid,
                 service_name: self.service_name.clone(),
-                message: LogMessage {
-                    level: record.level(),
-                    target: record.metadata().target().into(),
-                    timestamp: Local::now(),
-                    message: format!("{}", record.args()),
-                },
+                message: LogMessage::from_record(record),
+                stats: None,
             }));
         }
     }
@@ -1,55 +1,157 @@
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{collections::HashMap, path::Path, sync::Arc, time::Duration};
 
-use futures::{channel::mpsc::Receiver, lock::Mutex, select, FutureExt, StreamExt};
-use log::{debug, error, warn};
+use futures::{
+    channel::{
+        mpsc::{self, Sender, UnboundedSender},
+        oneshot,
+    },
+    future::join_all,
+    lock::Mutex,
+    select_biased, FutureExt, SinkExt, StreamExt,
+};
+use log::{debug, error, info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::time::timeout;
 
 use krossbar_bus_common::DEFAULT_HUB_SOCKET_PATH;
 use krossbar_bus_lib::{Service, Signal};
-use krossbar_log_common::logger_interface::{
-    SetLogLevel, LOGGER_SERVICE_NAME, LOG_CLIENTS_METHOD_NAME, ROTATED_SIGNAL,
-    SET_LOG_LEVEL_METHOD_NAME,
+use krossbar_log_common::{
+    log_message::LogRecord,
+    logger_interface::{
+        tail_signal_name, ClientInfo, LogQuery, LogRotated, ServiceLogLevel, SetLogLevel,
+        TailFilter, DUMP_METHOD_NAME, GET_LOG_LEVEL_METHOD_NAME, LOGGER_SERVICE_NAME,
+        LOG_CLIENTS_INFO_METHOD_NAME, LOG_CLIENTS_METHOD_NAME, LOG_ROTATED_SIGNAL,
+        QUERY_METHOD_NAME, REMOVED_SIGNAL, ROTATED_SIGNAL, ROTATE_METHOD_NAME,
+        SET_LOG_LEVEL_METHOD_NAME, TAIL_METHOD_NAME,
+    },
 };
 
 use krossbar_rpc::writer::RpcWriter;
 
-use crate::logger::Event;
+use crate::{
+    client::ClientHandle,
+    levels::LevelStore,
+    logger::{Command, Event, EventReceivers, TailEvent},
+    query::LogSearch,
+    ring::RingBuffer,
+    tail::TailSubscriptions,
+};
+
+/// How long to wait for a client to respond to a logger command
+const CLIENT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
 
-type ClientRegistryType = Arc<Mutex<HashMap<String, RpcWriter>>>;
+type ClientRegistryType = Arc<Mutex<HashMap<String, ClientHandle>>>;
+type LevelStoreType = Arc<Mutex<LevelStore>>;
+type TailSubscriptionsType = Arc<Mutex<TailSubscriptions>>;
+type RingBufferType = Arc<Mutex<RingBuffer>>;
+type LogSearchType = Arc<Mutex<LogSearch>>;
 
 struct ServiceEndpoints {
     service: Service,
     rotate_signal: Signal<String>,
+    log_rotated_signal: Signal<LogRotated>,
+    remove_signal: Signal<String>,
 }
 
 pub struct LoggerService;
 
 impl LoggerService {
-    pub async fn run(clients: ClientRegistryType, mut event_receiver: Receiver<Event>) {
+    pub async fn run(
+        clients: ClientRegistryType,
+        levels: LevelStoreType,
+        tail: TailSubscriptionsType,
+        search: LogSearchType,
+        ring: RingBufferType,
+        receivers: EventReceivers,
+        command_sender: Sender<Command>,
+    ) {
         tokio::spawn(async move {
+            let EventReceivers {
+                events: mut event_receiver,
+                tail: mut tail_receiver,
+            } = receivers;
+            let (subscriber_sender, mut subscriber_receiver) = mpsc::unbounded();
+
             let ServiceEndpoints {
                 mut service,
                 rotate_signal,
-            } = Self::connect(clients).await;
+                log_rotated_signal,
+                remove_signal,
+            } = Self::connect(
+                clients,
+                levels,
+                tail,
+                subscriber_sender,
+                search,
+                ring,
+                command_sender,
+            )
+            .await;
 
-            select! {
-                _ = service.poll().fuse() => {},
-            event = event_receiver.next() => {
-                if event.is_none() {
-                    error!("Event channel sender is closed");
-                }
+            // Live tail signals by subscriber service name
+            let mut tail_signals: HashMap<String, Signal<LogRecord>> = HashMap::new();
+
+            loop {
+                select_biased! {
+                    // Register subscriber signal before polling the service, which can receive
+                    // the subscription right after the tail call
+                    subscriber = subscriber_receiver.next() => {
+                        match subscriber {
+                            Some(subscriber) => {
+                                Self::register_tail_signal(&mut service, &mut tail_signals, subscriber)
+                            }
+                            None => {
+                                error!("Tail subscriber channel sender is closed");
+                                return;
+                            }
+                        }
+                    }
+                    _ = service.poll().fuse() => {},
+                    event = event_receiver.next() => {
+                        match event {
+                            Some(Event::Rotated(rotated)) => {
+                                if let Err(e) = rotate_signal.emit(rotated.file_name.clone()).await {
+                                    warn!("Failed to send 'rotated' event: {e:?}");
+                                }
 
-                match event.unwrap() {
-                    Event::Rotated(file_name) => {
-                        if let Err(e) = rotate_signal.emit(file_name).await {
-                            warn!("Failed to send 'rotated' event: {e:?}");
+                                if let Err(e) = log_rotated_signal.emit(rotated).await {
+                                    warn!("Failed to send 'log_rotated' event: {e:?}");
+                                }
+                            }
+                            Some(Event::Removed(file_name)) => {
+                                if let Err(e) = remove_signal.emit(file_name).await {
+                                    warn!("Failed to send 'removed' event: {e:?}");
+                                }
+                            }
+                            None => {
+                                error!("Event channel sender is closed");
+                                return;
+                            }
+                        }
+                    }
+                    event = tail_receiver.next() => {
+                        match event {
+                            Some(event) => Self::emit_tail_event(&tail_signals, event).await,
+                            None => {
+                                error!("Tail channel sender is closed");
+                                return;
+                            }
                         }
                     }
                 }
-            }}
+            }
         });
     }
 
-    async fn connect(clients: ClientRegistryType) -> ServiceEndpoints {
+    async fn connect(
+        clients: ClientRegistryType,
+        levels: LevelStoreType,
+        tail: TailSubscriptionsType,
+        subscriber_sender: UnboundedSender<String>,
+        search: LogSearchType,
+        ring: RingBufferType,
+        command_sender: Sender<Command>,
+    ) -> ServiceEndpoints {
         debug!("Connecting logger service");
 
         let mut service = Service::new(LOGGER_SERVICE_NAME, Path::new(DEFAULT_HUB_SOCKET_PATH))
@@ -57,35 +159,265 @@ impl LoggerService {
             .unwrap();
 
         let rotate_signal = service.register_signal(ROTATED_SIGNAL).unwrap();
+        let log_rotated_signal = service.register_signal(LOG_ROTATED_SIGNAL).unwrap();
+        let remove_signal = service.register_signal(REMOVED_SIGNAL).unwrap();
 
-        Self::register_set_log_level(&mut service, clients.clone());
+        Self::register_set_log_level(&mut service, clients.clone(), levels);
+        Self::register_get_log_level(&mut service, clients.clone());
         Self::register_get_clients(&mut service, clients.clone());
+        Self::register_get_clients_info(&mut service, clients.clone());
+        Self::register_rotate(&mut service, command_sender);
+        Self::register_tail(&mut service, tail, subscriber_sender);
+        Self::register_query(&mut service, search);
+        Self::register_dump(&mut service, ring);
 
         ServiceEndpoints {
             rotate_signal,
+            log_rotated_signal,
+            remove_signal,
             service,
         }
     }
 
-    fn register_set_log_level(service: &mut Service, clients: ClientRegistryType) {
+    fn register_set_log_level(
+        service: &mut Service,
+        clients: ClientRegistryType,
+        levels: LevelStoreType,
+    ) {
         service
             .register_async_method(
                 SET_LOG_LEVEL_METHOD_NAME,
                 move |_service, message: SetLogLevel| {
                     let clients = clients.clone();
+                    let levels = levels.clone();
 
                     async move {
-                        if let Some(writer) = clients.lock().await.get_mut(&message.service_name) {
-                            let _ = writer
-                                .send_message(SET_LOG_LEVEL_METHOD_NAME, &message.level)
-                                .await;
+                        let writer = match clients.lock().await.get(&message.service_name) {
+                            Some(client) => client.writer.clone(),
+                            None => {
+                                return Err(format!(
+                                    "Service '{}' is not connected to the logger",
+                                    message.service_name
+                                ))
+                            }
+                        };
+
+                        if message.reset {
+                            return Self::reset_log_level(message, writer, levels).await;
                         }
+
+                        match message.revert_after {
+                            Some(revert_after) => {
+                                Self::set_temporary_level(
+                                    message,
+                                    revert_after,
+                                    writer,
+                                    clients,
+                                    levels,
+                                )
+                                .await
+                            }
+                            None => {
+                                Self::set_client_log_level(&writer, &message).await?;
+
+                                // Persist only levels confirmed by the client
+                                let (temporary_save, save) = {
+                                    let mut levels = levels.lock().await;
+
+                                    (
+                                        levels.take_temporary(&message.service_name, None),
+                                        levels.set(&message),
+                                    )
+                                };
+
+                                if let Some((_, temporary_save)) = temporary_save {
+                                    temporary_save.write_in_background().await;
+                                }
+
+                                save.write_in_background().await;
+                                Ok(())
+                            }
+                        }
+                    }
+                },
+            )
+            .unwrap();
+    }
+
+    fn register_get_log_level(service: &mut Service, clients: ClientRegistryType) {
+        service
+            .register_async_method(
+                GET_LOG_LEVEL_METHOD_NAME,
+                move |_service, service_name: Option<String>| {
+                    let clients = clients.clone();
+
+                    async move {
+                        let mut writers: Vec<(String, RpcWriter)> = {
+                            let clients = clients.lock().await;
+
+                            match service_name {
+                                Some(service_name) => match clients.get(&service_name) {
+                                    Some(client) => vec![(service_name, client.writer.clone())],
+                                    None => {
+                                        return Err(format!(
+                                        "Service '{service_name}' is not connected to the logger"
+                                    ))
+                                    }
+                                },
+                                None => clients
+                                    .iter()
+                                    .map(|(service_name, client)| {
+                                        (service_name.clone(), client.writer.clone())
+                                    })
+                                    .collect(),
+                            }
+                        };
+
+                        writers.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+                        let levels = join_all(writers.into_iter().map(
+                            |(service_name, writer)| async move {
+                                ServiceLogLevel {
+                                    log_level: Self::call_client(
+                                        &writer,
+                                        GET_LOG_LEVEL_METHOD_NAME,
+                                        &(),
+                                    )
+                                    .await,
+                                    service_name,
+                                }
+                            },
+                        ))
+                        .await;
+
+                        Ok(levels)
                     }
                 },
             )
             .unwrap();
     }
 
+    /// Change client log level for **revert_after**, and restore the original level
+    /// when the timer expires
+    async fn set_temporary_level(
+        message: SetLogLevel,
+        revert_after: Duration,
+        writer: RpcWriter,
+        clients: ClientRegistryType,
+        levels: LevelStoreType,
+    ) -> Result<(), String> {
+        Self::set_client_log_level(&writer, &message).await?;
+
+        let (id, save) = levels.lock().await.set_temporary(&message, revert_after);
+        save.write_in_background().await;
+
+        let service_name = message.service_name;
+        info!("Temporarily changed {service_name} log level for {revert_after:?}");
+
+        Self::schedule_revert(service_name, id, revert_after, clients, levels);
+        Ok(())
+    }
+
+    /// Restore client original level after **revert_after** if the temporary change
+    /// with the **id** is still active
+    pub fn schedule_revert(
+        service_name: String,
+        id: u64,
+        revert_after: Duration,
+        clients: ClientRegistryType,
+        levels: LevelStoreType,
+    ) {
+        tokio::spawn(async move {
+            tokio::time::sleep(revert_after).await;
+
+            Self::revert_temporary_level(service_name, id, clients, levels).await
+        });
+    }
+
+    /// Restore client original level if the temporary change is still active.
+    /// The original level is the level before the change, or the client initial level if there was none
+    async fn revert_temporary_level(
+        service_name: String,
+        id: u64,
+        clients: ClientRegistryType,
+        levels: LevelStoreType,
+    ) {
+        // Reverted by a later change
+        let (message, save) = match levels.lock().await.take_temporary(&service_name, Some(id)) {
+            Some((temporary, save)) => (temporary.revert_message(&service_name), save),
+            None => return,
+        };
+
+        save.write_in_background().await;
+
+        let writer = match clients.lock().await.get(&service_name) {
+            Some(client) => client.writer.clone(),
+            None => return,
+        };
+
+        match Self::set_client_log_level(&writer, &message).await {
+            Ok(_) => info!("Restored {service_name} log level after a temporary change"),
+            Err(e) => warn!("Failed to restore {service_name} log level: {e}"),
+        }
+    }
+
+    /// Restore the config file default level, or the client initial level if there's none,
+    /// and remove the stored service level
+    async fn reset_log_level(
+        message: SetLogLevel,
+        writer: RpcWriter,
+        levels: LevelStoreType,
+    ) -> Result<(), String> {
+        let service_name = message.service_name;
+
+        let default_level = levels
+            .lock()
+            .await
+            .default_level(&service_name)
+            .unwrap_or_else(|| SetLogLevel::reset(&service_name));
+
+        Self::set_client_log_level(&writer, &default_level).await?;
+
+        let (temporary_save, save) = {
+            let mut levels = levels.lock().await;
+
+            (
+                levels.take_temporary(&service_name, None),
+                levels.remove(&service_name),
+            )
+        };
+
+        if let Some((_, temporary_save)) = temporary_save {
+            temporary_save.write_in_background().await;
+        }
+
+        save.write_in_background().await;
+        Ok(())
+    }
+
+    /// Change client log level and wait for the client to apply it
+    async fn set_client_log_level(writer: &RpcWriter, message: &SetLogLevel) -> Result<(), String> {
+        Self::call_client(writer, SET_LOG_LEVEL_METHOD_NAME, message).await
+    }
+
+    /// Make a client call and wait for the response
+    async fn call_client<P: Serialize, R: DeserializeOwned>(
+        writer: &RpcWriter,
+        endpoint: &str,
+        params: &P,
+    ) -> Result<R, String> {
+        let response = writer
+            .call::<_, R>(endpoint, params)
+            .await
+            .map_err(|e| format!("Failed to send '{endpoint}' request to the client: {e}"))?;
+
+        match timeout(CLIENT_RESPONSE_TIMEOUT, response).await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(e)) => Err(format!("Client failed to handle '{endpoint}' request: {e}")),
+            Err(_) => Err(format!("Client didn't respond to '{endpoint}' request")),
+        }
+    }
+
     fn register_get_clients(service: &mut Service, clients: ClientRegistryType) {
         service
             .register_async_method(LOG_CLIENTS_METHOD_NAME, move |_service, _message: ()| {
@@ -99,4 +431,145 @@ impl LoggerService {
             })
             .unwrap();
     }
+
+    fn register_get_clients_info(service: &mut Service, clients: ClientRegistryType) {
+        service
+            .register_async_method(
+                LOG_CLIENTS_INFO_METHOD_NAME,
+                move |_service, _message: ()| {
+                    let clients = clients.clone();
+
+                    async move {
+                        let mut clients: Vec<ClientInfo> = clients
+                            .lock()
+                            .await
+                            .iter()
+                            .map(|(service_name, client)| client.stats.info(service_name))
+                            .collect();
+
+                        clients.sort_by(|left, right| left.service_name.cmp(&right.service_name));
+                        clients
+                    }
+                },
+            )
+            .unwrap();
+    }
+
+    /// Rotate the combined log file, or a service log file if the service name is set.
+    /// Returns the rotated file path once the rotated file is compressed
+    fn register_rotate(service: &mut Service, command_sender: Sender<Command>) {
+        service
+            .register_async_method(
+                ROTATE_METHOD_NAME,
+                move |_service, service_name: Option<String>| {
+                    let mut command_sender = command_sender.clone();
+
+                    async move {
+                        let (reply, response) = oneshot::channel();
+
+                        command_sender
+                            .send(Command::Rotate {
+                                service_name,
+                                reply,
+                            })
+                            .await
+                            .map_err(|_| "Logger is shutting down".to_owned())?;
+
+                        response
+                            .await
+                            .map_err(|_| "Logger is shutting down".to_owned())?
+                    }
+                },
+            )
+            .unwrap();
+    }
+
+    /// Add or renew caller live tail subscription. Matching messages are emitted
+    /// with the caller [tail_signal_name] signal, which is registered by the service loop
+    fn register_tail(
+        service: &mut Service,
+        tail: TailSubscriptionsType,
+        subscriber_sender: UnboundedSender<String>,
+    ) {
+        service
+            .register_async_method(TAIL_METHOD_NAME, move |caller, filter: TailFilter| {
+                let tail = tail.clone();
+                let subscriber_sender = subscriber_sender.clone();
+
+                async move {
+                    debug!("Live tail subscription from {caller}: {filter:?}");
+
+                    tail.lock().await.subscribe(&caller, filter);
+                    let _ = subscriber_sender.unbounded_send(caller);
+                }
+            })
+            .unwrap();
+    }
+
+    /// Register **subscriber** live tail signal if not registered yet. Signals are kept
+    /// after the subscription expires, because the bus can't unregister them
+    fn register_tail_signal(
+        service: &mut Service,
+        tail_signals: &mut HashMap<String, Signal<LogRecord>>,
+        subscriber: String,
+    ) {
+        if tail_signals.contains_key(&subscriber) {
+            return;
+        }
+
+        match service.register_signal(&tail_signal_name(&subscriber)) {
+            Ok(signal) => {
+                tail_signals.insert(subscriber, signal);
+            }
+            Err(e) => warn!("Failed to register {subscriber} tail signal: {e:?}"),
+        }
+    }
+
+    /// Send live tail message to the subscribers whose filters match it
+    async fn emit_tail_event(tail_signals: &HashMap<String, Signal<LogRecord>>, event: TailEvent) {
+        for subscriber in event.subscribers {
+            if let Some(signal) = tail_signals.get(&subscriber) {
+                if let Err(e) = signal.emit(event.record.clone()).await {
+                    warn!("Failed to send log event to {subscriber}: {e:?}");
+                }
+            }
+        }
+    }
+
+    /// Search live and rotated log files. Files are read in a blocking task to not stall
+    /// the service loop
+    fn register_query(service: &mut Service, search: LogSearchType) {
+        service
+            .register_async_method(QUERY_METHOD_NAME, move |_service, query: LogQuery| {
+                let search = search.clone();
+
+                async move {
+                    let search = search.lock().await.clone();
+
+                    tokio::task::spawn_blocking(move || search.query(&query))
+                        .await
+                        .map_err(|e| format!("Log query failed: {e}"))?
+                }
+            })
+            .unwrap();
+    }
+
+    /// Recent messages from the in-memory buffer. Only the service messages if the service name is set
+    fn register_dump(service: &mut Service, ring: RingBufferType) {
+        service
+            .register_async_method(
+                DUMP_METHOD_NAME,
+                move |_service, service_name: Option<String>| {
+                    let ring = ring.clone();
+
+                    async move {
+                        let records: Vec<LogRecord> =
+                            ring.lock().await.records(service_name.as_deref());
+
+                        records
+                    }
+                },
+            )
+            .unwrap();
+    }
 }
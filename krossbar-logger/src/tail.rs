@@ -0,0 +1,57 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use krossbar_log_common::{log_message::LogMessage, logger_interface::TailFilter};
+
+struct Subscription {
+    filter: TailFilter,
+    expires_at: Instant,
+}
+
+/// Live tail subscriptions by subscriber service name. Each subscriber has own signal,
+/// so the logger sends only the messages matching the subscriber filter.
+/// Subscriptions expire if not renewed, because the logger is not notified when
+/// a subscriber disconnects
+pub struct TailSubscriptions {
+    timeout: Duration,
+    subscriptions: HashMap<String, Subscription>,
+}
+
+impl TailSubscriptions {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            subscriptions: HashMap::new(),
+        }
+    }
+
+    /// Add or renew a subscription
+    pub fn subscribe(&mut self, subscriber: &str, filter: TailFilter) {
+        self.subscriptions.insert(
+            subscriber.into(),
+            Subscription {
+                filter,
+                expires_at: Instant::now() + self.timeout,
+            },
+        );
+    }
+
+    /// Subscribers who want the message. Drops expired subscriptions
+    pub fn subscribers(&mut self, service_name: &str, message: &LogMessage) -> Vec<String> {
+        if self.subscriptions.is_empty() {
+            return vec![];
+        }
+
+        let now = Instant::now();
+        self.subscriptions
+            .retain(|_, subscription| subscription.expires_at > now);
+
+        self.subscriptions
+            .iter()
+            .filter(|(_, subscription)| subscription.filter.matches(service_name, message))
+            .map(|(subscriber, _)| subscriber.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+};
+
+use log::{Level, LevelFilter};
+
+use crate::LogEvent;
+
+const DEFAULT_FILE_LEVEL: LevelFilter = LevelFilter::Info;
+const DEFAULT_TRIGGER_LEVEL: Level = Level::Error;
+const DEFAULT_CONTEXT: usize = 100;
+
+/// Per-service trigger flush policy. Parsed from
+/// `<SERVICE>[:file_level=<LEVEL>,trigger=<LEVEL>,context=<NUM>]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerPolicy {
+    pub service_name: String,
+    /// Minimum level of messages written into log files right away
+    pub file_level: LevelFilter,
+    /// Minimum level of a message, which flushes buffered messages
+    pub trigger_level: Level,
+    /// Max number of buffered messages
+    pub context: usize,
+}
+
+impl FromStr for TriggerPolicy {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        let (service_name, settings) = match policy.split_once(':') {
+            Some((service_name, settings)) => (service_name, settings),
+            None => (policy, ""),
+        };
+
+        if service_name.is_empty() {
+            return Err(format!("Missing service name in trigger policy '{policy}'"));
+        }
+
+        let mut result = Self {
+            service_name: service_name.into(),
+            file_level: DEFAULT_FILE_LEVEL,
+            trigger_level: DEFAULT_TRIGGER_LEVEL,
+            context: DEFAULT_CONTEXT,
+        };
+
+        for setting in settings.split(',').filter(|setting| !setting.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid trigger policy setting '{setting}'"))?;
+
+            let invalid_value = || format!("Invalid trigger policy '{key}' value '{value}'");
+            let value = value.trim();
+
+            match key.trim() {
+                "file_level" => result.file_level = value.parse().map_err(|_| invalid_value())?,
+                "trigger" => result.trigger_level = value.parse().map_err(|_| invalid_value())?,
+                "context" => result.context = value.parse().map_err(|_| invalid_value())?,
+                _ => return Err(format!("Unknown trigger policy setting '{key}'")),
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Decides which messages are written into log files. Services with a trigger policy keep
+/// messages below the policy file level in memory, and the buffered context is written
+/// right before a message at the trigger level
+pub struct TriggerFilter {
+    /// File level for services without a policy
+    file_level: LevelFilter,
+    policies: HashMap<String, TriggerPolicy>,
+    buffers: HashMap<String, VecDeque<LogEvent>>,
+}
+
+impl TriggerFilter {
+    pub fn new(file_level: LevelFilter, policies: &[TriggerPolicy]) -> Self {
+        Self {
+            file_level,
+            policies: policies
+                .iter()
+                .map(|policy| (policy.service_name.clone(), policy.clone()))
+                .collect(),
+            buffers: HashMap::new(),
+        }
+    }
+
+    /// Messages to write into log files. Buffered messages go right before the trigger message,
+    /// so they're written after the messages logged since they were buffered
+    pub fn filter(&mut self, message: LogEvent) -> Vec<LogEvent> {
+        let level = message.message.level;
+
+        let policy = match self.policies.get(&message.service_name) {
+            Some(policy) => policy,
+            None if level <= self.file_level => return vec![message],
+            None => return vec![],
+        };
+
+        if level <= policy.trigger_level {
+            let mut result: Vec<LogEvent> = self
+                .buffers
+                .remove(&message.service_name)
+                .unwrap_or_default()
+                .into();
+
+            result.push(message);
+            result
+        } else if level <= policy.file_level {
+            vec![message]
+        } else {
+            let context = policy.context;
+            let buffer = self
+                .buffers
+                .entry(message.service_name.clone())
+                .or_default();
+
+            buffer.push_back(message);
+            while buffer.len() > context {
+                buffer.pop_front();
+            }
+
+            vec![]
+        }
+    }
+}
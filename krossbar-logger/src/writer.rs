@@ -1,74 +1,173 @@
-use crate::{args, LogEvent};
+use crate::{
+    args::{self, LogFormat},
+    LogEvent,
+};
 
 use std::{
     fs::{File, OpenOptions},
-    io::Write,
-    path::PathBuf,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
-use crate::rotator::Rotator;
+use log::Level;
+use serde::Serialize;
+use tokio::net::unix;
+
+use krossbar_log_common::{line_template::LineTemplate, log_message::LogMessage};
+
+use crate::rotator::{Rotation, Rotator};
+
+/// JSON Lines log record
+#[derive(Serialize)]
+struct JsonLogLine<'a> {
+    pid: unix::pid_t,
+    service_name: &'a str,
+    #[serde(flatten)]
+    message: &'a LogMessage,
+}
+
+/// Log file buffer size. Buffered lines are written into the file on [Writer::flush]
+const WRITE_BUFFER_SIZE: usize = 64 * 1024;
 
 pub struct Writer {
-    log_file: Option<File>,
+    log_file: Option<BufWriter<File>>,
+    format: LogFormat,
+    line_template: LineTemplate,
     log_location: PathBuf,
     rotator: Rotator,
     current_file_num_bytes: u64,
     max_file_len: u64,
+    rotate_period: Option<Duration>,
+    /// Time the current log file was started at
+    current_file_start: Instant,
+    fsync_period: Option<Duration>,
+    fsync_bytes: Option<u64>,
+    fsync_level: Option<Level>,
+    /// Bytes written since the last fsync
+    unsynced_num_bytes: u64,
+    /// Time of the first write since the last fsync
+    unsynced_since: Option<Instant>,
+    /// If the log file should be fsynced on the next flush
+    sync_required: bool,
+    /// Time of the last message write
+    last_write: Instant,
 }
 
 impl Writer {
-    pub fn new(args: &args::Args) -> Self {
-        println!("Log file location: {:?}", args.log_location);
+    /// Create writer and open the log file. Fails if the log file can't be opened
+    pub fn new(args: &args::Args, log_location: PathBuf) -> io::Result<Self> {
+        println!("Log file location: {:?}", log_location);
 
         let mut this = Self {
             log_file: None,
-            log_location: PathBuf::from(&args.log_location),
-            rotator: Rotator::new(args.keep_num_files, PathBuf::from(&args.log_location)),
+            format: args.format,
+            line_template: args.line_template.clone(),
+            rotator: Rotator::new(args.keep_num_files, log_location.clone())
+                .with_compression(args.compress, args.compress_level)
+                .with_retention(args.max_age, args.max_total_bytes),
+            log_location,
             current_file_num_bytes: 0,
             max_file_len: args.num_bytes_rotate,
+            rotate_period: args.rotate_period,
+            current_file_start: Instant::now(),
+            fsync_period: args.fsync_period,
+            fsync_bytes: args.fsync_bytes,
+            fsync_level: args.fsync_level,
+            unsynced_num_bytes: 0,
+            unsynced_since: None,
+            sync_required: false,
+            last_write: Instant::now(),
         };
 
-        this.open_log_file();
-        this
+        this.open_log_file()?;
+        Ok(this)
+    }
+
+    fn open_log_file(&mut self) -> io::Result<()> {
+        let log_file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.log_location)?;
+
+        // Continue an existing log file, e.g. after a config reload
+        self.current_file_num_bytes = log_file.metadata().map(|meta| meta.len()).unwrap_or(0);
+        self.log_file = Some(BufWriter::with_capacity(WRITE_BUFFER_SIZE, log_file));
+
+        Ok(())
     }
 
-    fn open_log_file(&mut self) {
-        self.log_file = Some(
-            OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(&self.log_location)
-                .expect(&format!(
-                    "Failed to open log file at {:?}",
-                    &self.log_location
-                )),
-        );
+    pub fn log_location(&self) -> &Path {
+        &self.log_location
+    }
+
+    pub fn current_file_start(&self) -> Instant {
+        self.current_file_start
+    }
+
+    /// Continue the log file from **start** time, e.g. after a config reload
+    pub fn set_current_file_start(&mut self, start: Instant) {
+        self.current_file_start = start;
+    }
+
+    /// Reopen the log file if it's closed, e.g. as idle or after a failed rotation
+    pub fn reopen(&mut self) -> io::Result<()> {
+        if self.log_file.is_none() {
+            self.open_log_file()?;
+        }
+
+        Ok(())
+    }
+
+    /// Close the log file if nothing is written for longer than **timeout**.
+    /// The file is reopened on the next message
+    pub fn close_if_idle(&mut self, timeout: Duration) {
+        if self.log_file.is_some() && self.last_write.elapsed() >= timeout {
+            self.close_log_file();
+        }
     }
 
     fn close_log_file(&mut self) {
+        self.sync();
         self.log_file = None;
     }
 
-    pub fn log_message(&mut self, message: LogEvent) -> Option<String> {
-        let log_line = format!(
-            "<{}> {}#{} [{}] {} > {}\n",
-            message.message.timestamp.format("%d-%m-%Y %H:%M:%S%.3f"),
-            message.service_name,
-            message.pid,
-            message.message.level,
-            message.message.target,
-            message.message.message
-        );
+    pub fn log_message(&mut self, message: &LogEvent) -> Option<Rotation> {
+        self.last_write = Instant::now();
+
+        if let Err(err) = self.reopen() {
+            eprintln!(
+                "Failed to write log message. Can't open log file at {:?}: {err}",
+                self.log_location
+            );
+            return None;
+        }
+
+        let log_line = match self.format {
+            LogFormat::Text => self.format_text(message),
+            LogFormat::Json => match Self::format_json(message) {
+                Ok(log_line) => log_line,
+                Err(err) => {
+                    eprintln!("Failed to serialize log message: {err}");
+                    return None;
+                }
+            },
+        };
 
         // New current log len
         self.current_file_num_bytes += log_line.len() as u64;
 
         match self.log_file {
-            Some(ref mut log_file) => {
-                if let Err(err) = log_file.write_all(log_line.as_bytes()) {
-                    eprintln!("Failed to write log message: {}", err.to_string())
+            Some(ref mut log_file) => match log_file.write_all(log_line.as_bytes()) {
+                Ok(_) => {
+                    if let Some(ref stats) = message.stats {
+                        stats.on_written(log_line.len())
+                    }
+
+                    self.on_written(log_line.len() as u64, message.message.level);
                 }
-            }
+                Err(err) => eprintln!("Failed to write log message: {}", err.to_string()),
+            },
             _ => {
                 eprintln!("Failed to write log message. Log file is closed");
             }
@@ -77,18 +176,129 @@ impl Writer {
         self.check_rotate()
     }
 
-    fn check_rotate(&mut self) -> Option<String> {
+    /// Update unsynced data. Messages at fsync level or above require fsync
+    fn on_written(&mut self, num_bytes: u64, level: Level) {
+        self.unsynced_num_bytes += num_bytes;
+        self.unsynced_since.get_or_insert_with(Instant::now);
+
+        if self
+            .fsync_level
+            .is_some_and(|fsync_level| level <= fsync_level)
+            || self
+                .fsync_bytes
+                .is_some_and(|fsync_bytes| self.unsynced_num_bytes >= fsync_bytes)
+        {
+            self.sync_required = true;
+        }
+    }
+
+    /// Write buffered lines into the log file. Fsync if required by the durability policy
+    pub fn flush(&mut self) {
+        if self.sync_required {
+            return self.sync();
+        }
+
+        if let Some(ref mut log_file) = self.log_file {
+            if let Err(err) = log_file.flush() {
+                eprintln!("Failed to write log messages: {err}")
+            }
+        }
+    }
+
+    /// Write buffered lines into the log file and fsync it if there's unsynced data
+    pub fn sync(&mut self) {
+        self.sync_required = false;
+
+        let Some(ref mut log_file) = self.log_file else {
+            return;
+        };
+
+        if let Err(err) = log_file.flush() {
+            eprintln!("Failed to write log messages: {err}")
+        }
+
+        if self.unsynced_since.take().is_some() {
+            if let Err(err) = log_file.get_ref().sync_data() {
+                eprintln!("Failed to sync log file: {err}")
+            }
+        }
+
+        self.unsynced_num_bytes = 0;
+    }
+
+    /// Time to fsync unsynced data according to the fsync period
+    pub fn sync_deadline(&self) -> Option<Instant> {
+        Some(self.unsynced_since? + self.fsync_period?)
+    }
+
+    /// Log line formatted with the line template
+    fn format_text(&self, message: &LogEvent) -> String {
+        let mut log_line =
+            self.line_template
+                .format(&message.service_name, message.pid, &message.message);
+
+        log_line.push('\n');
+        log_line
+    }
+
+    /// Self-describing JSON object per line
+    fn format_json(message: &LogEvent) -> serde_json::Result<String> {
+        let json_line = JsonLogLine {
+            pid: message.pid,
+            service_name: &message.service_name,
+            message: &message.message,
+        };
+
+        let mut log_line = serde_json::to_string(&json_line)?;
+        log_line.push('\n');
+
+        Ok(log_line)
+    }
+
+    fn check_rotate(&mut self) -> Option<Rotation> {
         if self.current_file_num_bytes < self.max_file_len {
             return None;
         }
 
+        Some(self.rotate())
+    }
+
+    /// Rotate log file if it's older than rotation period. Empty files are not rotated
+    pub fn check_rotate_period(&mut self) -> Option<Rotation> {
+        let rotate_period = self.rotate_period?;
+
+        if self.current_file_num_bytes == 0 || self.current_file_start.elapsed() < rotate_period {
+            return None;
+        }
+
+        Some(self.rotate())
+    }
+
+    /// Remove old logs according to the retention policies. Returns removed files
+    pub fn remove_old_logs(&self) -> Vec<String> {
+        self.rotator.remove_old_logs()
+    }
+
+    /// Close current log file, rotate it, and start a new one.
+    /// Buffered lines are written and synced before the rotation
+    pub fn rotate(&mut self) -> Rotation {
         self.close_log_file();
 
-        let rotate_file_path = self.rotator.rotate();
+        let rotation = self.rotator.rotate();
         self.current_file_num_bytes = 0;
+        self.current_file_start = Instant::now();
 
-        self.open_log_file();
+        if let Err(err) = self.open_log_file() {
+            eprintln!("Failed to open log file at {:?}: {err}", self.log_location);
+        }
+
+        rotation
+    }
+}
 
-        Some(rotate_file_path)
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // Don't lose buffered lines on config reload or shutdown
+        self.close_log_file()
     }
 }
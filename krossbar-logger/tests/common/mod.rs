@@ -0,0 +1,83 @@
+// Each test binary uses only a part of the fixtures
+#![allow(dead_code)]
+
+use std::{path::Path, time::Duration};
+
+use futures::channel::mpsc::Sender;
+use log::{Level, LevelFilter};
+use tempdir::TempDir;
+use tokio::net::UnixStream;
+
+use krossbar_log_common::{
+    log_message::LogMessage,
+    logger_interface::{RegisterResponse, LOG_METHOD_NAME, REGISTER_METHOD_NAME},
+};
+use krossbar_logger_lib::{
+    args::Args,
+    logger::{Command, Logger},
+};
+use krossbar_rpc::{rpc::Rpc, writer::RpcWriter};
+
+/// Default test arguments writing logs into **log_location**
+pub fn test_args(log_location: &Path) -> Args {
+    Args {
+        // Skip logger service hub connection messages
+        log_level: LevelFilter::Error,
+        log_location: log_location.to_string_lossy().into(),
+        ..Default::default()
+    }
+}
+
+/// Start a logger listening at **socket_path**. Returns the logger command sender
+pub async fn start_logger(args: Args, socket_path: &Path) -> Sender<Command> {
+    let logger = Logger::new(args, socket_path.to_owned());
+    let commands = logger.command_sender();
+    tokio::spawn(logger.run());
+
+    // Wait for the logger to start listening
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    commands
+}
+
+/// Register a client and return its connection writer
+pub async fn connect_client(socket_path: &Path, service_name: &str) -> RpcWriter {
+    let stream = UnixStream::connect(socket_path).await.unwrap();
+    let mut rpc = Rpc::new(stream, "krossbar.logger");
+    let writer = rpc.writer().clone();
+
+    let registration = writer
+        .call::<_, RegisterResponse>(REGISTER_METHOD_NAME, &service_name)
+        .await
+        .unwrap();
+
+    // Poll the connection to receive responses
+    tokio::spawn(async move { while rpc.poll().await.is_some() {} });
+
+    // The logger accepts batches
+    assert!(registration.await.unwrap().log_batch);
+    writer
+}
+
+/// Send a single info **message**
+pub async fn log(writer: &RpcWriter, message: &str) {
+    writer
+        .send_message(
+            LOG_METHOD_NAME,
+            &LogMessage::new(Level::Info, "test".into(), message.into()),
+        )
+        .await
+        .unwrap();
+}
+
+/// Sorted file names in the **log_dir**
+pub fn read_dir(log_dir: &TempDir) -> Vec<String> {
+    let mut files: Vec<String> = log_dir
+        .path()
+        .read_dir()
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+
+    files.sort();
+    files
+}
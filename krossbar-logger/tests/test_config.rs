@@ -0,0 +1,315 @@
+use std::{fs, path::Path, process::Command, time::Duration};
+
+use clap::Parser;
+use log::{Level, LevelFilter};
+use tempdir::TempDir;
+
+use krossbar_log_common::logger_interface::LogLevel;
+use krossbar_logger_lib::args::{Args, Compression, LogFormat, QueueOverflow, ServiceSettings};
+use krossbar_rpc::writer::RpcWriter;
+
+mod common;
+use common::{connect_client, log, start_logger};
+
+/// Log a message and let the logger write it
+async fn log_and_wait(client: &RpcWriter, message: &str) {
+    log(client, message).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+}
+
+fn reload() {
+    Command::new("kill")
+        .args(["-HUP", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+}
+
+#[test]
+fn test_default_args() {
+    let args = Args::parse_from(["krossbar-logger"]);
+
+    assert!(Args::default().diff(&args).is_empty());
+    assert!(args.config.is_none());
+}
+
+#[test]
+fn test_config_file() {
+    let config_dir = TempDir::new("krossbar_config_dir").expect("Failed to create tempdir");
+    let config_location = config_dir.path().join("logger.toml");
+
+    fs::write(
+        &config_location,
+        r#"
+log_level = "warn"
+socket_path = "/tmp/logger.sock"
+file_level = "info"
+
+[output]
+log_location = "/tmp/krossbar/krossbar.log"
+format = "json"
+per_service_files = true
+no_combined_log = true
+
+[rotation]
+num_bytes = 5000
+period = "1h"
+keep_num_files = 5
+compress = "zstd"
+
+[retention]
+max_age = "7d"
+
+[durability]
+fsync_period = "100ms"
+fsync_level = "error"
+
+[ring]
+size = 10
+
+[queue]
+size = 500
+overflow = "drop"
+
+[[trigger_flush]]
+service = "com.test.service"
+trigger = "warn"
+
+[[services]]
+service = "com.test.service"
+level = "debug"
+log_location = "/tmp/krossbar/service.log"
+num_bytes_rotate = 2000
+max_age = "1d"
+
+[[services]]
+service = "com.test.other"
+directives = "net=trace"
+"#,
+    )
+    .unwrap();
+
+    let cli_args = Args {
+        config: Some(config_location.to_string_lossy().into()),
+        keep_num_files: 3,
+        cli_overrides: vec!["keep_num_files".into()],
+        ..Default::default()
+    };
+
+    let args = cli_args.with_config().unwrap();
+
+    assert_eq!(args.log_level, LevelFilter::Warn);
+    assert_eq!(args.socket_path, "/tmp/logger.sock");
+    assert_eq!(args.file_level, LevelFilter::Info);
+    assert_eq!(args.log_location, "/tmp/krossbar/krossbar.log");
+    assert_eq!(args.format, LogFormat::Json);
+    assert!(args.per_service_files && args.no_combined_log);
+    assert_eq!(args.num_bytes_rotate, 5000);
+    assert_eq!(args.rotate_period, Some(Duration::from_secs(3600)));
+    assert_eq!(args.compress, Compression::Zstd);
+    assert_eq!(args.max_age, Some(Duration::from_secs(7 * 24 * 3600)));
+    assert_eq!(args.fsync_period, Some(Duration::from_millis(100)));
+    assert_eq!(args.fsync_level, Some(Level::Error));
+    assert_eq!(args.ring_size, 10);
+    assert_eq!(args.queue_size, 500);
+    assert_eq!(args.queue_overflow, QueueOverflow::Drop);
+    assert_eq!(
+        args.trigger_flush,
+        vec!["com.test.service:trigger=warn".parse().unwrap()]
+    );
+    assert_eq!(
+        args.services,
+        vec![
+            ServiceSettings {
+                service_name: "com.test.service".into(),
+                log_level: Some(LogLevel {
+                    level: LevelFilter::Debug,
+                    directives: None,
+                }),
+                log_location: Some("/tmp/krossbar/service.log".into()),
+                num_bytes_rotate: Some(2000),
+                max_age: Some(Duration::from_secs(24 * 3600)),
+                ..Default::default()
+            },
+            ServiceSettings {
+                service_name: "com.test.other".into(),
+                log_level: Some(LogLevel {
+                    level: LevelFilter::Off,
+                    directives: Some("net=trace".into()),
+                }),
+                log_location: None,
+                ..Default::default()
+            },
+        ]
+    );
+
+    // Command line flags take precedence
+    assert_eq!(args.keep_num_files, 3);
+
+    // Unset settings keep command line values
+    assert_eq!(args.max_total_bytes, None);
+    assert_eq!(args.levels_location, Args::default().levels_location);
+
+    // Service settings fall back to the global ones
+    let service_args = args.service_args("com.test.service");
+    assert_eq!(service_args.num_bytes_rotate, 2000);
+    assert_eq!(service_args.keep_num_files, 3);
+    assert_eq!(service_args.max_age, Some(Duration::from_secs(24 * 3600)));
+
+    let other_args = args.service_args("com.test.other");
+    assert_eq!(other_args.num_bytes_rotate, 5000);
+    assert_eq!(other_args.max_age, Some(Duration::from_secs(7 * 24 * 3600)));
+
+    let changes = cli_args.diff(&args);
+    assert!(changes.contains(&"num_bytes_rotate: 1000000 -> 5000".to_owned()));
+    assert!(!changes
+        .iter()
+        .any(|change| change.starts_with("keep_num_files")));
+
+    for invalid in [
+        "unknown_setting = 1",
+        "[rotation]\nperiod = \"forever\"",
+        "[output]\nformat = \"xml\"",
+        "[output]\nno_combined_log = true",
+        "[durability]\nfsync_level = \"loud\"",
+        "[[services]]\nlevel = \"debug\"",
+        "[[services]]\nservice = \"com.test.service\"\nmax_age = \"forever\"",
+    ] {
+        fs::write(&config_location, invalid).unwrap();
+        assert!(cli_args.with_config().is_err(), "{invalid}");
+    }
+
+    fs::remove_file(&config_location).unwrap();
+    assert!(cli_args.with_config().is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_reload() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+    let config_location = log_dir.path().join("logger.toml");
+    let first_log = log_dir.path().join("first.log");
+    let second_log = log_dir.path().join("second.log");
+
+    let write_config = |log_location: &Path| {
+        fs::write(
+            &config_location,
+            format!(
+                // Skip logger service hub connection messages
+                "log_level = \"error\"\n[output]\nlog_location = {:?}\n",
+                log_location
+            ),
+        )
+        .unwrap()
+    };
+
+    write_config(&first_log);
+
+    let args = Args {
+        config: Some(config_location.to_string_lossy().into()),
+        log_level: LevelFilter::Error,
+        ..Default::default()
+    };
+
+    start_logger(args, &socket_path).await;
+
+    let client = connect_client(&socket_path, "com.test.service").await;
+
+    log_and_wait(&client, "First message").await;
+    assert!(fs::read_to_string(&first_log)
+        .unwrap()
+        .contains("First message"));
+
+    // External log rotation moves the file away
+    let moved_log = log_dir.path().join("first.log.old");
+    fs::rename(&first_log, &moved_log).unwrap();
+
+    reload();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    log_and_wait(&client, "Second message").await;
+    assert!(fs::read_to_string(&first_log)
+        .unwrap()
+        .contains("Second message"));
+    assert!(!fs::read_to_string(&moved_log)
+        .unwrap()
+        .contains("Second message"));
+
+    // Changed log location. The client stays connected
+    write_config(&second_log);
+
+    reload();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    log_and_wait(&client, "Third message").await;
+    assert!(fs::read_to_string(&second_log)
+        .unwrap()
+        .contains("Third message"));
+    assert!(!fs::read_to_string(&first_log)
+        .unwrap()
+        .contains("Third message"));
+
+    // Invalid config keeps current settings
+    fs::write(&config_location, "unknown_setting = 1").unwrap();
+
+    reload();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    log_and_wait(&client, "Fourth message").await;
+    assert!(fs::read_to_string(&second_log)
+        .unwrap()
+        .contains("Fourth message"));
+
+    // Log file, which can't be opened, keeps current settings
+    write_config(&second_log.join("nested.log"));
+
+    reload();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    log_and_wait(&client, "Fifth message").await;
+    assert!(fs::read_to_string(&second_log)
+        .unwrap()
+        .contains("Fifth message"));
+
+    // Dedicated service log file
+    let service_log = log_dir.path().join("service.log");
+    fs::write(
+        &config_location,
+        format!(
+            "log_level = \"error\"\n[output]\nlog_location = {second_log:?}\n\
+            [[services]]\nservice = \"com.test.service\"\nlog_location = {service_log:?}\n"
+        ),
+    )
+    .unwrap();
+
+    reload();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    log_and_wait(&client, "Sixth message").await;
+    assert!(fs::read_to_string(&service_log)
+        .unwrap()
+        .contains("Sixth message"));
+    assert!(fs::read_to_string(&second_log)
+        .unwrap()
+        .contains("Sixth message"));
+
+    // Periodic reloads don't postpone periodic rotation
+    fs::write(
+        &config_location,
+        format!(
+            "log_level = \"error\"\n[output]\nlog_location = {second_log:?}\n\
+            [rotation]\nperiod = \"1s\"\n"
+        ),
+    )
+    .unwrap();
+
+    for _ in 0..10 {
+        reload();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+    }
+
+    assert!(fs::read_dir(log_dir.path()).unwrap().any(|entry| entry
+        .unwrap()
+        .file_name()
+        .to_string_lossy()
+        .starts_with("second_")));
+}
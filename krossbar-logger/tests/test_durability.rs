@@ -0,0 +1,103 @@
+use std::{fs, time::Duration};
+
+use clap::Parser;
+use log::Level;
+use tempdir::TempDir;
+
+use krossbar_log_common::{log_message::LogMessage, logger_interface::LOG_BATCH_METHOD_NAME};
+use krossbar_logger_lib::args::Args;
+
+mod common;
+use common::{connect_client, start_logger, test_args};
+
+const NUM_MESSAGES: usize = 1000;
+
+#[test]
+fn test_durability_args() {
+    let args = Args::parse_from([
+        "krossbar-logger",
+        "--fsync-period",
+        "100ms",
+        "--fsync-bytes",
+        "4096",
+        "--fsync-level",
+        "error",
+    ]);
+
+    assert_eq!(args.fsync_period, Some(Duration::from_millis(100)));
+    assert_eq!(args.fsync_bytes, Some(4096));
+    assert_eq!(args.fsync_level, Some(Level::Error));
+
+    let args = Args::default();
+    assert_eq!(args.fsync_period, None);
+    assert_eq!(args.fsync_bytes, None);
+    assert_eq!(args.fsync_level, None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_buffered_writes() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+
+    let args = Args {
+        // Rotated file names have a second resolution, so rotate once
+        num_bytes_rotate: 50_000,
+        keep_num_files: 100,
+        fsync_period: Some(Duration::from_millis(10)),
+        fsync_level: Some(Level::Error),
+        ..test_args(&log_dir.path().join("krossbar.log"))
+    };
+
+    start_logger(args, &socket_path).await;
+
+    let client = connect_client(&socket_path, "com.test.service").await;
+
+    let messages: Vec<_> = (0..NUM_MESSAGES)
+        .map(|i| {
+            let level = if i % 100 == 0 {
+                Level::Error
+            } else {
+                Level::Info
+            };
+
+            LogMessage::new(level, "test".into(), format!("Message #{i}"))
+        })
+        .collect();
+
+    for batch in messages.chunks(100) {
+        client
+            .send_message(LOG_BATCH_METHOD_NAME, &batch)
+            .await
+            .unwrap();
+    }
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Live and rotated log files. Rotation writes buffered lines first
+    let mut numbers = vec![];
+    let mut num_files = 0;
+
+    for entry in fs::read_dir(log_dir.path()).unwrap() {
+        let path = entry.unwrap().path();
+        if path == socket_path {
+            continue;
+        }
+
+        num_files += 1;
+
+        let file_numbers: Vec<usize> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.split_once("Message #"))
+            .map(|(_, number)| number.parse().unwrap())
+            .collect();
+
+        assert!(file_numbers.windows(2).all(|pair| pair[0] < pair[1]));
+        numbers.extend(file_numbers);
+    }
+
+    assert_eq!(num_files, 2);
+
+    numbers.sort();
+    assert_eq!(numbers, (0..NUM_MESSAGES).collect::<Vec<_>>());
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use log::{info, LevelFilter};
+use tempdir::TempDir;
+
+use krossbar_logger_lib::{
+    args::{Args, LogFormat},
+    logger::Logger,
+};
+
+fn make_args(log_dir: &TempDir) -> Args {
+    let log_location: String = log_dir
+        .path()
+        .join("krossbar_log.messages")
+        .into_os_string()
+        .into_string()
+        .unwrap();
+
+    Args {
+        log_level: LevelFilter::Debug,
+        log_location,
+        num_bytes_rotate: u64::MAX,
+        keep_num_files: 1,
+        format: LogFormat::Json,
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_json_format() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+
+    let logger = Logger::new(make_args(&log_dir), socket_path);
+    tokio::spawn(logger.run());
+
+    info!(request_id = "abc", attempt = 3; "Test > JSON\nmultiline");
+
+    tokio::time::sleep(Duration::from_millis(1)).await;
+
+    let log_content =
+        std::fs::read_to_string(log_dir.path().join("krossbar_log.messages")).unwrap();
+
+    let record = log_content
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .find(|record| record["message"] == "Test > JSON\nmultiline")
+        .expect("Failed to find JSON log record");
+
+    assert_eq!(record["pid"], std::process::id());
+    assert_eq!(record["service_name"], "krossbar.logger");
+    assert_eq!(record["level"], "INFO");
+    assert_eq!(record["target"], "test_json_format");
+    assert_eq!(record["fields"]["request_id"], "abc");
+    assert_eq!(record["fields"]["attempt"], 3);
+    assert_eq!(record["module_path"], "test_json_format");
+    assert!(record["timestamp"].is_string());
+}
@@ -0,0 +1,273 @@
+use std::{collections::BTreeMap, fs, path::Path, time::Duration};
+
+use chrono::{DateTime, Local};
+
+use log::LevelFilter;
+use tempdir::TempDir;
+use tokio::net::UnixStream;
+
+use krossbar_log_common::logger_interface::{
+    LogLevel, RegisterResponse, SetLogLevel, REGISTER_METHOD_NAME, SET_LOG_LEVEL_METHOD_NAME,
+};
+use krossbar_logger_lib::{
+    args::{Args, ServiceSettings},
+    levels::{LevelStore, TemporaryLevel},
+};
+use krossbar_rpc::{request::Body, rpc::Rpc};
+
+mod common;
+use common::{start_logger, test_args};
+
+/// Register a client and return a level message sent by the logger right after the registration
+async fn connect_client(socket_path: &Path, service_name: &str) -> Option<SetLogLevel> {
+    let stream = UnixStream::connect(socket_path).await.unwrap();
+    let mut rpc = Rpc::new(stream, "krossbar.logger");
+
+    let registration = rpc
+        .writer()
+        .call::<_, RegisterResponse>(REGISTER_METHOD_NAME, &service_name)
+        .await
+        .unwrap();
+
+    // Poll resolves the registration response and returns the next incoming request
+    let request = tokio::time::timeout(Duration::from_millis(100), rpc.poll()).await;
+    registration.await.unwrap();
+
+    let mut request = request.ok()??;
+    assert_eq!(request.endpoint(), SET_LOG_LEVEL_METHOD_NAME);
+
+    match request.take_body() {
+        Some(Body::Message(body)) => Some(bson::from_bson(body).unwrap()),
+        body => panic!("Unexpected level message body: {body:?}"),
+    }
+}
+
+#[test]
+fn test_level_store() {
+    let levels_dir = TempDir::new("krossbar_levels_dir").expect("Failed to create tempdir");
+    let levels_location = levels_dir.path().join("nested").join("log_levels.json");
+
+    let mut store = LevelStore::load(levels_location.clone());
+    assert!(store.get("com.test.service").is_none());
+
+    let level = SetLogLevel {
+        service_name: "com.test.service".into(),
+        level: LevelFilter::Info,
+        directives: Some("net=trace".into()),
+        revert_after: None,
+        reset: false,
+    };
+    store.set(&level).write().unwrap();
+
+    // Persisted across restarts
+    let mut store = LevelStore::load(levels_location.clone());
+    assert_eq!(store.get("com.test.service"), Some(level.clone()));
+    assert!(store.get("com.test.other").is_none());
+
+    // Outdated save doesn't overwrite a later change
+    let default_level = LogLevel {
+        level: LevelFilter::Warn,
+        directives: None,
+    };
+    store.set_defaults(BTreeMap::from([(
+        "com.test.service".into(),
+        default_level.clone(),
+    )]));
+
+    let outdated_save = store.set(&SetLogLevel::new("com.test.service", default_level.clone()));
+    store.remove("com.test.service").write().unwrap();
+    outdated_save.write().unwrap();
+
+    // Removed level falls back to the default one
+    assert_eq!(
+        store.get("com.test.service"),
+        Some(SetLogLevel::new("com.test.service", default_level))
+    );
+
+    let mut store = LevelStore::load(levels_location.clone());
+    assert!(store.get("com.test.service").is_none());
+
+    store.set(&level).write().unwrap();
+
+    // Broken table is ignored
+    fs::write(&levels_location, "{ broken").unwrap();
+    let store = LevelStore::load(levels_location);
+    assert!(store.get("com.test.service").is_none());
+}
+
+#[test]
+fn test_temporary_levels() {
+    let levels_dir = TempDir::new("krossbar_levels_dir").expect("Failed to create tempdir");
+    let levels_location = levels_dir.path().join("log_levels.json");
+
+    let mut store = LevelStore::load(levels_location.clone());
+
+    let stored_level = SetLogLevel::new(
+        "com.test.service",
+        LogLevel {
+            level: LevelFilter::Info,
+            directives: None,
+        },
+    );
+    store.set(&stored_level).write().unwrap();
+
+    let mut temporary_level = stored_level.clone();
+    temporary_level.level = LevelFilter::Trace;
+
+    let (first_id, save) = store.set_temporary(&temporary_level, Duration::from_secs(60));
+    save.write().unwrap();
+    let (second_id, save) = store.set_temporary(&temporary_level, Duration::from_secs(60));
+    save.write().unwrap();
+
+    // Outdated timer doesn't revert the latest change
+    assert!(store
+        .take_temporary("com.test.service", Some(first_id))
+        .is_none());
+    assert_eq!(store.temporary("com.test.service").unwrap().id, second_id);
+
+    // Temporary levels are persisted with the original level
+    let mut store = LevelStore::load(levels_location.clone());
+    let temporary = store.temporary("com.test.service").unwrap();
+    assert_eq!(temporary.log_level, temporary_level.log_level());
+    assert_eq!(temporary.revert_message("com.test.service"), stored_level);
+    assert!(temporary.remaining() > Duration::from_secs(50));
+
+    // Service without a stored level is reverted to the client initial level
+    let (_, save) = store.set_temporary(
+        &SetLogLevel::new("com.test.other", temporary_level.log_level()),
+        Duration::from_secs(60),
+    );
+    save.write().unwrap();
+
+    let (other_temporary, save) = store.take_temporary("com.test.other", None).unwrap();
+    save.write().unwrap();
+    assert_eq!(
+        other_temporary.revert_message("com.test.other"),
+        SetLogLevel::reset("com.test.other")
+    );
+
+    let (_, save) = store
+        .take_temporary("com.test.service", Some(temporary.id))
+        .unwrap();
+    save.write().unwrap();
+    assert!(store.take_temporary("com.test.service", None).is_none());
+
+    // Temporary levels file is removed with the last change
+    assert!(!levels_dir.path().join("log_levels.json.temporary").exists());
+
+    let store = LevelStore::load(levels_location);
+    assert!(store.temporary("com.test.service").is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_stored_level_applied_on_register() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+    let levels_location = log_dir.path().join("log_levels.json");
+
+    fs::write(
+        &levels_location,
+        r#"{ "com.test.debug": { "level": "DEBUG", "directives": "net=trace" } }"#,
+    )
+    .unwrap();
+
+    // Temporary changes made before the logger restart
+    let temporary_level = |revert_at: DateTime<Local>| TemporaryLevel {
+        log_level: LogLevel {
+            level: LevelFilter::Trace,
+            directives: None,
+        },
+        original: Some(LogLevel {
+            level: LevelFilter::Info,
+            directives: None,
+        }),
+        revert_at,
+        id: 0,
+    };
+
+    fs::write(
+        log_dir.path().join("log_levels.json.temporary"),
+        serde_json::to_string(&BTreeMap::from([
+            (
+                "com.test.temporary",
+                temporary_level(Local::now() + chrono::Duration::minutes(10)),
+            ),
+            (
+                "com.test.expired",
+                temporary_level(Local::now() - chrono::Duration::minutes(10)),
+            ),
+        ]))
+        .unwrap(),
+    )
+    .unwrap();
+
+    let args = Args {
+        levels_location: levels_location.to_string_lossy().into(),
+        services: vec![
+            ServiceSettings {
+                service_name: "com.test.debug".into(),
+                log_level: Some(LogLevel {
+                    level: LevelFilter::Error,
+                    directives: None,
+                }),
+                log_location: None,
+                ..Default::default()
+            },
+            ServiceSettings {
+                service_name: "com.test.configured".into(),
+                log_level: Some(LogLevel {
+                    level: LevelFilter::Warn,
+                    directives: None,
+                }),
+                log_location: None,
+                ..Default::default()
+            },
+        ],
+        ..test_args(&log_dir.path().join("krossbar.log"))
+    };
+
+    start_logger(args, &socket_path).await;
+
+    assert_eq!(
+        connect_client(&socket_path, "com.test.debug").await,
+        Some(SetLogLevel {
+            service_name: "com.test.debug".into(),
+            level: LevelFilter::Debug,
+            directives: Some("net=trace".into()),
+            revert_after: None,
+            reset: false,
+        })
+    );
+
+    // Config file default level, if there's no stored level
+    assert_eq!(
+        connect_client(&socket_path, "com.test.configured").await,
+        Some(SetLogLevel::new(
+            "com.test.configured",
+            LogLevel {
+                level: LevelFilter::Warn,
+                directives: None,
+            }
+        ))
+    );
+
+    assert!(connect_client(&socket_path, "com.test.other")
+        .await
+        .is_none());
+
+    // Active temporary change is applied again
+    assert_eq!(
+        connect_client(&socket_path, "com.test.temporary")
+            .await
+            .map(|message| message.level),
+        Some(LevelFilter::Trace)
+    );
+
+    // Temporary change, which expired while the logger was down, is reverted
+    assert_eq!(
+        connect_client(&socket_path, "com.test.expired")
+            .await
+            .map(|message| message.level),
+        Some(LevelFilter::Info)
+    );
+}
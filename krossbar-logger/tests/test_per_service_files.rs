@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use tempdir::TempDir;
+
+use krossbar_logger_lib::args::Args;
+
+mod common;
+use common::{connect_client, log, read_dir, start_logger, test_args};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_per_service_files() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+
+    let args = Args {
+        num_bytes_rotate: 500,
+        keep_num_files: 1,
+        per_service_files: true,
+        ..test_args(&log_dir.path().join("krossbar.messages"))
+    };
+
+    start_logger(args, &socket_path).await;
+
+    let chatty = connect_client(&socket_path, "com.test.chatty").await;
+    let quiet = connect_client(&socket_path, "com.test.quiet").await;
+
+    log(&quiet, "Quiet message").await;
+    for i in 0..20 {
+        log(&chatty, &format!("Chatty message {i}")).await;
+    }
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let files = read_dir(&log_dir);
+
+    // Quiet service history is kept untouched
+    let quiet_log =
+        std::fs::read_to_string(log_dir.path().join("com.test.quiet.messages")).unwrap();
+    assert!(quiet_log.contains("Quiet message"));
+    assert!(!quiet_log.contains("Chatty message"));
+    assert!(!files.iter().any(|file| file.starts_with("com.test.quiet_")));
+
+    // Chatty service log is rotated separately
+    let chatty_log =
+        std::fs::read_to_string(log_dir.path().join("com.test.chatty.messages")).unwrap();
+    assert!(chatty_log.contains("Chatty message 19"));
+    assert!(!chatty_log.contains("Quiet message"));
+    assert_eq!(
+        files
+            .iter()
+            .filter(|file| file.starts_with("com.test.chatty_"))
+            .count(),
+        1
+    );
+
+    // Combined log is still written
+    assert!(files.contains(&"krossbar.messages".to_owned()));
+}
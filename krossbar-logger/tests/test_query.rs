@@ -0,0 +1,352 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+use chrono::{DateTime, Local, TimeZone};
+use flate2::{write::GzEncoder, Compression};
+use log::{Level, LevelFilter};
+use tempdir::TempDir;
+
+use krossbar_log_common::{
+    line_template::LineTemplate,
+    log_message::{LogMessage, LogRecord},
+    logger_interface::{LogQuery, QueryPage},
+    naming::LogNaming,
+};
+use krossbar_logger_lib::{
+    args::{Args, LogFormat},
+    query::LogSearch,
+};
+
+const BASE_TIMESTAMP_MS: i64 = 1_700_000_000_000;
+
+fn timestamp(offset_secs: i64) -> DateTime<Local> {
+    Local
+        .timestamp_millis_opt(BASE_TIMESTAMP_MS + offset_secs * 1000 + 123)
+        .unwrap()
+}
+
+fn record(
+    offset_secs: i64,
+    service_name: &str,
+    level: Level,
+    target: &str,
+    text: &str,
+) -> LogRecord {
+    let mut message = LogMessage::new(level, target.into(), text.into());
+    message.timestamp = timestamp(offset_secs);
+
+    LogRecord {
+        pid: 42,
+        service_name: service_name.into(),
+        message,
+    }
+}
+
+fn format_lines(records: &[LogRecord]) -> String {
+    let template = LineTemplate::default();
+
+    records
+        .iter()
+        .map(|record| template.format(&record.service_name, record.pid, &record.message) + "\n")
+        .collect()
+}
+
+fn rotated_location(log_location: &Path, offset_secs: i64) -> std::path::PathBuf {
+    log_location
+        .with_file_name(LogNaming::new(log_location).rotated_name(&timestamp(offset_secs), 0))
+}
+
+fn query(level: LevelFilter, page_size: usize) -> LogQuery {
+    LogQuery {
+        from: None,
+        to: None,
+        service_name: None,
+        level,
+        target_prefix: None,
+        pattern: None,
+        regex: false,
+        page_size,
+        cursor: None,
+    }
+}
+
+fn messages(page: &QueryPage) -> Vec<&str> {
+    page.records
+        .iter()
+        .map(|record| record.message.message.as_str())
+        .collect()
+}
+
+/// Read all pages of the query
+fn query_all(search: &LogSearch, mut query: LogQuery) -> Vec<String> {
+    let mut result = vec![];
+
+    loop {
+        let page = search.query(&query).unwrap();
+        assert!(page.records.len() <= query.page_size);
+
+        result.extend(messages(&page).into_iter().map(String::from));
+
+        match page.next {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => return result,
+        }
+    }
+}
+
+/// Gzipped rotated log, plain rotated log, and the live log
+fn make_logs(log_location: &Path) -> Vec<LogRecord> {
+    let records = vec![
+        record(
+            0,
+            "com.test.first",
+            Level::Info,
+            "first::net",
+            "First connected",
+        ),
+        record(
+            1,
+            "com.test.second",
+            Level::Debug,
+            "second",
+            "Second started",
+        ),
+        record(
+            2,
+            "com.test.first",
+            Level::Error,
+            "first::db",
+            "First failed #1",
+        ),
+        record(
+            60,
+            "com.test.second",
+            Level::Warn,
+            "second::net",
+            "Second warning",
+        ),
+        record(
+            61,
+            "com.test.first",
+            Level::Trace,
+            "first::net",
+            "First trace",
+        ),
+        record(
+            120,
+            "com.test.first",
+            Level::Error,
+            "first::db",
+            "First failed #2",
+        ),
+        record(
+            121,
+            "com.test.second",
+            Level::Info,
+            "second",
+            "Second stopped",
+        ),
+    ];
+
+    let mut encoder = GzEncoder::new(
+        File::create(format!(
+            "{}.gz",
+            rotated_location(log_location, 10).display()
+        ))
+        .unwrap(),
+        Compression::default(),
+    );
+    encoder
+        .write_all(format_lines(&records[..3]).as_bytes())
+        .unwrap();
+    encoder.finish().unwrap();
+
+    fs::write(
+        rotated_location(log_location, 70),
+        format_lines(&records[3..5]),
+    )
+    .unwrap();
+
+    fs::write(
+        log_location,
+        format_lines(&records[5..]) + "Not a log line\n",
+    )
+    .unwrap();
+
+    records
+}
+
+fn make_search(log_dir: &TempDir) -> (LogSearch, std::path::PathBuf) {
+    let log_location = log_dir.path().join("krossbar.log");
+
+    let args = Args {
+        log_location: log_location.to_string_lossy().into(),
+        ..Default::default()
+    };
+
+    (LogSearch::new(&args), log_location)
+}
+
+#[test]
+fn test_query_pages() {
+    let log_dir = TempDir::new("krossbar_query_dir").expect("Failed to create tempdir");
+    let (search, log_location) = make_search(&log_dir);
+    let records = make_logs(&log_location);
+
+    // Records are restored from the text lines
+    let page = search.query(&query(LevelFilter::Trace, 100)).unwrap();
+    assert_eq!(page.records, records);
+    assert!(page.next.is_none());
+
+    let page = search.query(&query(LevelFilter::Trace, 3)).unwrap();
+    assert_eq!(
+        messages(&page),
+        vec!["First connected", "Second started", "First failed #1"]
+    );
+    assert!(page.next.is_some());
+
+    assert_eq!(
+        query_all(&search, query(LevelFilter::Trace, 2)),
+        records
+            .iter()
+            .map(|record| record.message.message.clone())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_query_filters() {
+    let log_dir = TempDir::new("krossbar_query_dir").expect("Failed to create tempdir");
+    let (search, log_location) = make_search(&log_dir);
+    make_logs(&log_location);
+
+    assert_eq!(
+        query_all(&search, query(LevelFilter::Warn, 1)),
+        vec!["First failed #1", "Second warning", "First failed #2"]
+    );
+
+    let mut service_query = query(LevelFilter::Trace, 10);
+    service_query.service_name = Some("com.test.second".into());
+    assert_eq!(
+        query_all(&search, service_query),
+        vec!["Second started", "Second warning", "Second stopped"]
+    );
+
+    let mut target_query = query(LevelFilter::Trace, 10);
+    target_query.target_prefix = Some("first::net".into());
+    assert_eq!(
+        query_all(&search, target_query),
+        vec!["First connected", "First trace"]
+    );
+
+    let mut time_query = query(LevelFilter::Trace, 10);
+    time_query.from = Some(timestamp(2));
+    time_query.to = Some(timestamp(120));
+    assert_eq!(
+        query_all(&search, time_query),
+        vec![
+            "First failed #1",
+            "Second warning",
+            "First trace",
+            "First failed #2"
+        ]
+    );
+
+    let mut substring_query = query(LevelFilter::Trace, 10);
+    substring_query.pattern = Some("failed".into());
+    assert_eq!(
+        query_all(&search, substring_query),
+        vec!["First failed #1", "First failed #2"]
+    );
+
+    let mut regex_query = query(LevelFilter::Trace, 10);
+    regex_query.pattern = Some("^Second (started|stopped)$".into());
+    regex_query.regex = true;
+    assert_eq!(
+        query_all(&search, regex_query),
+        vec!["Second started", "Second stopped"]
+    );
+
+    let mut invalid_query = query(LevelFilter::Trace, 10);
+    invalid_query.pattern = Some("(unclosed".into());
+    invalid_query.regex = true;
+    assert!(search.query(&invalid_query).is_err());
+}
+
+#[test]
+fn test_query_cursor_after_rotation() {
+    let log_dir = TempDir::new("krossbar_query_dir").expect("Failed to create tempdir");
+    let (search, log_location) = make_search(&log_dir);
+    make_logs(&log_location);
+
+    let mut query = query(LevelFilter::Trace, 6);
+    let page = search.query(&query).unwrap();
+    assert_eq!(messages(&page).last(), Some(&"First failed #2"));
+    query.cursor = page.next;
+
+    // Rotate the live log after the page is received
+    fs::rename(
+        &log_location,
+        log_location.with_file_name(LogNaming::new(&log_location).rotated_name(&Local::now(), 0)),
+    )
+    .unwrap();
+
+    let mut new_record = record(
+        180,
+        "com.test.first",
+        Level::Info,
+        "first",
+        "First restarted",
+    );
+    new_record.message.timestamp = Local::now();
+    fs::write(&log_location, format_lines(&[new_record])).unwrap();
+
+    let page = search.query(&query).unwrap();
+    assert_eq!(messages(&page), vec!["Second stopped", "First restarted"]);
+    assert!(page.next.is_none());
+}
+
+#[test]
+fn test_query_json() {
+    let log_dir = TempDir::new("krossbar_query_dir").expect("Failed to create tempdir");
+    let log_location = log_dir.path().join("krossbar.log");
+
+    let args = Args {
+        log_location: log_location.to_string_lossy().into(),
+        format: LogFormat::Json,
+        ..Default::default()
+    };
+    let search = LogSearch::new(&args);
+
+    let records = vec![
+        record(0, "com.test.first", Level::Info, "first", "First connected"),
+        record(
+            1,
+            "com.test.second",
+            Level::Error,
+            "second",
+            "Second failed",
+        ),
+    ];
+
+    let lines: String = records
+        .iter()
+        .map(|record| {
+            let mut line = serde_json::to_value(&record.message).unwrap();
+            line["pid"] = record.pid.into();
+            line["service_name"] = record.service_name.clone().into();
+
+            line.to_string() + "\n"
+        })
+        .collect();
+    fs::write(&log_location, lines).unwrap();
+
+    let page = search.query(&query(LevelFilter::Trace, 10)).unwrap();
+    assert_eq!(page.records, records);
+
+    let page = search.query(&query(LevelFilter::Error, 10)).unwrap();
+    assert_eq!(messages(&page), vec!["Second failed"]);
+}
@@ -0,0 +1,61 @@
+use std::{fs, time::Duration};
+
+use log::Level;
+use tempdir::TempDir;
+
+use krossbar_log_common::{
+    log_message::LogMessage,
+    logger_interface::{LOG_BATCH_METHOD_NAME, LOG_METHOD_NAME},
+};
+use krossbar_logger_lib::args::{Args, QueueOverflow};
+
+mod common;
+use common::{connect_client, start_logger, test_args};
+
+const NUM_MESSAGES: usize = 100;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_queue_overflow_drop() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+    let log_location = log_dir.path().join("krossbar.log");
+
+    let args = Args {
+        queue_size: 1,
+        queue_overflow: QueueOverflow::Drop,
+        ..test_args(&log_location)
+    };
+
+    start_logger(args, &socket_path).await;
+
+    let client = connect_client(&socket_path, "com.test.service").await;
+
+    // A batch is queued at once, so it doesn't fit into the queue
+    let messages: Vec<_> = (0..NUM_MESSAGES)
+        .map(|i| LogMessage::new(Level::Info, "test".into(), format!("Message {i}")))
+        .collect();
+
+    client
+        .send_message(LOG_BATCH_METHOD_NAME, &messages)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    client
+        .send_message(
+            LOG_METHOD_NAME,
+            &LogMessage::new(Level::Info, "test".into(), "After overflow".into()),
+        )
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let log = fs::read_to_string(&log_location).unwrap();
+    let num_written = log.matches("> Message ").count();
+    assert!(num_written < NUM_MESSAGES);
+
+    // Dropped messages are reported before the next message
+    let report = format!("{} messages dropped", NUM_MESSAGES - num_written);
+    let report_position = log.find(&report).expect("Missing dropped messages report");
+    assert!(report_position < log.find("After overflow").unwrap());
+}
@@ -0,0 +1,110 @@
+use std::{fs, time::Duration};
+
+use log::{Level, LevelFilter};
+use tempdir::TempDir;
+
+use krossbar_log_common::{
+    log_message::{LogMessage, LogRecord},
+    logger_interface::LOG_METHOD_NAME,
+};
+use krossbar_logger_lib::{args::Args, ring::RingBuffer};
+
+mod common;
+use common::{connect_client, start_logger, test_args};
+
+fn record(service_name: &str, message: &str) -> LogRecord {
+    LogRecord {
+        pid: 42,
+        service_name: service_name.into(),
+        message: LogMessage::new(Level::Debug, "test".into(), message.into()),
+    }
+}
+
+fn messages(records: Vec<LogRecord>) -> Vec<String> {
+    records
+        .into_iter()
+        .map(|record| record.message.message)
+        .collect()
+}
+
+#[test]
+fn test_ring_buffer_limits() {
+    let mut ring = RingBuffer::new(3, None);
+    for i in 0..5 {
+        ring.push(record("com.test.service", &format!("Message #{i}")));
+    }
+
+    assert_eq!(
+        messages(ring.records(None)),
+        vec!["Message #2", "Message #3", "Message #4"]
+    );
+
+    // Bytes limit drops the oldest records
+    let mut ring = RingBuffer::new(100, Some(2000));
+    for i in 0..50 {
+        ring.push(record("com.test.service", &format!("Message #{i}")));
+    }
+
+    let records = messages(ring.records(None));
+    assert!(!records.is_empty() && records.len() < 50);
+    assert_eq!(records.last().unwrap(), "Message #49");
+
+    let mut ring = RingBuffer::new(10, Some(1));
+    ring.push(record("com.test.service", "Too big"));
+    assert!(ring.records(None).is_empty());
+
+    let mut ring = RingBuffer::new(100, None);
+    ring.push(record("com.test.first", "First #1"));
+    ring.push(record("com.test.second", "Second #1"));
+    ring.push(record("com.test.first", "First #2"));
+
+    assert_eq!(
+        messages(ring.records(Some("com.test.first"))),
+        vec!["First #1", "First #2"]
+    );
+    assert!(ring.records(Some("com.test.other")).is_empty());
+
+    let mut ring = RingBuffer::new(0, None);
+    assert!(!ring.is_enabled());
+    ring.push(record("com.test.service", "Dropped"));
+    assert!(ring.records(None).is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_file_level() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+    let log_location = log_dir.path().join("krossbar.log");
+
+    let args = Args {
+        file_level: LevelFilter::Info,
+        ..test_args(&log_location)
+    };
+
+    start_logger(args, &socket_path).await;
+
+    let client = connect_client(&socket_path, "com.test.service").await;
+
+    for (level, message) in [
+        (Level::Debug, "Debug message"),
+        (Level::Info, "Info message"),
+        (Level::Trace, "Trace message"),
+        (Level::Error, "Error message"),
+    ] {
+        client
+            .send_message(
+                LOG_METHOD_NAME,
+                &LogMessage::new(level, "test".into(), message.into()),
+            )
+            .await
+            .unwrap();
+    }
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let log = fs::read_to_string(&log_location).unwrap();
+    assert!(log.contains("Info message"));
+    assert!(log.contains("Error message"));
+    assert!(!log.contains("Debug message"));
+    assert!(!log.contains("Trace message"));
+}
@@ -0,0 +1,77 @@
+use std::{fs::File, io::Read, time::Duration};
+
+use flate2::read::GzDecoder;
+use futures::{channel::oneshot, SinkExt};
+use tempdir::TempDir;
+
+use krossbar_logger_lib::{
+    args::{Args, Compression},
+    logger::Command,
+};
+
+mod common;
+use common::{connect_client, log, start_logger, test_args};
+
+fn read_gzip(path: &str) -> String {
+    let mut content = String::new();
+    GzDecoder::new(File::open(path).unwrap())
+        .read_to_string(&mut content)
+        .unwrap();
+
+    content
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rotate() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+
+    let args = Args {
+        num_bytes_rotate: u64::MAX,
+        keep_num_files: 10,
+        compress: Compression::Gzip,
+        ..test_args(&log_dir.path().join("krossbar.messages"))
+    };
+
+    let mut commands = start_logger(args, &socket_path).await;
+
+    let client = connect_client(&socket_path, "com.test.rotate").await;
+
+    let mut rotated_files = vec![];
+    for i in 0..2 {
+        log(&client, &format!("Message {i}")).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let (reply, response) = oneshot::channel();
+        commands
+            .send(Command::Rotate {
+                service_name: None,
+                reply,
+            })
+            .await
+            .unwrap();
+
+        // Replies once the rotated file is compressed
+        let rotated_file = response.await.unwrap().unwrap();
+        assert!(rotated_file.ends_with(".messages.gz"));
+        assert!(read_gzip(&rotated_file).contains(&format!("Message {i}")));
+
+        rotated_files.push(rotated_file);
+    }
+
+    // Rotations within the same second get different names
+    assert_ne!(rotated_files[0], rotated_files[1]);
+    assert!(!read_gzip(&rotated_files[1]).contains("Message 0"));
+
+    // Per-service files are disabled
+    let (reply, response) = oneshot::channel();
+    commands
+        .send(Command::Rotate {
+            service_name: Some("com.test.rotate".into()),
+            reply,
+        })
+        .await
+        .unwrap();
+
+    assert!(response.await.unwrap().is_err());
+}
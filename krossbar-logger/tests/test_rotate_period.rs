@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use log::error;
+use tempdir::TempDir;
+
+use krossbar_logger_lib::args::Args;
+
+mod common;
+use common::{start_logger, test_args};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rotate_period() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+
+    let args = Args {
+        num_bytes_rotate: u64::MAX,
+        rotate_period: Some(Duration::from_secs(1)),
+        ..test_args(&log_dir.path().join("krossbar_log.messages"))
+    };
+
+    start_logger(args, &socket_path).await;
+
+    error!("First file message");
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    error!("Second file message");
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let mut rotated_logs: Vec<_> = log_dir
+        .path()
+        .read_dir()
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("krossbar_log_"))
+        .collect();
+    rotated_logs.sort();
+
+    // Single rotation, because empty log files are not rotated
+    assert_eq!(rotated_logs.len(), 1);
+
+    let rotated_content = std::fs::read_to_string(log_dir.path().join(&rotated_logs[0])).unwrap();
+    assert!(rotated_content.contains("First file message"));
+
+    let live_content =
+        std::fs::read_to_string(log_dir.path().join("krossbar_log.messages")).unwrap();
+    assert!(live_content.contains("Second file message"));
+}
@@ -1,14 +1,18 @@
 use std::{
-    fs::OpenOptions,
+    fs::{File, OpenOptions},
     io::{Read, Write},
     path::PathBuf,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
+use flate2::read::GzDecoder;
 use log::LevelFilter;
 use tempdir::TempDir;
 
-use krossbar_logger_lib::{args::Args, rotator::Rotator};
+use krossbar_logger_lib::{
+    args::{Args, Compression},
+    rotator::Rotator,
+};
 
 fn make_args(log_dir: &TempDir) -> Args {
     let log_location: String = log_dir
@@ -25,6 +29,7 @@ fn make_args(log_dir: &TempDir) -> Args {
         num_bytes_rotate: u64::MAX,
         // Keep single rotated file
         keep_num_files: 1,
+        ..Default::default()
     }
 }
 
@@ -106,3 +111,262 @@ async fn test_rotator() {
     assert_eq!(&read_log_content(&log_files[1]), "Log1");
     assert_eq!(&read_log_content(&args.log_location), "Log2");
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rotator_compression() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+
+    let args = make_args(&log_dir);
+
+    write_log_message("Log0", &args.log_location);
+
+    let rotation = Rotator::new(args.keep_num_files, PathBuf::from(&args.log_location))
+        .with_compression(Compression::Gzip, Some(9))
+        .rotate();
+    assert!(rotation.rotated_file.ends_with(".messages"));
+
+    let rotated_path = rotation.compression.unwrap().await.unwrap();
+    assert_eq!(rotated_path, format!("{}.gz", rotation.rotated_file));
+
+    // Uncompressed file is removed after compression
+    assert_eq!(
+        log_dir_files(PathBuf::from(&args.log_location)),
+        vec![rotated_path.clone()]
+    );
+
+    let mut content = String::new();
+    GzDecoder::new(File::open(&rotated_path).unwrap())
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "Log0");
+
+    // Sleep to make different names
+    std::thread::sleep(Duration::from_secs(1));
+
+    write_log_message("Log1", &args.log_location);
+
+    let rotated_path = Rotator::new(args.keep_num_files, PathBuf::from(&args.log_location))
+        .with_compression(Compression::Zstd, None)
+        .rotate()
+        .compression
+        .unwrap()
+        .await
+        .unwrap();
+    assert!(rotated_path.ends_with(".messages.zst"));
+
+    // Keep single rotated file
+    assert_eq!(
+        log_dir_files(PathBuf::from(&args.log_location)),
+        vec![rotated_path.clone()]
+    );
+
+    let content = zstd::decode_all(File::open(&rotated_path).unwrap()).unwrap();
+    assert_eq!(content, "Log1".as_bytes());
+}
+
+fn write_rotated_log(log_dir: &TempDir, name: &str, num_bytes: usize, age: Duration) -> String {
+    let path = log_dir.path().join(name);
+    let file = File::create(&path).unwrap();
+
+    file.set_len(num_bytes as u64).unwrap();
+    file.set_modified(SystemTime::now() - age).unwrap();
+
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_rotator_same_second() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+
+    let args = make_args(&log_dir);
+    let rotator = Rotator::new(10, PathBuf::from(&args.log_location));
+
+    write_log_message("Log0", &args.log_location);
+    let first_file = rotator.rotate().rotated_file;
+
+    write_log_message("Log1", &args.log_location);
+    let second_file = rotator.rotate().rotated_file;
+
+    // Previously rotated file is never overwritten
+    assert_ne!(first_file, second_file);
+    assert_eq!(read_log_content(&first_file), "Log0");
+    assert_eq!(read_log_content(&second_file), "Log1");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rotator_retention_during_compression() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+
+    let args = make_args(&log_dir);
+
+    write_log_message("Log0", &args.log_location);
+
+    // Don't keep any rotated files
+    let rotator = Rotator::new(0, PathBuf::from(&args.log_location))
+        .with_compression(Compression::Gzip, None);
+
+    let rotation = rotator.rotate();
+    // File being compressed is never removed
+    assert!(!rotation.removed_files.contains(&rotation.rotated_file));
+
+    let rotated_path = rotation.compression.unwrap().await.unwrap();
+    assert_eq!(rotated_path, format!("{}.gz", rotation.rotated_file));
+
+    // Compressed file is removed either by the rotation, if the compression was fast enough,
+    // or by the next retention run
+    let mut removed_files = rotation.removed_files;
+    removed_files.extend(rotator.remove_old_logs());
+    assert_eq!(removed_files, vec![rotated_path]);
+
+    assert!(log_dir_files(PathBuf::from(&args.log_location)).is_empty());
+}
+
+/// Pseudo-random data, which compresses slowly
+fn write_incompressible_log(num_bytes: usize, log_path: &str) {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let data: Vec<u8> = (0..num_bytes)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect();
+
+    File::create(log_path).unwrap().write_all(&data).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rotator_retention_waits_for_compression() {
+    // Keep the compression thread busy, so the next rotated log stays in flight
+    let busy_dir = TempDir::new("krossbar_busy_log_dir").expect("Failed to create log tempdir");
+    let busy_args = make_args(&busy_dir);
+    write_incompressible_log(8 * 1024 * 1024, &busy_args.log_location);
+
+    let busy_rotator = Rotator::new(10, PathBuf::from(&busy_args.log_location))
+        .with_compression(Compression::Gzip, Some(9));
+    let busy_rotation = busy_rotator.rotate();
+
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let args = make_args(&log_dir);
+
+    // Keep single rotated file
+    let rotator = Rotator::new(1, PathBuf::from(&args.log_location))
+        .with_compression(Compression::Gzip, None);
+
+    write_log_message("Log0", &args.log_location);
+    let rotation = rotator.rotate();
+    assert!(rotation.removed_files.is_empty());
+
+    let newer = write_rotated_log(
+        &log_dir,
+        "krossbar_log_2099_01_01_00_00_00.messages",
+        100,
+        Duration::ZERO,
+    );
+
+    // The oldest log is still being compressed. Newer logs are kept
+    assert!(rotator.remove_old_logs().is_empty());
+    assert!(log_dir_files(PathBuf::from(&args.log_location)).contains(&newer));
+
+    busy_rotation.compression.unwrap().await.unwrap();
+    let rotated_path = rotation.compression.unwrap().await.unwrap();
+
+    // The next retention run removes the oldest log once it's compressed
+    assert_eq!(rotator.remove_old_logs(), vec![rotated_path]);
+
+    let log_files = log_dir_files(PathBuf::from(&args.log_location));
+    assert_eq!(log_files, vec![newer]);
+}
+
+#[test]
+fn test_rotator_retention() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+
+    let args = make_args(&log_dir);
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    let oldest = write_rotated_log(
+        &log_dir,
+        "krossbar_log_2024_01_01_00_00_00.messages",
+        100,
+        10 * DAY,
+    );
+    let old = write_rotated_log(
+        &log_dir,
+        "krossbar_log_2024_01_05_00_00_00.messages.gz",
+        100,
+        8 * DAY,
+    );
+    let recent = write_rotated_log(
+        &log_dir,
+        "krossbar_log_2024_01_10_00_00_00.messages",
+        100,
+        3 * DAY,
+    );
+    let newest = write_rotated_log(
+        &log_dir,
+        "krossbar_log_2024_01_12_00_00_00.messages",
+        100,
+        DAY,
+    );
+    write_log_message(&"x".repeat(50), &args.log_location);
+
+    // Nothing to remove
+    let rotator = Rotator::new(10, PathBuf::from(&args.log_location));
+    assert!(rotator.remove_old_logs().is_empty());
+
+    // Remove logs older than a week
+    let rotator =
+        Rotator::new(10, PathBuf::from(&args.log_location)).with_retention(Some(7 * DAY), None);
+    assert_eq!(rotator.remove_old_logs(), vec![oldest, old]);
+
+    // Keep total size including live log under the limit
+    let rotator = Rotator::new(10, PathBuf::from(&args.log_location))
+        .with_retention(Some(7 * DAY), Some(200));
+    assert_eq!(rotator.remove_old_logs(), vec![recent]);
+
+    let log_files = log_dir_files(PathBuf::from(&args.log_location));
+    assert_eq!(log_files.len(), 2);
+    assert!(log_files.contains(&newest));
+    assert!(log_files.contains(&args.log_location));
+}
+
+#[test]
+fn test_rotator_foreign_files() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+
+    let args = make_args(&log_dir);
+
+    // Files, which don't belong to the log, and sort before rotated logs
+    let foreign_files = [
+        "core.1234",
+        "another_daemon.log",
+        "krossbar_log_backup.messages",
+        "krossbar_log.messages.old",
+        "krossbar_log_2024_01_01_00_00_00.log",
+    ];
+
+    for file in foreign_files {
+        write_log_message("Foreign", &log_dir.path().join(file).to_string_lossy());
+    }
+
+    for i in 0..2 {
+        write_log_message(&format!("Log{i}"), &args.log_location);
+        Rotator::new(args.keep_num_files, PathBuf::from(&args.log_location)).rotate();
+
+        // Sleep to make different names
+        std::thread::sleep(Duration::from_secs(1));
+    }
+
+    let log_files = log_dir_files(PathBuf::from(&args.log_location));
+
+    // Foreign files and a single rotated log
+    assert_eq!(log_files.len(), foreign_files.len() + 1);
+    for file in foreign_files {
+        assert_eq!(
+            read_log_content(&log_dir.path().join(file).to_string_lossy()),
+            "Foreign"
+        );
+    }
+}
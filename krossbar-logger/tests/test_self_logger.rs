@@ -21,6 +21,7 @@ fn make_args(log_dir: &TempDir) -> Args {
         num_bytes_rotate: u64::MAX,
         // Keep single rotated file
         keep_num_files: 1,
+        ..Default::default()
     }
 }
 
@@ -46,4 +47,7 @@ async fn test_self_logger() {
     assert!(log_content.contains("Test DEBUG"));
     assert!(log_content.contains("Test INFO"));
     assert!(log_content.contains("Test WARN"));
+    // Source location and thread metadata
+    assert!(log_content.contains("@ test_self_logger "));
+    assert!(log_content.contains("test_self_logger.rs:"));
 }
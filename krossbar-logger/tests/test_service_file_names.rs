@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use tempdir::TempDir;
+
+use krossbar_logger_lib::args::Args;
+
+mod common;
+use common::{connect_client, log, read_dir, start_logger, test_args};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_per_service_file_names() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+
+    let args = Args {
+        per_service_files: true,
+        no_combined_log: true,
+        ..test_args(&log_dir.path().join("krossbar.messages"))
+    };
+
+    start_logger(args, &socket_path).await;
+
+    let long_name = "com.test.".to_owned() + &"long".repeat(100);
+
+    let nested = connect_client(&socket_path, "com/test\tnested").await;
+    let long = connect_client(&socket_path, &long_name).await;
+    // Conflicts with the combined log file
+    let combined = connect_client(&socket_path, "krossbar").await;
+    // Looks like a rotated log of the 'com.test.service' service
+    let rotated = connect_client(&socket_path, "com.test.service_2024_01_31_12_30_59").await;
+
+    log(&nested, "Nested message").await;
+    log(&long, "Long message").await;
+    log(&combined, "Combined message").await;
+    log(&rotated, "Rotated message").await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let files = read_dir(&log_dir);
+
+    let nested_log =
+        std::fs::read_to_string(log_dir.path().join("com_test_nested.messages")).unwrap();
+    assert!(nested_log.contains("Nested message"));
+
+    let long_file = files
+        .iter()
+        .find(|file| file.starts_with("com.test.long"))
+        .unwrap();
+    assert!(long_file.len() < 255);
+    let long_log = std::fs::read_to_string(log_dir.path().join(long_file)).unwrap();
+    assert!(long_log.contains("Long message"));
+
+    // Conflicting services are written into the combined log file even if it's disabled
+    let combined_log = std::fs::read_to_string(log_dir.path().join("krossbar.messages")).unwrap();
+    assert!(combined_log.contains("Combined message"));
+    assert!(combined_log.contains("Rotated message"));
+    assert!(!combined_log.contains("Nested message"));
+    assert!(!files
+        .iter()
+        .any(|file| file.starts_with("com.test.service")));
+}
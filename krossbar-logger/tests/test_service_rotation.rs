@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use tempdir::TempDir;
+
+use krossbar_logger_lib::args::{Args, ServiceSettings};
+
+mod common;
+use common::{connect_client, log, read_dir, start_logger, test_args};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_service_rotation_settings() {
+    let log_dir = TempDir::new("krossbar_log_dir").expect("Failed to create log tempdir");
+    let socket_path = log_dir.path().join("logger.sock");
+
+    let args = Args {
+        num_bytes_rotate: 1_000_000,
+        keep_num_files: 10,
+        per_service_files: true,
+        no_combined_log: true,
+        services: vec![ServiceSettings {
+            service_name: "com.test.small".into(),
+            num_bytes_rotate: Some(300),
+            keep_num_files: Some(2),
+            ..Default::default()
+        }],
+        ..test_args(&log_dir.path().join("krossbar.messages"))
+    };
+
+    start_logger(args, &socket_path).await;
+
+    let small = connect_client(&socket_path, "com.test.small").await;
+    let large = connect_client(&socket_path, "com.test.large").await;
+
+    for i in 0..20 {
+        log(&small, &format!("Small message {i}")).await;
+        log(&large, &format!("Large message {i}")).await;
+    }
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let files = read_dir(&log_dir);
+    let rotated = |prefix: &str| -> Vec<String> {
+        files
+            .iter()
+            .filter(|file| file.starts_with(prefix))
+            .cloned()
+            .collect()
+    };
+
+    // Service settings override the global size limit and the number of rotated files
+    let small_rotated = rotated("com.test.small_");
+    assert_eq!(small_rotated.len(), 2);
+    for file in small_rotated {
+        assert!(std::fs::metadata(log_dir.path().join(file)).unwrap().len() <= 400);
+    }
+
+    // Other services use the global settings
+    assert!(rotated("com.test.large_").is_empty());
+    let large_log =
+        std::fs::read_to_string(log_dir.path().join("com.test.large.messages")).unwrap();
+    assert!(large_log.contains("Large message 0"));
+    assert!(large_log.contains("Large message 19"));
+}
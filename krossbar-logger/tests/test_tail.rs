@@ -0,0 +1,87 @@
+use std::{thread, time::Duration};
+
+use log::{Level, LevelFilter};
+
+use krossbar_log_common::{log_message::LogMessage, logger_interface::TailFilter};
+use krossbar_logger_lib::tail::TailSubscriptions;
+
+/// Subscription lifetime. A subscription renewed after [RENEW_AFTER] outlives
+/// the one, which is not renewed. Margins are wide to not depend on the test machine load
+const TIMEOUT: Duration = Duration::from_secs(3);
+const RENEW_AFTER: Duration = Duration::from_secs(2);
+
+#[test]
+fn test_tail_subscriptions() {
+    let mut subscriptions = TailSubscriptions::new(TIMEOUT);
+
+    let debug = LogMessage::new(Level::Debug, "my_crate".into(), "Debug".into());
+    let error = LogMessage::new(Level::Error, "my_crate".into(), "Error".into());
+
+    // No subscribers
+    assert!(subscriptions
+        .subscribers("com.test.service", &error)
+        .is_empty());
+
+    subscriptions.subscribe(
+        "com.test.tail",
+        TailFilter {
+            service_name: Some("com.test.service".into()),
+            level: LevelFilter::Warn,
+            target_prefix: None,
+        },
+    );
+    assert_eq!(
+        subscriptions.subscribers("com.test.service", &error),
+        vec!["com.test.tail".to_owned()]
+    );
+    assert!(subscriptions
+        .subscribers("com.test.service", &debug)
+        .is_empty());
+    assert!(subscriptions
+        .subscribers("com.test.other", &error)
+        .is_empty());
+
+    // Messages are sent only to the subscribers whose filters match
+    subscriptions.subscribe(
+        "com.test.other_tail",
+        TailFilter {
+            service_name: None,
+            level: LevelFilter::Trace,
+            target_prefix: None,
+        },
+    );
+    assert_eq!(
+        subscriptions.subscribers("com.test.other", &debug),
+        vec!["com.test.other_tail".to_owned()]
+    );
+
+    let mut subscribers = subscriptions.subscribers("com.test.service", &error);
+    subscribers.sort();
+    assert_eq!(subscribers, vec!["com.test.other_tail", "com.test.tail"]);
+
+    thread::sleep(RENEW_AFTER);
+
+    // Renewed subscription outlives the other one
+    subscriptions.subscribe(
+        "com.test.tail",
+        TailFilter {
+            service_name: Some("com.test.service".into()),
+            level: LevelFilter::Warn,
+            target_prefix: None,
+        },
+    );
+
+    thread::sleep(RENEW_AFTER);
+    assert!(subscriptions
+        .subscribers("com.test.other", &debug)
+        .is_empty());
+    assert_eq!(
+        subscriptions.subscribers("com.test.service", &error),
+        vec!["com.test.tail".to_owned()]
+    );
+
+    thread::sleep(RENEW_AFTER);
+    assert!(subscriptions
+        .subscribers("com.test.service", &error)
+        .is_empty());
+}
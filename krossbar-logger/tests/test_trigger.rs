@@ -0,0 +1,126 @@
+use log::{Level, LevelFilter};
+
+use krossbar_log_common::log_message::LogMessage;
+use krossbar_logger_lib::{
+    trigger::{TriggerFilter, TriggerPolicy},
+    LogEvent,
+};
+
+fn event(service_name: &str, level: Level, message: &str) -> LogEvent {
+    LogEvent {
+        pid: 42,
+        service_name: service_name.into(),
+        message: LogMessage::new(level, "test".into(), message.into()),
+        stats: None,
+    }
+}
+
+fn messages(events: Vec<LogEvent>) -> Vec<String> {
+    events
+        .into_iter()
+        .map(|event| event.message.message)
+        .collect()
+}
+
+#[test]
+fn test_trigger_policy_parse() {
+    assert_eq!(
+        "com.test.service".parse::<TriggerPolicy>().unwrap(),
+        TriggerPolicy {
+            service_name: "com.test.service".into(),
+            file_level: LevelFilter::Info,
+            trigger_level: Level::Error,
+            context: 100,
+        }
+    );
+
+    assert_eq!(
+        "com.test.service:file_level=warn, trigger=warn,context=5"
+            .parse::<TriggerPolicy>()
+            .unwrap(),
+        TriggerPolicy {
+            service_name: "com.test.service".into(),
+            file_level: LevelFilter::Warn,
+            trigger_level: Level::Warn,
+            context: 5,
+        }
+    );
+
+    assert!(":context=5".parse::<TriggerPolicy>().is_err());
+    assert!("com.test.service:context".parse::<TriggerPolicy>().is_err());
+    assert!("com.test.service:context=many"
+        .parse::<TriggerPolicy>()
+        .is_err());
+    assert!("com.test.service:trigger=loud"
+        .parse::<TriggerPolicy>()
+        .is_err());
+    assert!("com.test.service:unknown=1"
+        .parse::<TriggerPolicy>()
+        .is_err());
+}
+
+#[test]
+fn test_trigger_flush() {
+    let mut filter = TriggerFilter::new(
+        LevelFilter::Trace,
+        &["com.test.service:context=2".parse().unwrap()],
+    );
+
+    // Services without a policy use the default file level
+    assert_eq!(
+        messages(filter.filter(event("com.test.other", Level::Trace, "Other trace"))),
+        vec!["Other trace"]
+    );
+
+    assert!(filter
+        .filter(event("com.test.service", Level::Debug, "Debug #1"))
+        .is_empty());
+
+    assert_eq!(
+        messages(filter.filter(event("com.test.service", Level::Info, "Info"))),
+        vec!["Info"]
+    );
+
+    assert!(filter
+        .filter(event("com.test.service", Level::Trace, "Trace #2"))
+        .is_empty());
+    assert!(filter
+        .filter(event("com.test.service", Level::Debug, "Debug #3"))
+        .is_empty());
+
+    // Error flushes the last context messages
+    assert_eq!(
+        messages(filter.filter(event("com.test.service", Level::Error, "Error #1"))),
+        vec!["Trace #2", "Debug #3", "Error #1"]
+    );
+
+    // Buffer is empty after the flush
+    assert_eq!(
+        messages(filter.filter(event("com.test.service", Level::Error, "Error #2"))),
+        vec!["Error #2"]
+    );
+
+    // Other services messages are not flushed
+    let mut filter = TriggerFilter::new(
+        LevelFilter::Info,
+        &[
+            "com.test.first".parse().unwrap(),
+            "com.test.second".parse().unwrap(),
+        ],
+    );
+
+    assert!(filter
+        .filter(event("com.test.first", Level::Debug, "First debug"))
+        .is_empty());
+    assert!(filter
+        .filter(event("com.test.second", Level::Debug, "Second debug"))
+        .is_empty());
+    assert!(filter
+        .filter(event("com.test.other", Level::Debug, "Other debug"))
+        .is_empty());
+
+    assert_eq!(
+        messages(filter.filter(event("com.test.second", Level::Error, "Second error"))),
+        vec!["Second debug", "Second error"]
+    );
+}